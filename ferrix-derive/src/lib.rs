@@ -0,0 +1,196 @@
+//! `#[derive(ByteObject)]` を提供するマクロクレート。
+//!
+//! 構造体のフィールドを宣言順にビット列として読み書きする
+//! `from_stream` / `write_to` を生成する。
+//!
+//! * `#[bits(n)]` を付けたフィールドは `BitField` として `n` ビットで読み書きする。
+//!   `n` が型のビット幅を超える場合はコンパイルエラーになる。
+//! * 属性のない整数型 (`u8` / `u16` / `u32` / `u64`) のフィールドは型のビット幅で読み書きする。
+//! * それ以外の属性のないフィールドはその型の `ByteObject` 実装に委ねる。
+//! * 構造体に `#[byte_object(validate = path)]` を付けると、
+//!   読み取り後に `path(&value)` を呼び出して値を検証する。
+//!
+//! ```ignore
+//! #[derive(ByteObject)]
+//! pub struct UdpHeader {
+//!     pub source_port: u16,
+//!     pub destination_port: u16,
+//!     pub length: u16,
+//!     pub checksum: u16,
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{Data, DeriveInput, Fields, LitInt, Path, Type, parse_macro_input};
+
+#[proc_macro_derive(ByteObject, attributes(bits, byte_object))]
+pub fn derive_byte_object(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// フィールドの読み書き方法
+enum FieldKind {
+    /// `#[bits(n)]` 指定された固定ビット幅の整数
+    Bits(LitInt),
+    /// 属性のない整数型。型のビット幅をそのまま使う
+    Int,
+    /// 型自身の `ByteObject` 実装を使う
+    Nested,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "ByteObject can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "ByteObject can only be derived for structs",
+            ));
+        }
+    };
+
+    let validate = parse_validate(input)?;
+
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let mut idents = Vec::new();
+    let mut width_checks = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        match parse_field_kind(field)? {
+            FieldKind::Bits(width) => {
+                width_checks.push(quote_spanned! {width.span()=>
+                    ::core::assert!(
+                        #width <= <#ty as ::ferrix::types::bit_field::BitField>::WIDTH,
+                        "bit width exceeds the field type",
+                    )
+                });
+                reads.push(quote! {
+                    let #ident = <#ty as ::ferrix::types::bit_field::BitField>::read_bits(src, #width)?;
+                });
+                writes.push(quote! {
+                    ::ferrix::types::bit_field::BitField::write_bits(&self.#ident, writer, #width);
+                });
+            }
+            FieldKind::Int => {
+                reads.push(quote! {
+                    let #ident = <#ty as ::ferrix::types::bit_field::BitField>::read_bits(
+                        src,
+                        <#ty as ::ferrix::types::bit_field::BitField>::WIDTH,
+                    )?;
+                });
+                writes.push(quote! {
+                    ::ferrix::types::bit_field::BitField::write_bits(
+                        &self.#ident,
+                        writer,
+                        <#ty as ::ferrix::types::bit_field::BitField>::WIDTH,
+                    );
+                });
+            }
+            FieldKind::Nested => {
+                reads.push(quote! {
+                    let #ident = <#ty as ::ferrix::types::byte_object::ByteObject>::from_stream(src)?;
+                });
+                writes.push(quote! {
+                    ::ferrix::types::byte_object::ByteObject::write_to(&self.#ident, writer);
+                });
+            }
+        }
+        idents.push(ident);
+    }
+
+    let validate = validate.map(|path| quote! { #path(&value)?; });
+
+    // ジェネリクスがなければ、使われなくても評価される定数項目で幅を確かめる。
+    // ジェネリクスがある場合は型引数を参照できないため、読み取り時のインライン定数で確かめる
+    let (width_items, width_blocks) = if input.generics.params.is_empty() {
+        let items = width_checks.iter().map(|check| quote! { const _: () = #check; });
+        (quote! { #(#items)* }, quote! {})
+    } else {
+        let blocks = width_checks.iter().map(|check| quote! { const { #check }; });
+        (quote! {}, quote! { #(#blocks)* })
+    };
+
+    Ok(quote! {
+        #width_items
+
+        impl #impl_generics ::ferrix::types::byte_object::ByteObject for #name #ty_generics #where_clause {
+            fn from_stream(
+                src: &mut ::ferrix::types::bit_stream::BitStream,
+            ) -> ::core::result::Result<Self, ::ferrix::types::parse_error::ParseError> {
+                #width_blocks
+                #(#reads)*
+                let value = #name { #(#idents),* };
+                #validate
+                ::core::result::Result::Ok(value)
+            }
+
+            fn write_to(&self, writer: &mut ::ferrix::types::bit_stream::BitWriter) {
+                #(#writes)*
+            }
+        }
+    })
+}
+
+fn parse_field_kind(field: &syn::Field) -> syn::Result<FieldKind> {
+    let mut kind = if is_int_type(&field.ty) {
+        FieldKind::Int
+    } else {
+        FieldKind::Nested
+    };
+    for attr in &field.attrs {
+        if attr.path().is_ident("bits") {
+            let width: LitInt = attr.parse_args()?;
+            if width.base10_parse::<usize>()? == 0 {
+                return Err(syn::Error::new_spanned(width, "bit width must be positive"));
+            }
+            kind = FieldKind::Bits(width);
+        }
+    }
+    Ok(kind)
+}
+
+fn is_int_type(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => ["u8", "u16", "u32", "u64"]
+            .iter()
+            .any(|name| path.path.is_ident(name)),
+        _ => false,
+    }
+}
+
+fn parse_validate(input: &DeriveInput) -> syn::Result<Option<Path>> {
+    let mut validate = None;
+    for attr in &input.attrs {
+        if !attr.path().is_ident("byte_object") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("validate") {
+                validate = Some(meta.value()?.parse::<Path>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported byte_object attribute"))
+            }
+        })?;
+    }
+    Ok(validate)
+}
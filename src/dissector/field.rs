@@ -0,0 +1,139 @@
+use crate::types::bit_stream::BitStream;
+use crate::types::field_layout::FieldSpec;
+use crate::types::parse_error::ParseError;
+
+/// 分解されたフィールド（またはプロトコル層）を表す木のノード
+#[derive(Debug, Clone)]
+pub struct DissectedField {
+    /// フィールド名
+    pub name: String,
+    /// フレーム先頭からのビットオフセット
+    pub bit_offset: usize,
+    /// ビット長
+    pub bit_len: usize,
+    /// 64ビット以下の整数フィールドの場合、その生の値
+    pub raw: Option<u64>,
+    /// 人間が読める形式の値
+    pub value: String,
+    pub children: Vec<DissectedField>,
+}
+
+impl DissectedField {
+    pub fn new(name: &str, bit_offset: usize, bit_len: usize, value: String) -> Self {
+        DissectedField {
+            name: name.to_string(),
+            bit_offset,
+            bit_len,
+            raw: None,
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    /// 名前でフィールドを検索する（直下の子のみ）
+    pub fn child(&self, name: &str) -> Option<&DissectedField> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// `BitStream` から読み取りながらフィールドの木を組み立てる
+///
+/// オフセットはストリームの `origin` を足したフレーム先頭からの位置で記録する。
+pub struct LayerBuilder<'a> {
+    stream: &'a mut BitStream,
+    layer: DissectedField,
+}
+
+impl<'a> LayerBuilder<'a> {
+    pub fn new(name: &str, stream: &'a mut BitStream) -> Self {
+        let offset = stream.origin() + stream.position();
+        LayerBuilder {
+            stream,
+            layer: DissectedField::new(name, offset, 0, String::new()),
+        }
+    }
+
+    /// 現在位置のフレーム先頭からのビットオフセット
+    pub fn offset(&self) -> usize {
+        self.stream.origin() + self.stream.position()
+    }
+
+    /// `width` ビットの整数フィールドを読み、10進数で表示する
+    pub fn field(&mut self, name: &str, width: usize) -> Result<u64, ParseError> {
+        self.field_with(name, width, |raw| raw.to_string())
+    }
+
+    /// `width` ビットの整数フィールドを読み、`format` で表示用の文字列に変換する
+    pub fn field_with(
+        &mut self,
+        name: &str,
+        width: usize,
+        format: impl Fn(u64) -> String,
+    ) -> Result<u64, ParseError> {
+        let offset = self.offset();
+        let raw = self.stream.try_pop(width)?.to_u64();
+        let mut field = DissectedField::new(name, offset, width, format(raw));
+        field.raw = Some(raw);
+        self.layer.children.push(field);
+        Ok(raw)
+    }
+
+    /// 型付きのパーサーと共有する配置の順にフィールドを読む
+    ///
+    /// `format` で表示用の文字列に変換し、読むたびに `annotate` で内訳を加えられるようにする。
+    pub fn fields<const N: usize>(
+        &mut self,
+        layout: &[FieldSpec; N],
+        format: impl Fn(&FieldSpec, u64) -> String,
+        mut annotate: impl FnMut(&mut Self, &FieldSpec, u64),
+    ) -> Result<[u64; N], ParseError> {
+        let mut values = [0; N];
+        for (value, spec) in values.iter_mut().zip(layout) {
+            *value = self.field_with(spec.name, spec.width, |raw| format(spec, raw))?;
+            annotate(self, spec, *value);
+        }
+        Ok(values)
+    }
+
+    /// 直前に読んだフィールドに子ノード（フラグの内訳など）を追加する
+    pub fn annotate_last(&mut self, child: DissectedField) {
+        if let Some(last) = self.layer.children.last_mut() {
+            last.children.push(child);
+        }
+    }
+
+    /// `len` バイトをまとめて読み、バイト数を表示する
+    pub fn bytes(&mut self, name: &str, len: usize) -> Result<Vec<u8>, ParseError> {
+        self.bytes_with(name, len, |bytes| format!("{} bytes", bytes.len()))
+    }
+
+    /// `len` バイトをまとめて読み、`format` で表示用の文字列に変換する（IPv6アドレスなど）
+    pub fn bytes_with(
+        &mut self,
+        name: &str,
+        len: usize,
+        format: impl Fn(&[u8]) -> String,
+    ) -> Result<Vec<u8>, ParseError> {
+        let offset = self.offset();
+        let bytes = self.stream.try_pop(len * 8)?.to_u8s();
+        self.layer
+            .children
+            .push(DissectedField::new(name, offset, len * 8, format(&bytes)));
+        Ok(bytes)
+    }
+
+    /// 任意のノードを子として追加する
+    pub fn push(&mut self, field: DissectedField) {
+        self.layer.children.push(field);
+    }
+
+    /// 層の要約を設定する
+    pub fn set_summary(&mut self, summary: String) {
+        self.layer.value = summary;
+    }
+
+    pub fn finish(mut self) -> DissectedField {
+        self.layer.bit_len = self.offset() - self.layer.bit_offset;
+        self.layer
+    }
+}
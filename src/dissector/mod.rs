@@ -0,0 +1,906 @@
+//! パケットを層ごとに分解して注釈付きの木として表示するモジュール。
+//!
+//! `dissect` はフレームを IPv4/IPv6 → TCP → HTTP（または IPv4 → ICMP、IPv6 → ICMPv6）の順に解析し、
+//! IPv6の拡張ヘッダーはそれぞれ1つの層として扱う。
+//! 各フィールドのビットオフセット・ビット長・生の値を持つ `DissectedField` の木を返す。
+//! 結果はインデントされたテキストの木、または注釈付きの16進ダンプとして出力できる。
+
+pub mod field;
+pub mod render;
+
+use crate::dissector::field::{DissectedField, LayerBuilder};
+use crate::http::request::HttpRequest;
+use crate::protocols::checksum::{self, InternetChecksum};
+use crate::protocols::icmp::icmp_message::{
+    ICMP_DESTINATION_UNREACHABLE, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST, ICMP_PARAMETER_PROBLEM,
+    ICMP_REDIRECT, ICMP_TIME_EXCEEDED,
+};
+use crate::protocols::icmpv6::icmpv6_message::{
+    self, ICMPV6_DESTINATION_UNREACHABLE, ICMPV6_ECHO_REPLY, ICMPV6_ECHO_REQUEST,
+    ICMPV6_NEIGHBOR_ADVERTISEMENT, ICMPV6_NEIGHBOR_SOLICITATION, ICMPV6_PACKET_TOO_BIG,
+    ICMPV6_PARAMETER_PROBLEM, ICMPV6_ROUTER_ADVERTISEMENT, ICMPV6_ROUTER_SOLICITATION,
+    ICMPV6_TIME_EXCEEDED, IPV6_NEXT_HEADER_ICMPV6,
+};
+use crate::protocols::icmpv6::ndp_option::NdpOption;
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv4_header::layout as ipv4_layout;
+use crate::protocols::ip::ipv4_option::IPv4Option;
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_extension::{
+    IPV6_AUTHENTICATION, IPV6_DESTINATION_OPTIONS, IPV6_ESP, IPV6_FRAGMENT, IPV6_HOP_BY_HOP,
+    IPV6_NO_NEXT_HEADER, IPV6_ROUTING, is_extension_header,
+};
+use crate::protocols::tcp::tcp_header::layout as tcp_layout;
+use crate::protocols::tcp::tcp_option::{TcpOption, TCP_MAX_WINDOW_SCALE};
+use crate::protocols::tcp::tcp_flags::flag_names;
+use crate::types::bit_stream::BitStream;
+use crate::types::parse_error::ParseError;
+use bytes::Bytes;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// フレームを分解した結果
+pub struct Dissection {
+    /// 元のフレーム
+    pub data: Bytes,
+    /// 先頭から順に並んだプロトコル層
+    pub layers: Vec<DissectedField>,
+    /// 途中で解析に失敗した場合、その理由
+    pub error: Option<ParseError>,
+}
+
+/// フレームを層ごとに分解する
+pub fn dissect(frame: &[u8]) -> Dissection {
+    // フレームのコピーは1度だけにし、ストリームとはバッファを共有する
+    let data = Bytes::copy_from_slice(frame);
+    let mut stream = BitStream::from_bytes(data.clone());
+    let mut dissection = Dissection {
+        data,
+        layers: Vec::new(),
+        error: None,
+    };
+    if let Err(e) = dissect_frame(frame, &mut stream, &mut dissection.layers) {
+        dissection.error = Some(e);
+    }
+    dissection
+}
+
+/// 1つの層を解析する。途中で失敗しても、それまでに読んだフィールドは層として残す
+fn run_layer<T>(
+    name: &str,
+    stream: &mut BitStream,
+    layers: &mut Vec<DissectedField>,
+    f: impl FnOnce(&mut LayerBuilder) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let mut builder = LayerBuilder::new(name, stream);
+    let result = f(&mut builder);
+    layers.push(builder.finish());
+    result
+}
+
+fn dissect_frame(
+    frame: &[u8],
+    stream: &mut BitStream,
+    layers: &mut Vec<DissectedField>,
+) -> Result<(), ParseError> {
+    match stream.try_view(4)?.to_u8() {
+        4 => dissect_ipv4_packet(frame, stream, layers)?,
+        6 => dissect_ipv6_packet(stream, layers)?,
+        _ => {
+            layers.push(data_layer("Data", frame, 0));
+            return Ok(());
+        }
+    }
+
+    // ペイロード長より後ろに残ったデータはリンク層のパディング
+    if stream.remaining > 0 {
+        let offset = stream.position() / 8;
+        layers.push(data_layer("Padding", &frame[offset..], offset));
+    }
+    Ok(())
+}
+
+/// IPv4ヘッダーと上位層を分解する
+fn dissect_ipv4_packet(
+    frame: &[u8],
+    stream: &mut BitStream,
+    layers: &mut Vec<DissectedField>,
+) -> Result<(), ParseError> {
+    let ipv4 = run_layer("Internet Protocol Version 4", stream, layers, |b| {
+        dissect_ipv4(b, frame)
+    })?;
+
+    // ペイロードは total_length までに限定する。フレームが短い場合は読める分だけ
+    let payload_bits = (ipv4.payload_length * 8).min(stream.remaining);
+    let mut payload = stream.sub_stream(payload_bits)?;
+    let segment = payload.bits.to_u8s();
+
+    if ipv4.fragment_offset != 0 {
+        if !segment.is_empty() {
+            layers.push(data_layer("Fragment Data", &segment, payload.origin() / 8));
+        }
+    } else if ipv4.protocol == 1 {
+        run_layer("Internet Control Message Protocol", &mut payload, layers, |b| {
+            dissect_icmp(b, &segment)
+        })?;
+    } else if ipv4.protocol == 6 {
+        let pseudo_header =
+            checksum::ipv4_pseudo_header(ipv4.source, ipv4.destination, 6, segment.len() as u16);
+        dissect_tcp_segment(&mut payload, &segment, pseudo_header, layers)?;
+    } else if !segment.is_empty() {
+        layers.push(data_layer("Data", &segment, payload.origin() / 8));
+    }
+    Ok(())
+}
+
+/// IPv6ヘッダー、拡張ヘッダーの連鎖と上位層を分解する
+fn dissect_ipv6_packet(
+    stream: &mut BitStream,
+    layers: &mut Vec<DissectedField>,
+) -> Result<(), ParseError> {
+    let ipv6 = run_layer("Internet Protocol Version 6", stream, layers, dissect_ipv6)?;
+    let payload_bits = (ipv6.payload_length * 8).min(stream.remaining);
+    let mut payload = stream.sub_stream(payload_bits)?;
+
+    let mut next_header = Some(ipv6.next_header);
+    let mut rest_name = "Data";
+    while let Some(kind) = next_header.filter(|kind| is_extension_header(*kind)) {
+        next_header = run_layer(extension_name(kind), &mut payload, layers, |b| {
+            dissect_extension(b, kind)
+        })?;
+        if next_header.is_none() {
+            // 先頭以外のフラグメントと、ESPで暗号化された部分は上位層として読めない
+            rest_name = if kind == IPV6_ESP { "Encrypted Data" } else { "Fragment Data" };
+        }
+    }
+
+    let offset = (payload.origin() + payload.position()) / 8;
+    let segment = payload.read_remaining_bytes();
+    match next_header {
+        Some(6) => {
+            let pseudo_header = checksum::ipv6_pseudo_header(
+                ipv6.source.octets(),
+                ipv6.destination.octets(),
+                6,
+                segment.len() as u32,
+            );
+            dissect_tcp_segment(&mut payload, &segment, pseudo_header, layers)?;
+        }
+        Some(IPV6_NEXT_HEADER_ICMPV6) => {
+            run_layer("Internet Control Message Protocol v6", &mut payload, layers, |b| {
+                dissect_icmpv6(b, &segment, &ipv6)
+            })?;
+        }
+        _ if segment.is_empty() => {}
+        _ => layers.push(data_layer(rest_name, &segment, offset)),
+    }
+    Ok(())
+}
+
+/// TCPヘッダーと、そのデータ（ポート80ならHTTP）を分解する
+fn dissect_tcp_segment(
+    payload: &mut BitStream,
+    segment: &[u8],
+    pseudo_header: InternetChecksum,
+    layers: &mut Vec<DissectedField>,
+) -> Result<(), ParseError> {
+    let (source_port, destination_port) =
+        run_layer("Transmission Control Protocol", payload, layers, |b| {
+            dissect_tcp(b, segment, pseudo_header)
+        })?;
+    let offset = (payload.origin() + payload.position()) / 8;
+    let data = payload.read_remaining_bytes();
+    if !data.is_empty() {
+        if source_port == 80 || destination_port == 80 {
+            layers.push(dissect_http(&data, offset));
+        } else {
+            layers.push(data_layer("Data", &data, offset));
+        }
+    }
+    Ok(())
+}
+
+/// 上位層の解析に必要なIPv4ヘッダーの情報
+struct Ipv4Summary {
+    protocol: u8,
+    /// 先頭以外のフラグメントの場合、上位層のヘッダーは含まれない
+    fragment_offset: u64,
+    source: [u8; 4],
+    destination: [u8; 4],
+    payload_length: usize,
+}
+
+/// IPv4ヘッダーを分解する
+fn dissect_ipv4(b: &mut LayerBuilder, frame: &[u8]) -> Result<Ipv4Summary, ParseError> {
+    let start = b.offset() / 8;
+    let header_end = start + frame.get(start).map_or(0, |byte| (byte & 0x0f) as usize * 4);
+    let checksum_status = match frame.get(start..header_end) {
+        Some(header) if checksum::verify(header) => "correct",
+        Some(_) => "incorrect",
+        None => "unverified",
+    };
+    let [
+        _version,
+        ihl,
+        _dscp,
+        _ecn,
+        total_length,
+        _identification,
+        _flags,
+        fragment_offset,
+        _ttl,
+        protocol,
+        _checksum,
+        source,
+        destination,
+    ] = b.fields(
+        &ipv4_layout::FIXED,
+        |spec, v| match *spec {
+            ipv4_layout::HEADER_LENGTH => format!("{} bytes ({})", v * 4, v),
+            ipv4_layout::IDENTIFICATION => format!("{:#06x} ({})", v, v),
+            ipv4_layout::FLAGS => format!("{:03b}", v),
+            ipv4_layout::FRAGMENT_OFFSET => format!("{} ({} bytes)", v, v * 8),
+            ipv4_layout::PROTOCOL => format!("{} ({})", protocol_name(v as u8), v),
+            ipv4_layout::HEADER_CHECKSUM => format!("{:#06x} [{}]", v, checksum_status),
+            ipv4_layout::SOURCE_ADDRESS | ipv4_layout::DESTINATION_ADDRESS => format_ipv4(v),
+            _ => v.to_string(),
+        },
+        |b, spec, v| {
+            if *spec == ipv4_layout::FLAGS {
+                let offset = b.offset() - spec.width;
+                annotate_flags(
+                    b,
+                    offset,
+                    spec.width,
+                    v,
+                    &["Reserved bit", "Don't fragment", "More fragments"],
+                );
+            }
+        },
+    )?;
+    let protocol = protocol as u8;
+
+    if ihl > 5 {
+        let options_offset = b.offset();
+        let bytes = b.bytes("Options", (ihl as usize - 5) * 4)?;
+        annotate_ipv4_options(b, &bytes, options_offset);
+    }
+    b.set_summary(format!(
+        "Src: {}, Dst: {}",
+        format_ipv4(source),
+        format_ipv4(destination)
+    ));
+    Ok(Ipv4Summary {
+        protocol,
+        fragment_offset,
+        source: (source as u32).to_be_bytes(),
+        destination: (destination as u32).to_be_bytes(),
+        payload_length: (total_length as usize).saturating_sub(ihl as usize * 4),
+    })
+}
+
+/// 上位層の解析に必要なIPv6ヘッダーの情報
+struct Ipv6Summary {
+    next_header: u8,
+    source: IPv6Address,
+    destination: IPv6Address,
+    payload_length: usize,
+}
+
+/// IPv6の基本ヘッダーを分解する
+fn dissect_ipv6(b: &mut LayerBuilder) -> Result<Ipv6Summary, ParseError> {
+    b.field("Version", 4)?;
+    b.field_with("Traffic Class", 8, |v| format!("{:#04x}", v))?;
+    b.field_with("Flow Label", 20, |v| format!("{:#07x}", v))?;
+    let payload_length = b.field("Payload Length", 16)?;
+    let next_header = b.field_with("Next Header", 8, format_next_header)? as u8;
+    b.field("Hop Limit", 8)?;
+    let source = read_ipv6(b, "Source Address")?;
+    let destination = read_ipv6(b, "Destination Address")?;
+    b.set_summary(format!(
+        "Src: {}, Dst: {}",
+        Ipv6Addr::from(source),
+        Ipv6Addr::from(destination)
+    ));
+    Ok(Ipv6Summary {
+        next_header,
+        source,
+        destination,
+        payload_length: payload_length as usize,
+    })
+}
+
+/// 拡張ヘッダーを1つ分解し、続くヘッダーの Next Header を返す
+///
+/// 先頭以外のフラグメントとESPでは、続きを上位層として読めないので `None` を返す。
+fn dissect_extension(b: &mut LayerBuilder, kind: u8) -> Result<Option<u8>, ParseError> {
+    if kind == IPV6_ESP {
+        let spi = b.field_with("Security Parameters Index", 32, |v| format!("{:#010x}", v))?;
+        b.field("Sequence Number", 32)?;
+        b.set_summary(format!("SPI: {:#010x}", spi));
+        return Ok(None);
+    }
+    let next_header = b.field_with("Next Header", 8, format_next_header)? as u8;
+    b.set_summary(format!("Next Header: {}", format_next_header(next_header as u64)));
+    match kind {
+        IPV6_FRAGMENT => {
+            b.field("Reserved", 8)?;
+            let offset =
+                b.field_with("Fragment Offset", 13, |v| format!("{} ({} bytes)", v, v * 8))?;
+            b.field("Reserved", 2)?;
+            let more = b.field_with("More Fragments", 1, |v| {
+                if v == 1 { "Set" } else { "Not set" }.to_string()
+            })?;
+            let identification =
+                b.field_with("Identification", 32, |v| format!("{:#010x}", v))?;
+            b.set_summary(format!(
+                "Offset: {}, More: {}, ID: {:#010x}",
+                offset * 8,
+                more == 1,
+                identification
+            ));
+            Ok((offset == 0).then_some(next_header))
+        }
+        IPV6_AUTHENTICATION => {
+            let len =
+                b.field_with("Payload Length", 8, |v| format!("{} bytes ({})", (v + 2) * 4, v))?;
+            b.field("Reserved", 16)?;
+            b.field_with("Security Parameters Index", 32, |v| format!("{:#010x}", v))?;
+            b.field("Sequence Number", 32)?;
+            let icv = ((len as usize + 2) * 4).saturating_sub(12);
+            if icv > 0 {
+                b.bytes("Integrity Check Value", icv)?;
+            }
+            Ok(Some(next_header))
+        }
+        _ => {
+            let len = b.field_with("Header Length", 8, |v| {
+                format!("{} bytes ({})", (v + 1) * 8, v)
+            })?;
+            let body = (len as usize + 1) * 8 - 2;
+            if kind == IPV6_ROUTING {
+                b.field("Routing Type", 8)?;
+                b.field("Segments Left", 8)?;
+                b.bytes("Data", body - 2)?;
+            } else {
+                let options_offset = b.offset();
+                let bytes = b.bytes("Options", body)?;
+                annotate_ipv6_options(b, &bytes, options_offset);
+            }
+            Ok(Some(next_header))
+        }
+    }
+}
+
+fn extension_name(kind: u8) -> &'static str {
+    match kind {
+        IPV6_HOP_BY_HOP => "IPv6 Hop-by-Hop Options",
+        IPV6_ROUTING => "IPv6 Routing Header",
+        IPV6_FRAGMENT => "IPv6 Fragment Header",
+        IPV6_DESTINATION_OPTIONS => "IPv6 Destination Options",
+        IPV6_AUTHENTICATION => "Authentication Header",
+        _ => "Encapsulating Security Payload",
+    }
+}
+
+/// Hop-by-Hop / Destination Options のオプションを1つずつ子ノードとして追加する
+fn annotate_ipv6_options(b: &mut LayerBuilder, bytes: &[u8], offset: usize) {
+    let mut i = 0;
+    while i < bytes.len() {
+        let kind = bytes[i];
+        let len = if kind == 0 {
+            1
+        } else {
+            match bytes.get(i + 1) {
+                Some(&len) if i + 2 + len as usize <= bytes.len() => 2 + len as usize,
+                _ => {
+                    b.annotate_last(DissectedField::new(
+                        "Malformed Option",
+                        offset + i * 8,
+                        (bytes.len() - i) * 8,
+                        format!("kind {}", kind),
+                    ));
+                    return;
+                }
+            }
+        };
+        let (name, value) = match kind {
+            0 => ("Pad1", String::new()),
+            1 => ("PadN", format!("{} bytes", len)),
+            5 if len == 4 => (
+                "Router Alert",
+                u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]).to_string(),
+            ),
+            _ => ("Unknown", format!("kind {}, {} bytes", kind, len - 2)),
+        };
+        b.annotate_last(DissectedField::new(name, offset + i * 8, len * 8, value));
+        i += len;
+    }
+}
+
+/// IPv4オプションを1つずつ子ノードとして追加する
+fn annotate_ipv4_options(b: &mut LayerBuilder, bytes: &[u8], offset: usize) {
+    let options = match IPv4Option::parse_list(&mut BitStream::from_bytes(bytes.to_vec())) {
+        Ok(options) => options,
+        Err(e) => {
+            b.annotate_last(DissectedField::new(
+                "Malformed Option",
+                offset,
+                bytes.len() * 8,
+                e.to_string(),
+            ));
+            return;
+        }
+    };
+    let mut pos = offset;
+    for option in options {
+        let len = option.encoded_len() * 8;
+        let (name, value) = match &option {
+            IPv4Option::End => ("End of Option List", String::new()),
+            IPv4Option::Nop => ("No Operation", String::new()),
+            IPv4Option::RecordRoute { route, .. } => ("Record Route", format_route(route)),
+            IPv4Option::Timestamp { entries, .. } => {
+                ("Timestamp", format!("{} entries", entries.len()))
+            }
+            IPv4Option::LooseSourceRoute { route, .. } => {
+                ("Loose Source Route", format_route(route))
+            }
+            IPv4Option::StrictSourceRoute { route, .. } => {
+                ("Strict Source Route", format_route(route))
+            }
+            IPv4Option::RouterAlert(value) => ("Router Alert", value.to_string()),
+            IPv4Option::Unknown { kind, data } => {
+                ("Unknown", format!("kind {}, {} bytes", kind, data.len()))
+            }
+            IPv4Option::Padding(bytes) => ("Padding", format!("{} bytes", bytes.len())),
+        };
+        b.annotate_last(DissectedField::new(name, pos, len, value));
+        pos += len;
+    }
+}
+
+fn format_route(route: &[IPv4Address]) -> String {
+    route
+        .iter()
+        .map(|address| Ipv4Addr::from(*address).to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// TCPオプションを1つずつ子ノードとして追加する
+fn annotate_tcp_options(b: &mut LayerBuilder, bytes: &[u8], offset: usize) {
+    let options = match TcpOption::parse_list(&mut BitStream::from_bytes(bytes.to_vec())) {
+        Ok(options) => options,
+        Err(e) => {
+            b.annotate_last(DissectedField::new(
+                "Malformed Option",
+                offset,
+                bytes.len() * 8,
+                e.to_string(),
+            ));
+            return;
+        }
+    };
+    let mut pos = offset;
+    for option in options {
+        let len = option.encoded_len() * 8;
+        let (name, value) = match &option {
+            TcpOption::End => ("End of Option List", String::new()),
+            TcpOption::Nop => ("No Operation", String::new()),
+            TcpOption::MaximumSegmentSize(mss) => ("Maximum Segment Size", format!("{} bytes", mss)),
+            TcpOption::WindowScale(shift) => {
+                ("Window Scale", format!("{} (multiply by {})", shift, 1u32 << (*shift).min(TCP_MAX_WINDOW_SCALE)))
+            }
+            TcpOption::SackPermitted => ("SACK Permitted", String::new()),
+            TcpOption::Timestamps { value, echo_reply } => {
+                ("Timestamps", format!("TSval {}, TSecr {}", value, echo_reply))
+            }
+            TcpOption::Unknown { kind, data } => {
+                ("Unknown", format!("kind {}, {} bytes", kind, data.len()))
+            }
+            TcpOption::Padding(bytes) => ("Padding", format!("{} bytes", bytes.len())),
+        };
+        b.annotate_last(DissectedField::new(name, pos, len, value));
+        pos += len;
+    }
+}
+
+/// TCPヘッダーを分解し、(送信元ポート, 宛先ポート) を返す
+///
+/// `pseudo_header` はチェックサムの検証に使う、疑似ヘッダーを加算済みのアキュムレータ。
+fn dissect_tcp(
+    b: &mut LayerBuilder,
+    segment: &[u8],
+    mut pseudo_header: InternetChecksum,
+) -> Result<(u16, u16), ParseError> {
+    let start = b.offset();
+    pseudo_header.add_bytes(segment);
+    let checksum_status = if pseudo_header.finish() == 0 { "correct" } else { "incorrect" };
+    let [
+        source_port,
+        destination_port,
+        sequence,
+        acknowledgment,
+        data_offset,
+        _reserved,
+        flags,
+        _window,
+        _checksum,
+        _urgent_pointer,
+    ] = b.fields(
+        &tcp_layout::FIXED,
+        |spec, v| match *spec {
+            tcp_layout::DATA_OFFSET => format!("{} bytes ({})", v * 4, v),
+            tcp_layout::FLAGS => format!("{:#04x} ({})", v, flag_names(v as u8).join(", ")),
+            tcp_layout::CHECKSUM => format!("{:#06x} [{}]", v, checksum_status),
+            _ => v.to_string(),
+        },
+        |b, spec, v| {
+            if *spec == tcp_layout::FLAGS {
+                let offset = b.offset() - spec.width;
+                annotate_flags(
+                    b,
+                    offset,
+                    spec.width,
+                    v,
+                    &[
+                        "Congestion Window Reduced",
+                        "ECN-Echo",
+                        "Urgent",
+                        "Acknowledgment",
+                        "Push",
+                        "Reset",
+                        "Syn",
+                        "Fin",
+                    ],
+                );
+            }
+        },
+    )?;
+    let (source_port, destination_port, flags) =
+        (source_port as u16, destination_port as u16, flags as u8);
+    if data_offset > 5 {
+        let options_offset = b.offset();
+        let bytes = b.bytes("Options", (data_offset as usize - 5) * 4)?;
+        annotate_tcp_options(b, &bytes, options_offset);
+    }
+
+    let payload_len = segment.len().saturating_sub((b.offset() - start) / 8);
+    b.set_summary(format!(
+        "Src Port: {}, Dst Port: {}, Seq: {}, Ack: {}, Flags: [{}], Len: {}",
+        source_port,
+        destination_port,
+        sequence,
+        acknowledgment,
+        flag_names(flags).join(", "),
+        payload_len
+    ));
+    Ok((source_port, destination_port))
+}
+
+/// ICMPメッセージを分解する
+fn dissect_icmp(b: &mut LayerBuilder, message: &[u8]) -> Result<(), ParseError> {
+    let icmp_type = b.field_with("Type", 8, |v| format!("{} ({})", v, icmp_type_name(v as u8)))? as u8;
+    let code = b.field("Code", 8)?;
+    let checksum_status = if checksum::verify(message) { "correct" } else { "incorrect" };
+    b.field_with("Checksum", 16, |v| format!("{:#06x} [{}]", v, checksum_status))?;
+    let rest = message.len().saturating_sub(8);
+    let summary = match icmp_type {
+        ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY => {
+            let identifier = b.field("Identifier", 16)?;
+            let sequence = b.field("Sequence Number", 16)?;
+            if rest > 0 {
+                b.bytes("Data", rest)?;
+            }
+            format!(
+                "{}, ID: {}, Seq: {}, Len: {}",
+                icmp_type_name(icmp_type),
+                identifier,
+                sequence,
+                rest
+            )
+        }
+        _ => {
+            match icmp_type {
+                ICMP_DESTINATION_UNREACHABLE => {
+                    b.field("Unused", 16)?;
+                    b.field("Next-Hop MTU", 16)?;
+                }
+                ICMP_REDIRECT => {
+                    b.field_with("Gateway Address", 32, format_ipv4)?;
+                }
+                ICMP_PARAMETER_PROBLEM => {
+                    b.field("Pointer", 8)?;
+                    b.field("Unused", 24)?;
+                }
+                _ => {
+                    b.field_with("Rest of Header", 32, |v| format!("{:#010x}", v))?;
+                }
+            }
+            if rest > 0 {
+                b.bytes("Original Datagram", rest)?;
+            }
+            format!("{}, Code: {}", icmp_type_name(icmp_type), code)
+        }
+    };
+    b.set_summary(summary);
+    Ok(())
+}
+
+/// ICMPv6メッセージを分解する。Neighbor Discovery のオプションも1つずつ表示する
+fn dissect_icmpv6(
+    b: &mut LayerBuilder,
+    message: &[u8],
+    ipv6: &Ipv6Summary,
+) -> Result<(), ParseError> {
+    let start = b.offset();
+    let icmp_type =
+        b.field_with("Type", 8, |v| format!("{} ({})", v, icmpv6_type_name(v as u8)))? as u8;
+    let code = b.field("Code", 8)?;
+    let checksum_status = if icmpv6_message::verify(message, &ipv6.source, &ipv6.destination) {
+        "correct"
+    } else {
+        "incorrect"
+    };
+    b.field_with("Checksum", 16, |v| format!("{:#06x} [{}]", v, checksum_status))?;
+    let rest = |b: &LayerBuilder| message.len().saturating_sub((b.offset() - start) / 8);
+    let name = icmpv6_type_name(icmp_type);
+    let summary = match icmp_type {
+        ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY => {
+            let identifier = b.field("Identifier", 16)?;
+            let sequence = b.field("Sequence Number", 16)?;
+            let len = rest(b);
+            if len > 0 {
+                b.bytes("Data", len)?;
+            }
+            format!("{}, ID: {}, Seq: {}, Len: {}", name, identifier, sequence, len)
+        }
+        ICMPV6_DESTINATION_UNREACHABLE
+        | ICMPV6_PACKET_TOO_BIG
+        | ICMPV6_TIME_EXCEEDED
+        | ICMPV6_PARAMETER_PROBLEM => {
+            let summary = match icmp_type {
+                ICMPV6_PACKET_TOO_BIG => format!("{}, MTU: {}", name, b.field("MTU", 32)?),
+                ICMPV6_PARAMETER_PROBLEM => {
+                    let pointer = b.field("Pointer", 32)?;
+                    format!("{}, Code: {}, Pointer: {}", name, code, pointer)
+                }
+                _ => {
+                    b.field("Unused", 32)?;
+                    format!("{}, Code: {}", name, code)
+                }
+            };
+            let len = rest(b);
+            if len > 0 {
+                b.bytes("Original Packet", len)?;
+            }
+            summary
+        }
+        ICMPV6_ROUTER_SOLICITATION => {
+            b.field("Reserved", 32)?;
+            dissect_ndp_options(b, rest(b))?;
+            name.to_string()
+        }
+        ICMPV6_ROUTER_ADVERTISEMENT => {
+            b.field("Cur Hop Limit", 8)?;
+            let flags_offset = b.offset();
+            let flags = b.field_with("Flags", 8, |v| format!("{:#04x}", v))?;
+            annotate_flags(b, flags_offset, 8, flags, &["Managed", "Other"]);
+            let lifetime = b.field_with("Router Lifetime", 16, |v| format!("{} s", v))?;
+            b.field_with("Reachable Time", 32, |v| format!("{} ms", v))?;
+            b.field_with("Retrans Timer", 32, |v| format!("{} ms", v))?;
+            dissect_ndp_options(b, rest(b))?;
+            format!("{}, Lifetime: {} s", name, lifetime)
+        }
+        ICMPV6_NEIGHBOR_SOLICITATION | ICMPV6_NEIGHBOR_ADVERTISEMENT => {
+            if icmp_type == ICMPV6_NEIGHBOR_ADVERTISEMENT {
+                let flags_offset = b.offset();
+                let flags = b.field_with("Flags", 3, |v| format!("{:03b}", v))?;
+                annotate_flags(b, flags_offset, 3, flags, &["Router", "Solicited", "Override"]);
+                b.field("Reserved", 29)?;
+            } else {
+                b.field("Reserved", 32)?;
+            }
+            let target = read_ipv6(b, "Target Address")?;
+            dissect_ndp_options(b, rest(b))?;
+            format!("{} for {}", name, Ipv6Addr::from(target))
+        }
+        _ => {
+            let len = rest(b);
+            if len > 0 {
+                b.bytes("Data", len)?;
+            }
+            format!("{}, Code: {}", name, code)
+        }
+    };
+    b.set_summary(summary);
+    Ok(())
+}
+
+/// Neighbor Discovery のオプション部を読み、1つずつ子ノードとして追加する
+fn dissect_ndp_options(b: &mut LayerBuilder, len: usize) -> Result<(), ParseError> {
+    if len == 0 {
+        return Ok(());
+    }
+    let offset = b.offset();
+    let bytes = b.bytes("Options", len)?;
+    let options = match NdpOption::parse_list(&mut BitStream::from_bytes(bytes.clone())) {
+        Ok(options) => options,
+        Err(e) => {
+            b.annotate_last(DissectedField::new(
+                "Malformed Option",
+                offset,
+                bytes.len() * 8,
+                e.to_string(),
+            ));
+            return Ok(());
+        }
+    };
+    let mut pos = offset;
+    for option in options {
+        let len = option.encoded_len() * 8;
+        let (name, value) = match &option {
+            NdpOption::SourceLinkLayerAddress(address) => {
+                ("Source Link-Layer Address", format_link_layer(address))
+            }
+            NdpOption::TargetLinkLayerAddress(address) => {
+                ("Target Link-Layer Address", format_link_layer(address))
+            }
+            NdpOption::PrefixInformation {
+                prefix_len, prefix, ..
+            } => (
+                "Prefix Information",
+                format!("{}/{}", Ipv6Addr::from(*prefix), prefix_len),
+            ),
+            NdpOption::Mtu(mtu) => ("MTU", mtu.to_string()),
+            NdpOption::Unknown { kind, data } => {
+                ("Unknown", format!("kind {}, {} bytes", kind, data.len()))
+            }
+        };
+        b.annotate_last(DissectedField::new(name, pos, len, value));
+        pos += len;
+    }
+    Ok(())
+}
+
+fn icmpv6_type_name(icmp_type: u8) -> &'static str {
+    match icmp_type {
+        ICMPV6_DESTINATION_UNREACHABLE => "Destination Unreachable",
+        ICMPV6_PACKET_TOO_BIG => "Packet Too Big",
+        ICMPV6_TIME_EXCEEDED => "Time Exceeded",
+        ICMPV6_PARAMETER_PROBLEM => "Parameter Problem",
+        ICMPV6_ECHO_REQUEST => "Echo Request",
+        ICMPV6_ECHO_REPLY => "Echo Reply",
+        ICMPV6_ROUTER_SOLICITATION => "Router Solicitation",
+        ICMPV6_ROUTER_ADVERTISEMENT => "Router Advertisement",
+        ICMPV6_NEIGHBOR_SOLICITATION => "Neighbor Solicitation",
+        ICMPV6_NEIGHBOR_ADVERTISEMENT => "Neighbor Advertisement",
+        _ => "Unknown",
+    }
+}
+
+fn icmp_type_name(icmp_type: u8) -> &'static str {
+    match icmp_type {
+        ICMP_ECHO_REPLY => "Echo Reply",
+        ICMP_DESTINATION_UNREACHABLE => "Destination Unreachable",
+        ICMP_REDIRECT => "Redirect",
+        ICMP_ECHO_REQUEST => "Echo Request",
+        ICMP_TIME_EXCEEDED => "Time Exceeded",
+        ICMP_PARAMETER_PROBLEM => "Parameter Problem",
+        _ => "Unknown",
+    }
+}
+
+/// HTTPメッセージを行ごとに分解する
+fn dissect_http(payload: &[u8], offset: usize) -> DissectedField {
+    let text = String::from_utf8_lossy(payload);
+    let summary = match HttpRequest::parse(&text) {
+        Ok(request) => format!("{} {} {}", request.method, request.path, request.version),
+        Err(_) => text.lines().next().unwrap_or_default().to_string(),
+    };
+    let mut layer = DissectedField::new(
+        "Hypertext Transfer Protocol",
+        offset * 8,
+        payload.len() * 8,
+        summary,
+    );
+
+    // ヘッダー部は1行ずつ、空行以降はまとめてボディとして扱う
+    let mut pos = 0;
+    while pos < payload.len() {
+        let line_end = payload[pos..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map(|i| pos + i + 1)
+            .unwrap_or(payload.len());
+        let line = String::from_utf8_lossy(&payload[pos..line_end]);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            pos = line_end;
+            break;
+        }
+        layer.children.push(DissectedField::new(
+            "Line",
+            (offset + pos) * 8,
+            (line_end - pos) * 8,
+            line.to_string(),
+        ));
+        pos = line_end;
+    }
+    if pos < payload.len() {
+        layer.children.push(DissectedField::new(
+            "Body",
+            (offset + pos) * 8,
+            (payload.len() - pos) * 8,
+            format!("{} bytes", payload.len() - pos),
+        ));
+    }
+    layer
+}
+
+/// 解釈できないペイロードを生データの層として表す
+fn data_layer(name: &str, payload: &[u8], offset: usize) -> DissectedField {
+    DissectedField::new(
+        name,
+        offset * 8,
+        payload.len() * 8,
+        format!("{} bytes", payload.len()),
+    )
+}
+
+/// `width` ビットのフラグのフィールドに、上位ビットから順に `names` の内訳を追加する
+fn annotate_flags(b: &mut LayerBuilder, offset: usize, width: usize, flags: u64, names: &[&str]) {
+    for (i, name) in names.iter().enumerate() {
+        let set = flags & (1 << (width - 1 - i)) != 0;
+        let mut flag = DissectedField::new(
+            name,
+            offset + i,
+            1,
+            if set { "Set" } else { "Not set" }.to_string(),
+        );
+        flag.raw = Some(set as u64);
+        b.annotate_last(flag);
+    }
+}
+
+/// 16バイトを読み、IPv6アドレスとして表示する
+fn read_ipv6(b: &mut LayerBuilder, name: &str) -> Result<IPv6Address, ParseError> {
+    let bytes = b.bytes_with(name, 16, |bytes| {
+        let octets: [u8; 16] = bytes.try_into().unwrap_or_default();
+        Ipv6Addr::from(octets).to_string()
+    })?;
+    Ok(IPv6Address::from_octets(bytes.try_into().unwrap_or_default()))
+}
+
+fn format_link_layer(address: &[u8]) -> String {
+    address
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn format_next_header(raw: u64) -> String {
+    format!("{} ({})", protocol_name(raw as u8), raw)
+}
+
+fn format_ipv4(raw: u64) -> String {
+    let [a, b, c, d] = (raw as u32).to_be_bytes();
+    format!("{}.{}.{}.{}", a, b, c, d)
+}
+
+fn protocol_name(protocol: u8) -> &'static str {
+    match protocol {
+        IPV6_HOP_BY_HOP => "IPv6 Hop-by-Hop",
+        1 => "ICMP",
+        6 => "TCP",
+        17 => "UDP",
+        IPV6_ROUTING => "IPv6 Routing",
+        IPV6_FRAGMENT => "IPv6 Fragment",
+        IPV6_ESP => "ESP",
+        IPV6_AUTHENTICATION => "AH",
+        IPV6_NEXT_HEADER_ICMPV6 => "ICMPv6",
+        IPV6_NO_NEXT_HEADER => "No Next Header",
+        IPV6_DESTINATION_OPTIONS => "IPv6 Destination Options",
+        _ => "Unknown",
+    }
+}
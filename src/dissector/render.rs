@@ -0,0 +1,127 @@
+use crate::dissector::Dissection;
+use crate::dissector::field::DissectedField;
+use std::fmt::{Display, Formatter, Write};
+
+impl Dissection {
+    /// Wireshark の詳細ペインのようなインデント付きの木として出力する
+    pub fn render_tree(&self) -> String {
+        let mut out = String::new();
+        for layer in &self.layers {
+            self.render_field(&mut out, layer, 0, true);
+        }
+        if let Some(error) = &self.error {
+            let _ = writeln!(out, "[Malformed Packet: {}]", error);
+        }
+        out
+    }
+
+    fn render_field(&self, out: &mut String, field: &DissectedField, depth: usize, is_layer: bool) {
+        let indent = "    ".repeat(depth);
+        if is_layer {
+            if field.value.is_empty() {
+                let _ = writeln!(out, "{}{}", indent, field.name);
+            } else {
+                let _ = writeln!(out, "{}{}, {}", indent, field.name, field.value);
+            }
+        } else {
+            let _ = write!(
+                out,
+                "{}{}{}: {}",
+                indent,
+                self.bit_pattern(field),
+                field.name,
+                field.value
+            );
+            let _ = write!(
+                out,
+                "  [bit {}, {} bits",
+                field.bit_offset, field.bit_len
+            );
+            if let Some(raw) = field.raw {
+                let _ = write!(out, ", raw {:#x}", raw);
+            }
+            let _ = writeln!(out, "]");
+        }
+        for child in &field.children {
+            self.render_field(out, child, depth + 1, false);
+        }
+    }
+
+    /// バイト境界に揃っていないフィールドを `.... 0101 = ` の形式で表す
+    fn bit_pattern(&self, field: &DissectedField) -> String {
+        if field.bit_offset.is_multiple_of(8) && field.bit_len.is_multiple_of(8) {
+            return String::new();
+        }
+        let first_byte = field.bit_offset / 8;
+        let last_byte = (field.bit_offset + field.bit_len).div_ceil(8);
+        let mut pattern = String::new();
+        for bit in first_byte * 8..last_byte * 8 {
+            if bit > first_byte * 8 && bit % 4 == 0 {
+                pattern.push(' ');
+            }
+            let inside = bit >= field.bit_offset && bit < field.bit_offset + field.bit_len;
+            match self.data.get(bit / 8) {
+                Some(byte) if inside => {
+                    pattern.push(if byte & (1 << (7 - bit % 8)) != 0 { '1' } else { '0' })
+                }
+                _ => pattern.push('.'),
+            }
+        }
+        pattern.push_str(" = ");
+        pattern
+    }
+
+    /// 16バイトごとの16進ダンプに、その行にかかるプロトコル層の名前を添えて出力する
+    pub fn render_hex_dump(&self) -> String {
+        let mut out = String::new();
+        for (row, chunk) in self.data.chunks(16).enumerate() {
+            let start = row * 16;
+            let _ = write!(out, "{:04x}  ", start);
+            for i in 0..16 {
+                match chunk.get(i) {
+                    Some(byte) => {
+                        let _ = write!(out, "{:02x} ", byte);
+                    }
+                    None => out.push_str("   "),
+                }
+                if i == 7 {
+                    out.push(' ');
+                }
+            }
+            out.push(' ');
+            for &byte in chunk {
+                out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                });
+            }
+            for _ in chunk.len()..16 {
+                out.push(' ');
+            }
+
+            let row_bits = start * 8..(start + chunk.len()) * 8;
+            let names = self
+                .layers
+                .iter()
+                .filter(|layer| {
+                    layer.bit_offset < row_bits.end
+                        && layer.bit_offset + layer.bit_len > row_bits.start
+                })
+                .map(|layer| layer.name.as_str())
+                .collect::<Vec<_>>();
+            if !names.is_empty() {
+                let _ = write!(out, "  ; {}", names.join(" | "));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Display for Dissection {
+    /// 分解結果を木の形式でフォーマットする。
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(&self.render_tree())
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct HttpRequest {
     pub method: String,
     pub path: String,
@@ -61,6 +63,27 @@ impl HttpRequest {
         })
     }
 
+    /// 受信したバイト列にリクエストが揃っていれば、その長さを返す
+    ///
+    /// ヘッダーの終わり（空行）までと、Content-Length があればその長さのボディを待つ。
+    /// 1つのリクエストが複数のセグメントに分かれて届く場合に使う。
+    pub fn message_length(received: &[u8]) -> Option<usize> {
+        let header_end = received
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")?
+            + 4;
+        let headers = String::from_utf8_lossy(&received[..header_end]);
+        let content_length = headers
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(Some(0))?;
+        let length = header_end.checked_add(content_length)?;
+        (received.len() >= length).then_some(length)
+    }
+
     /// パス情報を取得（クエリパラメータを除く）
     pub fn get_path(&self) -> String {
         if let Some(question_pos) = self.path.find('?') {
@@ -37,11 +37,7 @@ impl FileServer {
     /// リクエストされたパスを実際のファイルパスに解決
     fn resolve_path(&self, request_path: &str) -> Option<PathBuf> {
         // パスを正規化（先頭の/を除去）
-        let clean_path = if request_path.starts_with('/') {
-            &request_path[1..]
-        } else {
-            request_path
-        };
+        let clean_path = request_path.strip_prefix('/').unwrap_or(request_path);
 
         // 空のパスまたは"/"の場合はindex.htmlを試す
         let target_path = if clean_path.is_empty() {
@@ -53,12 +49,11 @@ impl FileServer {
         let full_path = self.root_dir.join(target_path);
 
         // パストラバーサル攻撃を防ぐため、root_dir内に収まっているかチェック
-        if let Ok(canonical_path) = full_path.canonicalize() {
-            if let Ok(canonical_root) = self.root_dir.canonicalize() {
-                if canonical_path.starts_with(canonical_root) {
-                    return Some(canonical_path);
-                }
-            }
+        if let Ok(canonical_path) = full_path.canonicalize()
+            && let Ok(canonical_root) = self.root_dir.canonicalize()
+            && canonical_path.starts_with(canonical_root)
+        {
+            return Some(canonical_path);
         }
 
         None
@@ -1,3 +1,9 @@
+// `#[derive(ByteObject)]` が生成する `::ferrix::...` パスをクレート内でも解決できるようにする
+extern crate self as ferrix;
+
+pub mod dissector;
+#[cfg(feature = "serde")]
+pub mod packet_log;
 pub mod protocols;
 pub mod types;
 pub mod http;
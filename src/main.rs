@@ -1,20 +1,76 @@
+use bytes::Bytes;
 use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
 use tokio_tun::Tun;
 
-mod protocols;
-mod types;
-mod http;
+use ferrix::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use ferrix::protocols::ip::ipv6_cidr::Ipv6Cidr;
+use ferrix::protocols::checksum;
+use ferrix::protocols::icmp::echo::EchoResponder;
+use ferrix::protocols::icmp::error::{
+    ICMP_PORT_UNREACHABLE, ICMP_PROTOCOL_UNREACHABLE, IcmpErrorGenerator,
+};
+use ferrix::protocols::icmp::icmp_message::IcmpMessage;
+use ferrix::protocols::icmp::rate_limit::RateLimiter;
+use ferrix::protocols::icmpv6::icmpv6_message::{
+    self, ICMPV6_PORT_UNREACHABLE, IPV6_NEXT_HEADER_ICMPV6, Icmpv6Message,
+};
+use ferrix::protocols::icmpv6::neighbor_discovery::{self, ND_HOP_LIMIT};
+use ferrix::protocols::icmpv6::slaac::Slaac;
+use ferrix::protocols::ip::fragmentation::{self, IdentificationGenerator};
+use ferrix::protocols::ip::ingress::Ipv4Ingress;
+use ferrix::protocols::ip::ip_address::IpAddress;
+use ferrix::protocols::ip::ipv4_header::{IPV4_FLAG_DF, IPv4Header};
+use ferrix::protocols::ip::ipv6_extension::{
+    self, ChainError, ExtensionChain, ExtensionHeaders, IPV6_NO_NEXT_HEADER,
+    PARAMETER_PROBLEM_UNRECOGNIZED_NEXT_HEADER,
+};
+use ferrix::protocols::ip::ipv6_address::IPv6Address;
+use ferrix::protocols::ip::ipv6_header::{IPV6_HEADER_LENGTH, IPv6Header};
+use ferrix::protocols::ip::reassembly::{Ipv4Reassembler, Ipv6Reassembler, ReassemblyConfig};
+use ferrix::protocols::ip::stats::IpStats;
+use ferrix::protocols::tcp::connection::{ConnectionKey, ConnectionTable};
+use ferrix::protocols::tcp::rtt::CLOCK_GRANULARITY;
+use ferrix::protocols::tcp::tcp_flags::TCP_RST;
+use ferrix::protocols::tcp::tcp_header::TcpHeader;
+use ferrix::protocols::tcp::tcp_state::TcpState;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+use ferrix::types::parse_error::ParseError;
 
-use crate::protocols::ip::ipv4_address::IPv4Address;
-use crate::protocols::ip::ipv4_header::IPv4Header;
-use crate::protocols::tcp::tcp_flags::{TCP_ACK, TCP_FIN, TCP_SYN};
-use crate::protocols::tcp::tcp_header::TcpHeader;
-use crate::types::bit_stream::{BitStream, Bits, BitsCompatible};
-use crate::types::byte_object::ByteObject;
+use ferrix::dissector::dissect;
+use ferrix::http::request::HttpRequest;
+use ferrix::http::response::HttpResponse;
+use ferrix::http::server::FileServer;
+#[cfg(feature = "serde")]
+use ferrix::packet_log::PacketLogEntry;
 
-use crate::http::request::HttpRequest;
-use crate::http::response::HttpResponse;
-use crate::http::server::FileServer;
+/// パケットの処理をまたいで保持する状態
+struct Stack {
+    file_server: FileServer,
+    /// 受信したIPv4データグラムの検証
+    ingress: Ipv4Ingress,
+    ip_stats: IpStats,
+    /// IPv4フラグメントの再構築
+    reassembler: Ipv4Reassembler,
+    /// IPv6フラグメントの再構築
+    ipv6_reassembler: Ipv6Reassembler,
+    /// 送信するIPv4データグラムの Identification
+    identification: IdentificationGenerator,
+    /// インターフェースのMTU（バイト）
+    mtu: usize,
+    echo: EchoResponder,
+    /// ICMPエラーの生成とレート制限
+    icmp_errors: IcmpErrorGenerator,
+    /// ICMPv6エラーのレート制限
+    icmpv6_errors: RateLimiter,
+    /// IPv6アドレスの自動設定
+    slaac: Slaac,
+    /// 自分宛てとして応答するIPv6プレフィックス
+    ipv6_network: Ipv6Cidr,
+    /// TCPの待ち受けとコネクション
+    tcp: ConnectionTable,
+}
 
 #[tokio::main]
 async fn main() {
@@ -25,14 +81,15 @@ async fn main() {
     let file_server = FileServer::new("www");
 
     // TUNデバイスの設定
+    let network: Ipv4Cidr = "10.0.0.1/24".parse().unwrap();
     let tun = &Tun::builder()
         .name("") // 名称はOSに委ねる
         .mtu(1350)
         .up()
-        .address(Ipv4Addr::new(10, 0, 0, 1))
+        .address(network.address().into())
         .destination(Ipv4Addr::new(10, 1, 0, 1))
         .broadcast(Ipv4Addr::BROADCAST)
-        .netmask(Ipv4Addr::new(255, 255, 255, 0))
+        .netmask(network.netmask().into())
         .queues(1)
         .build()
         .unwrap()[0];
@@ -46,113 +103,723 @@ async fn main() {
     println!("  Broadcast: {}", tun.broadcast().unwrap());
     println!("  Netmask: {}", tun.netmask().unwrap());
 
-    println!("Please execute `curl 10.1.0.2` from another terminal to test.");
+    println!("Please execute `ping 10.1.0.2` or `curl 10.1.0.2` from another terminal to test.");
+
+    // `--json-log <path>` が指定された場合、受信したパケットをJSON Lines形式で追記する
+    #[cfg(feature = "serde")]
+    let mut json_log = json_log_path().map(|path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", path, e))
+    });
+    #[cfg(not(feature = "serde"))]
+    if json_log_path().is_some() {
+        eprintln!("--json-log requires building with `--features serde`; ignoring");
+    }
+
+    // `--dissect` が指定された場合、受信したパケットを層ごとに分解して表示する
+    let dissect_packets = std::env::args().any(|arg| arg == "--dissect");
+
+    // ユーザー空間のスタックが自分宛てとして応答するアドレス
+    let local_network: Ipv4Cidr = "10.1.0.0/24".parse().unwrap();
+    let mut stack = Stack {
+        file_server,
+        ingress: Ipv4Ingress::new(vec![local_network]),
+        ip_stats: IpStats::new(),
+        reassembler: Ipv4Reassembler::new(ReassemblyConfig::default()),
+        // 重なったフラグメントは破棄する（RFC 5722）。既定の OverlapPolicy::Drop のまま使う
+        ipv6_reassembler: Ipv6Reassembler::new(ReassemblyConfig::default()),
+        identification: IdentificationGenerator::new(),
+        mtu: tun.mtu().unwrap() as usize,
+        echo: EchoResponder::new(vec![local_network]),
+        icmp_errors: IcmpErrorGenerator::default().local_networks(vec![local_network]),
+        icmpv6_errors: RateLimiter::default(),
+        slaac: Slaac::new(),
+        ipv6_network: "fd00:1::/64".parse().unwrap(),
+        tcp: ConnectionTable::new().mtu(tun.mtu().unwrap() as usize),
+    };
+    stack.tcp.listen(80);
+    println!("IPv6 link-local address: {}", stack.slaac.link_local());
+    // タイムアウトなどの定期処理の間隔
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    // TCPの再送タイマーを確かめる間隔
+    let mut tcp_ticker = tokio::time::interval(CLOCK_GRANULARITY);
+    // IP層の統計を出力する間隔
+    let mut stats_ticker = tokio::time::interval(Duration::from_secs(60));
+    // 最後に出力した統計。変わっていなければ出力しない
+    let mut reported_stats = IpStats::new();
 
     let mut buf = vec![0; 1504];
+    // 解析に失敗して破棄したパケット数
+    let mut malformed_packets: u64 = 0;
 
     // メインループ
     loop {
         let buf = tokio::select! {
             Ok(n) = tun.recv(&mut buf) => &buf[..n],
+            _ = ticker.tick() => {
+                expire_timers(&mut stack, tun).await;
+                continue;
+            }
+            _ = tcp_ticker.tick() => {
+                retransmit_tcp(&mut stack, tun).await;
+                continue;
+            }
+            _ = stats_ticker.tick() => {
+                if stack.ip_stats != reported_stats {
+                    print!("{}", stack.ip_stats);
+                    reported_stats = stack.ip_stats.clone();
+                }
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                // 終了する前にIP層の統計を出力する
+                print!("{}", stack.ip_stats);
+                return;
+            }
         };
-        println!("reading {} bytes from tun: {:?}", buf.len(), buf);
+        if dissect_packets {
+            let dissection = dissect(buf);
+            println!("reading {} bytes from tun:", buf.len());
+            print!("{}", dissection.render_tree());
+            print!("{}", dissection.render_hex_dump());
+        }
 
-        if let Err(e) = handle_packet(buf, tun, &file_server).await {
-            eprintln!("Error handling packet: {}", e);
+        #[cfg(feature = "serde")]
+        if let Some(file) = json_log.as_mut()
+            && let Err(e) = PacketLogEntry::from_frame(buf).write_line(file)
+        {
+            eprintln!("Failed to write packet log: {}", e);
+        }
+
+        if let Err(e) = handle_packet(buf, tun, &mut stack).await {
+            if e.is::<ParseError>() {
+                // 壊れたパケットは破棄して数えるだけにする
+                malformed_packets += 1;
+                eprintln!(
+                    "Dropped malformed packet ({} so far): {}",
+                    malformed_packets, e
+                );
+            } else {
+                eprintln!("Error handling packet: {}", e);
+            }
         }
     }
 }
 
+/// コマンドライン引数から `--json-log` の出力先を取り出す
+fn json_log_path() -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--json-log");
+    args.next()?;
+    args.next()
+}
+
 // パケット受信時、処理を行う
-async fn handle_packet(buf: &[u8], tun: &Tun, file_server: &FileServer) -> Result<(), Box<dyn std::error::Error>> {
+async fn handle_packet(buf: &[u8], tun: &Tun, stack: &mut Stack) -> Result<(), Box<dyn std::error::Error>> {
     // 前から読んでいくため、Streamに変換
-    let mut stream = BitStream::new(buf.to_vec().to_bits());
+    let mut stream = BitStream::from_bytes(Bytes::copy_from_slice(buf));
     // 先頭4bitがプロコトルを表す
-    match stream.view(4).to_u8() {
+    let version = stream.try_view(4)?.to_u8();
+    match version {
         4 => {
             // IPv4パケットの処理
             println!("IPv4 Packet Detected");
-            let ipv4_header = IPv4Header::from_stream(&mut stream);
+            stack.ip_stats.in_receives += 1;
+            // ペイロードはヘッダーの直後から total_length までに限定され、末尾のパディングを含まない
+            let (ipv4_header, payload) = match stack.ingress.validate(buf) {
+                Ok(validated) => validated,
+                Err(reason) => {
+                    stack.ip_stats.record_drop(&reason);
+                    println!("Dropped IPv4 datagram: {}", reason);
+                    return Ok(());
+                }
+            };
             println!("IPv4 Header: {}", ipv4_header);
+            // フラグメントは全て揃うまで保持し、揃ったら1つのデータグラムとして処理する
+            let (ipv4_header, payload) = if ipv4_header.is_fragment() {
+                stack.ip_stats.reasm_reqds += 1;
+                let data = payload.read_remaining_bytes();
+                match stack.reassembler.insert_ipv4(&ipv4_header, &data, Instant::now()) {
+                    Ok(Some((header, data))) => {
+                        stack.ip_stats.reasm_oks += 1;
+                        println!("Reassembled {} byte datagram", data.len());
+                        (header, BitStream::from_bytes(data))
+                    }
+                    Ok(None) => return Ok(()),
+                    Err(e) => {
+                        stack.ip_stats.record_reassembly_failure(&e);
+                        return Err(e.into());
+                    }
+                }
+            } else {
+                (ipv4_header, payload)
+            };
+            let source = IpAddress::V4(ipv4_header.source_address);
+            let destination = IpAddress::V4(ipv4_header.destination_address);
             match ipv4_header.protocol {
+                1 => {
+                    stack.ip_stats.in_delivers += 1;
+                    handle_icmp(&ipv4_header, payload, tun, stack).await?;
+                }
                 6 => {
-                    // TCPパケットの処理
-                    println!("TCP Packet Detected");
-                    let tcp_header = TcpHeader::from_stream(&mut stream);
-                    println!("TCP Header: {}", tcp_header);
-                    if (tcp_header.flags & TCP_SYN) != 0 {
-                        // SYNフラグが立っている場合、SYN-ACKを送信
-                        println!("TCP SYN Packet Detected");
-                        send_syn_ack(
-                            ipv4_header.source_address,
-                            ipv4_header.destination_address,
-                            tcp_header.source_port,
-                            tcp_header.destination_port,
-                            tcp_header.sequence_number,
-                            tun,
-                        )
-                        .await?;
-                    } else if tcp_header.destination_port == 80
-                        && (tcp_header.flags & TCP_ACK) != 0
-                        && (tcp_header.flags & TCP_FIN) == 0
-                        && stream.remaining > 0
-                    {
-                        // HTTPリクエストを受信した場合、HTTPレスポンスを送信
-                        println!("HTTP Packet Detected");
-                        
-                        // TCPペイロードからHTTPリクエストを抽出
-                        let http_payload = stream.read_remaining_bytes();
-                        
-                        send_http_response(
-                            ipv4_header.source_address,
-                            ipv4_header.destination_address,
-                            tcp_header.source_port,
-                            tcp_header.destination_port,
-                            tcp_header.sequence_number,
-                            tcp_header.acknowledgment_number,
-                            &http_payload,
-                            tun,
-                            file_server,
-                        )
-                        .await?;
-                    }
+                    stack.ip_stats.in_delivers += 1;
+                    handle_tcp(source, destination, payload, tun, stack).await?;
                 }
                 17 => {
-                    // UDPパケットの処理
+                    // UDPで待ち受けているポートはないため、Port Unreachable を返す。
+                    // 上位層には渡しているので、ポートがなくても InDelivers に数える
                     println!("UDP Packet Detected");
-                    todo!("Handle UDP packet");
+                    stack.ip_stats.in_delivers += 1;
+                    let payload = payload.read_remaining_bytes();
+                    send_destination_unreachable(
+                        ICMP_PORT_UNREACHABLE,
+                        &ipv4_header,
+                        &payload,
+                        tun,
+                        stack,
+                    )
+                    .await?;
                 }
                 _ => {
-                    // その他のプロトコルは無視
+                    // その他のプロトコルは Protocol Unreachable を返す
                     println!("Unknown Protocol: {}", ipv4_header.protocol);
-                    return Ok(());
+                    stack.ip_stats.in_unknown_protos += 1;
+                    let payload = payload.read_remaining_bytes();
+                    send_destination_unreachable(
+                        ICMP_PROTOCOL_UNREACHABLE,
+                        &ipv4_header,
+                        &payload,
+                        tun,
+                        stack,
+                    )
+                    .await?;
                 }
             }
         }
         6 => {
             // IPv6パケットの処理
             println!("IPv6 Packet Detected");
-            // todo!("Handle IPv6 packet");
+            let ipv6_header = IPv6Header::from_stream(&mut stream)?;
+            println!("IPv6 Header: {}", ipv6_header);
+            // 自分のアドレスとマルチキャスト（Neighbor Discovery など）以外は受け取らない
+            let destination = &ipv6_header.destination_address;
+            if !destination.is_multicast() && !is_local_ipv6(stack, destination) {
+                println!("Dropped IPv6 packet not addressed to us: {}", destination);
+                return Ok(());
+            }
+            // ペイロードは payload_length までに限定し、末尾のパディングを含めない
+            let payload = stream
+                .sub_stream(ipv6_header.payload_length as usize * 8)?
+                .read_remaining_bytes();
+            let packet = &buf[..IPV6_HEADER_LENGTH + payload.len()];
+            let chain = match ipv6_extension::parse_chain(&ipv6_header, &payload) {
+                Ok(chain) => chain,
+                Err(e) => return handle_chain_error(e, &ipv6_header, packet, tun, stack).await,
+            };
+            println!("IPv6 Extension Headers: {:?}", chain.headers);
+            // 分割されていれば揃うまで保持し、揃ったら断片化可能部分の拡張ヘッダーを続けてたどる
+            let (chain, data, packet) = if chain.is_fragmented() {
+                let reassembled = stack.ipv6_reassembler.insert_ipv6(
+                    &ipv6_header,
+                    &chain,
+                    &payload,
+                    packet,
+                    Instant::now(),
+                )?;
+                let Some((head, data)) = reassembled else {
+                    return Ok(());
+                };
+                println!("Reassembled {} byte IPv6 packet", data.len());
+                let headers = ExtensionHeaders::resume(
+                    head.next_header,
+                    &data,
+                    &head.header.destination_address,
+                    head.fragmentable_offset,
+                );
+                match ExtensionChain::parse(headers) {
+                    Ok(chain) => (chain, data, head.packet),
+                    Err(e) => {
+                        return handle_chain_error(e, &ipv6_header, &head.packet, tun, stack).await;
+                    }
+                }
+            } else {
+                (chain, payload, packet.to_vec())
+            };
+            let source = IpAddress::V6(ipv6_header.source_address);
+            let destination = IpAddress::V6(ipv6_header.destination_address);
+            match chain.upper_layer {
+                6 => {
+                    // TCPはユニキャストでしか使わない
+                    if ipv6_header.destination_address.is_multicast() {
+                        return Ok(());
+                    }
+                    let upper = BitStream::from_bytes(data[chain.offset..].to_vec());
+                    handle_tcp(source, destination, upper, tun, stack).await?
+                }
+                IPV6_NEXT_HEADER_ICMPV6 => {
+                    handle_icmpv6(&ipv6_header, &data[chain.offset..], tun, stack).await?
+                }
+                17 => {
+                    // UDPで待ち受けているポートはないため、Port Unreachable を返す
+                    println!("UDP Packet Detected");
+                    let message = Icmpv6Message::DestinationUnreachable {
+                        code: ICMPV6_PORT_UNREACHABLE,
+                        original: Icmpv6Message::original_packet(&packet),
+                    };
+                    send_icmpv6_error(message, &ipv6_header, tun, stack).await?;
+                }
+                IPV6_NO_NEXT_HEADER => {}
+                _ => {
+                    // 処理できない上位層は Parameter Problem で知らせる（RFC 8200 4）
+                    println!("Unknown Next Header: {}", chain.upper_layer);
+                    let error = ChainError::ParameterProblem {
+                        code: PARAMETER_PROBLEM_UNRECOGNIZED_NEXT_HEADER,
+                        pointer: chain.next_header_pointer as u32,
+                    };
+                    return handle_chain_error(error, &ipv6_header, &packet, tun, stack).await;
+                }
+            }
         }
         _ => {
-            println!("Unknown Packet Type: {}", stream.view(4).to_u8());
+            println!("Unknown Packet Type: {}", version);
             return Ok(());
         }
     }
-    return Ok(());
+    Ok(())
 }
 
-async fn send_http_response(
-    src_ip: IPv4Address,
-    dest_ip: IPv4Address,
-    src_port: u16,
-    dest_port: u16,
-    seq_num: u32,
-    ack_num: u32,
-    http_payload: &[u8],
+/// TCPセグメントの処理。IPv4とIPv6で共通
+///
+/// HTTPリクエストを受け取ったら、レスポンスを送ってこちらから閉じる。
+async fn handle_tcp(
+    source: IpAddress,
+    destination: IpAddress,
+    mut payload: BitStream,
     tun: &Tun,
-    file_server: &FileServer,
+    stack: &mut Stack,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    println!("TCP Packet Detected");
+    let tcp_header = TcpHeader::from_stream(&mut payload)?;
+    println!("TCP Header: {}", tcp_header);
+    let data = payload.read_remaining_bytes();
+    if !tcp_header.verify_checksum(source, destination, &data) {
+        println!("Dropped TCP segment with bad checksum");
+        return Ok(());
+    }
+    let key = ConnectionKey::incoming(source, destination, &tcp_header);
+    let now = Instant::now();
+    let before = stack.tcp.state(&key);
+    let mut segments = stack.tcp.receive(source, destination, &tcp_header, &data, now);
+    let after = stack.tcp.state(&key);
+    if before != after {
+        println!("TCP {}: {} -> {}", key, before, after);
+    }
+    if after.can_send() && request_complete(&stack.tcp, &key) {
+        let request = stack.tcp.read(&key)?;
+        if !request.is_empty() {
+            let response = http_response(&request, &stack.file_server);
+            segments.extend(stack.tcp.send(&key, &response, now)?);
+            segments.extend(stack.tcp.close(&key, now)?);
+            println!("TCP {}: {} -> {}", key, after, stack.tcp.state(&key));
+        }
+    }
+    for segment in segments {
+        send_ip(segment.source, segment.destination, 6, false, &segment.encode(), tun, stack)
+            .await?;
+    }
+    Ok(())
+}
+
+/// ICMPメッセージの処理。Echo Request には Echo Reply を返す
+async fn handle_icmp(
+    ipv4_header: &IPv4Header,
+    payload: BitStream,
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = payload.read_remaining_bytes();
+    if !checksum::verify(&data) {
+        println!("Dropped ICMP message with bad checksum");
+        return Ok(());
+    }
+    let message = IcmpMessage::from_stream(&mut BitStream::from_bytes(data))?;
+    println!("{}", message);
+    if let Some(reply) = stack.echo.respond(ipv4_header, &message) {
+        let mut writer = BitWriter::new();
+        reply.write_to(&mut writer);
+        send_ip(
+            IpAddress::V4(ipv4_header.destination_address),
+            IpAddress::V4(ipv4_header.source_address),
+            1,
+            false,
+            &writer.finish(),
+            tun,
+            stack,
+        )
+        .await?;
+        println!("Sent ICMP Echo Reply to {}", ipv4_header.source_address);
+    }
+    Ok(())
+}
+
+/// 期限の過ぎた状態を破棄する定期処理
+///
+/// 再構築がタイムアウトしたデータグラムは、先頭フラグメントを受信していれば
+/// ICMP Time Exceeded を送り返す（RFC 792, RFC 4443）。
+/// 寿命の切れたIPv6アドレスと、TIME-WAIT を終えたTCPコネクションも取り除く。
+async fn expire_timers(stack: &mut Stack, tun: &Tun) {
+    for expired in stack.reassembler.expire(Instant::now()) {
+        println!("Fragment reassembly timed out: {:?}", expired.key);
+        stack.ip_stats.reasm_timeout += 1;
+        stack.ip_stats.reasm_fails += 1;
+        let Some(header) = expired.header else {
+            continue;
+        };
+        // Code 1: 再構築時間超過
+        let Some(message) =
+            stack.icmp_errors.time_exceeded(1, &header, &expired.payload, Instant::now())
+        else {
+            continue;
+        };
+        if let Err(e) = send_icmp_error(message, &header, tun, stack).await {
+            eprintln!("Failed to send ICMP Time Exceeded: {}", e);
+        }
+    }
+    for expired in stack.ipv6_reassembler.expire(Instant::now()) {
+        println!("IPv6 fragment reassembly timed out: {:?}", expired.key);
+        let Some(head) = expired.header else {
+            continue;
+        };
+        // Code 1: 再構築時間超過
+        let message = Icmpv6Message::TimeExceeded {
+            code: 1,
+            original: head.packet,
+        };
+        if let Err(e) = send_icmpv6_error(message, &head.header, tun, stack).await {
+            eprintln!("Failed to send ICMPv6 Time Exceeded: {}", e);
+        }
+    }
+    for expired in stack.slaac.expire(Instant::now()) {
+        println!("IPv6 address {} expired", expired.cidr);
+    }
+    for key in stack.tcp.expire(Instant::now()) {
+        println!("TCP {}: TIME-WAIT -> CLOSED", key);
+    }
+}
+
+/// 再送タイマーの期限が来たTCPセグメントを再送する
+///
+/// 再送回数の上限を超えたコネクションは中断され、RSTを送る。
+async fn retransmit_tcp(stack: &mut Stack, tun: &Tun) {
+    for segment in stack.tcp.retransmit(Instant::now()) {
+        let key = ConnectionKey {
+            local_address: segment.source,
+            local_port: segment.header.source_port,
+            remote_address: segment.destination,
+            remote_port: segment.header.destination_port,
+        };
+        if segment.header.flags & TCP_RST != 0 {
+            println!("TCP {}: retransmission limit reached, aborted", key);
+        } else {
+            println!("TCP {}: retransmitting seq {}", key, segment.header.sequence_number);
+        }
+        let encoded = segment.encode();
+        if let Err(e) =
+            send_ip(segment.source, segment.destination, 6, false, &encoded, tun, stack).await
+        {
+            eprintln!("Failed to retransmit TCP segment: {}", e);
+        }
+    }
+}
+
+/// 拡張ヘッダーを処理できなかったIPv6パケットを破棄し、必要なら Parameter Problem を返す
+async fn handle_chain_error(
+    error: ChainError,
+    original: &IPv6Header,
+    packet: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match error {
+        ChainError::Malformed(e) => Err(e.into()),
+        ChainError::Discard => {
+            println!("Dropped IPv6 packet: {}", error);
+            Ok(())
+        }
+        ChainError::ParameterProblem { code, pointer } => {
+            println!("Dropped IPv6 packet: {}", error);
+            let message = Icmpv6Message::ParameterProblem {
+                code,
+                pointer,
+                original: Icmpv6Message::original_packet(packet),
+            };
+            send_icmpv6_error(message, original, tun, stack).await
+        }
+    }
+}
+
+/// ICMPv6メッセージの処理
+///
+/// Echo Request と自分のアドレスを対象とする Neighbor Solicitation に応答し、
+/// Router Advertisement からアドレスを自動設定する。
+async fn handle_icmpv6(
+    header: &IPv6Header,
+    data: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !icmpv6_message::verify(data, &header.source_address, &header.destination_address) {
+        println!("Dropped ICMPv6 message with bad checksum");
+        return Ok(());
+    }
+    let message = Icmpv6Message::from_stream(&mut BitStream::from_bytes(data.to_vec()))?;
+    println!("{}", message);
+    if message.is_neighbor_discovery() && !neighbor_discovery::is_valid(header, &message) {
+        println!("Dropped invalid Neighbor Discovery message");
+        return Ok(());
+    }
+    match &message {
+        Icmpv6Message::EchoRequest {
+            identifier,
+            sequence,
+            data,
+        } => {
+            // 自分のユニキャストアドレス宛てにだけ応答する
+            if !is_local_ipv6(stack, &header.destination_address) {
+                return Ok(());
+            }
+            let reply = Icmpv6Message::EchoReply {
+                identifier: *identifier,
+                sequence: *sequence,
+                data: data.clone(),
+            };
+            send_icmpv6(header.destination_address, header.source_address, 64, &reply, tun, stack)
+                .await?;
+        }
+        Icmpv6Message::NeighborSolicitation { target, .. } => {
+            let slaac = &stack.slaac;
+            if let Some((destination, advertisement)) =
+                neighbor_discovery::advertise(header, &message, |address| slaac.is_local(address))
+            {
+                send_icmpv6(*target, destination, ND_HOP_LIMIT, &advertisement, tun, stack).await?;
+            }
+        }
+        Icmpv6Message::RouterAdvertisement { .. } => {
+            for address in stack
+                .slaac
+                .process_router_advertisement(&message, Instant::now())
+            {
+                println!("Configured IPv6 address {}", address);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 自分のIPv6アドレスかどうか
+///
+/// リンクローカルアドレス、自動設定したアドレスと、設定したプレフィックス内のアドレスを自分宛てとする。
+fn is_local_ipv6(stack: &Stack, address: &IPv6Address) -> bool {
+    stack.slaac.is_local(address) || stack.ipv6_network.contains(address)
+}
+
+/// ICMPv6メッセージを送信する
+async fn send_icmpv6(
+    source: IPv6Address,
+    destination: IPv6Address,
+    hop_limit: u8,
+    message: &Icmpv6Message,
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let icmp = message.encode(&source, &destination);
+    let header = IPv6Header::new(
+        icmp.len() as u16,
+        IPV6_NEXT_HEADER_ICMPV6,
+        hop_limit,
+        source,
+        destination,
+    );
+    send_ipv6(header, &icmp, tun, stack).await?;
+    println!("Sent {} to {}", message, destination);
+    Ok(())
+}
+
+/// ICMPv6エラーメッセージ（RFC 4443）を元のパケットの送信元へ送る
+///
+/// 送信元が未指定やマルチキャストのパケットには返さない。マルチキャスト宛ての場合は
+/// 返信に使うユニキャストアドレスが決まらないため、これも返さない。ICMPv6エラーメッセージへの
+/// エラーや、レート制限中も送らない。
+async fn send_icmpv6_error(
+    message: Icmpv6Message,
+    original: &IPv6Header,
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if original.source_address.is_unspecified()
+        || original.source_address.is_multicast()
+        || original.destination_address.is_multicast()
+        || message.original().is_some_and(icmpv6_message::carries_error)
+        || !stack.icmpv6_errors.allow(Instant::now())
+    {
+        return Ok(());
+    }
+    send_icmpv6(
+        original.destination_address,
+        original.source_address,
+        64,
+        &message,
+        tun,
+        stack,
+    )
+    .await
+}
+
+/// 届け先のないデータグラムに ICMP Destination Unreachable を返す
+///
+/// 返してはならないデータグラムやレート制限中は何もしない。
+async fn send_destination_unreachable(
+    code: u8,
+    original: &IPv4Header,
+    payload: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(message) =
+        stack.icmp_errors.destination_unreachable(code, original, payload, Instant::now())
+    else {
+        return Ok(());
+    };
+    send_icmp_error(message, original, tun, stack).await
+}
+
+/// ICMPエラーを元のデータグラムの送信元へ送る
+async fn send_icmp_error(
+    message: IcmpMessage,
+    original: &IPv4Header,
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut icmp = BitWriter::new();
+    message.write_to(&mut icmp);
+    send_ip(
+        IpAddress::V4(original.destination_address),
+        IpAddress::V4(original.source_address),
+        1,
+        false,
+        &icmp.finish(),
+        tun,
+        stack,
+    )
+    .await?;
+    println!("Sent {} to {}", message, original.source_address);
+    Ok(())
+}
+
+/// IPv4データグラムを送信する
+///
+/// Identification を宛先ごとに採番し、MTUを超える場合はフラグメント化する。
+/// Don't Fragment が立っていてMTUを超える場合はエラーを返す。
+async fn send_ipv4(
+    mut header: IPv4Header,
+    payload: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stack.ip_stats.out_requests += 1;
+    header.identification = stack.identification.next(header.destination_address);
+    let packets = match fragmentation::fragment(&header, payload, stack.mtu) {
+        Ok(packets) => packets,
+        Err(e) => {
+            stack.ip_stats.frag_fails += 1;
+            stack.ip_stats.out_discards += 1;
+            return Err(e.into());
+        }
+    };
+    if packets.len() > 1 {
+        println!("Fragmented {} byte datagram into {} packets", payload.len(), packets.len());
+        stack.ip_stats.frag_oks += 1;
+        stack.ip_stats.frag_creates += packets.len() as u64;
+    }
+    for packet in packets {
+        tun.send(&packet).await?;
+    }
+    Ok(())
+}
+
+/// IPデータグラムを送信する。アドレスのバージョンに応じてIPv4かIPv6のヘッダーを付ける
+async fn send_ip(
+    source: IpAddress,
+    destination: IpAddress,
+    protocol: u8,
+    dont_fragment: bool,
+    payload: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match (source, destination) {
+        (IpAddress::V4(source), IpAddress::V4(destination)) => {
+            let header = IPv4Header::new_with_checksum(
+                4,
+                0,
+                0,
+                (20 + payload.len()) as u16,
+                0,
+                if dont_fragment { IPV4_FLAG_DF } else { 0 },
+                0,
+                64,
+                protocol,
+                source,
+                destination,
+            );
+            send_ipv4(header, payload, tun, stack).await
+        }
+        (IpAddress::V6(source), IpAddress::V6(destination)) => {
+            let header = IPv6Header::new(payload.len() as u16, protocol, 64, source, destination);
+            send_ipv6(header, payload, tun, stack).await
+        }
+        _ => Err("source and destination address families differ".into()),
+    }
+}
+
+/// IPv6データグラムを送信する
+///
+/// 送信元でのフラグメント化はまだ行わないため、MTUを超える場合はエラーを返す。
+async fn send_ipv6(
+    header: IPv6Header,
+    payload: &[u8],
+    tun: &Tun,
+    stack: &mut Stack,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut packet = BitWriter::with_capacity(IPV6_HEADER_LENGTH + payload.len());
+    header.write_to(&mut packet);
+    packet.write_bytes(payload);
+    let packet = packet.finish();
+    if packet.len() > stack.mtu {
+        return Err(format!("{} byte IPv6 packet exceeds MTU {}", packet.len(), stack.mtu).into());
+    }
+    tun.send(&packet).await?;
+    Ok(())
+}
+
+/// HTTPリクエストを全て受け取ったかどうか
+///
+/// 揃うまでは受信バッファに溜めておく。相手が送信を終えた場合や、受信バッファが
+/// いっぱいになった場合は、それまでに受け取った分で応答する。
+fn request_complete(tcp: &ConnectionTable, key: &ConnectionKey) -> bool {
+    let Some(tcb) = tcp.get(key) else {
+        return false;
+    };
+    let received = tcb.received();
+    HttpRequest::message_length(received).is_some()
+        || (!received.is_empty() && (tcb.state == TcpState::CloseWait || tcb.rcv_wnd() == 0))
+}
+
+/// HTTPリクエストに対するレスポンスを作る
+fn http_response(http_payload: &[u8], file_server: &FileServer) -> Vec<u8> {
     // HTTPペイロードからHTTPリクエストを解析
     let http_request_str = String::from_utf8_lossy(http_payload);
     println!("Received HTTP request: {}", http_request_str);
@@ -173,112 +840,5 @@ async fn send_http_response(
         }
     };
 
-    let response_bytes = http_response.to_bytes();
-
-    let mut response = BitStream::new(Bits::new());
-
-    // IPv4ヘッダーの作成
-    let ipv4_header = IPv4Header::new_with_checksum(
-        4,
-        5,
-        0,
-        0,
-        (20 + 20 + response_bytes.len() as u16) as u16, // IPv4ヘッダー(20 bytes) + TCPヘッダー(20 bytes) + HTTPデータ
-        (ack_num + 1) as u16,
-        2, // Don't Fragment
-        0,
-        64,
-        6, // TCP
-        dest_ip.clone(),
-        src_ip.clone(),
-    );
-    response.append(ipv4_header.to_bits());
-
-    // TCPヘッダーの作成
-    let tcp_header = TcpHeader::new_with_checksum(
-        dest_port,
-        src_port,
-        ack_num,
-        seq_num + 1,
-        5, // 5 * 4 = 20 bytes
-        0,
-        TCP_ACK,
-        (seq_num + 1) as u16, // 最大ウィンドウサイズ
-        0,
-        &src_ip,
-        &dest_ip,
-        &response_bytes,
-    );
-    response.append(tcp_header.to_bits());
-
-    response.append(response_bytes.to_bits());
-
-    tun.send(&response.bits.to_u8s()).await?;
-    println!("Sent HTTP response with FIN");
-    Ok(())
-}
-
-async fn send_syn_ack(
-    src_ip: IPv4Address,
-    dest_ip: IPv4Address,
-    src_port: u16,
-    dest_port: u16,
-    seq_num: u32,
-    tun: &Tun,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut response = BitStream::new(Bits::new());
-
-    let mut tcp_header = TcpHeader {
-        source_port: dest_port,
-        destination_port: src_port,
-        sequence_number: 12345,
-        acknowledgment_number: seq_num + 1,
-        data_offset: 5, // 5 * 4 = 20 bytes
-        reserved: 0,
-        flags: 0b00010010,                 // SYN flag
-        window_size: (seq_num + 1) as u16, // Maximum window size
-        checksum: 0,                       // Placeholder for checksum
-        urgent_pointer: 0,
-    };
-    tcp_header.update_checksum(&src_ip, &dest_ip, &[]);
-
-    // Calculate total length: IPv4 header (20 bytes) + TCP header (20 bytes)
-    let ipv4_header_size = 20u16; // IHL = 5, so 5 * 4 = 20 bytes
-    let tcp_header_size = 20u16; // Data offset = 5, so 5 * 4 = 20 bytes
-    let total_length = ipv4_header_size + tcp_header_size;
-
-    let mut ipv4_header = IPv4Header {
-        version: 4,
-        ihl: 5,
-        dscp: 0,
-        ecn: 0,
-        total_length,
-        identification: 0,
-        flags: 2,
-        fragment_offset: 0,
-        ttl: 64,
-        protocol: 6,
-        header_checksum: 0,
-        source_address: dest_ip.clone(),
-        destination_address: src_ip.clone(),
-    };
-    ipv4_header.update_checksum();
-
-    response.append(ipv4_header.to_bits());
-    response.append(tcp_header.to_bits());
-
-    println!(
-        "{:?}",
-        &response
-            .bits
-            .to_u8s()
-            .iter()
-            .map(|b| format!("{:02x}", b))
-            .collect::<Vec<_>>()
-    );
-    // exit(0);
-    tun.send(&response.bits.to_u8s()).await?;
-    println!("Sent SYN-ACK response");
-
-    Ok(())
+    http_response.to_bytes()
 }
@@ -0,0 +1,121 @@
+//! 受信したパケットを JSON Lines 形式で記録するモジュール（`serde` フィーチャーが必要）。
+//!
+//! 1パケットにつき1行、解析できたヘッダーをそのままJSONオブジェクトとして出力する。
+//! IPv4/IPv6 の上の TCP（ポート80なら HTTP リクエストも）と ICMP/ICMPv6 を解析する。
+
+use crate::http::request::HttpRequest;
+use crate::protocols::icmp::icmp_message::IcmpMessage;
+use crate::protocols::icmpv6::icmpv6_message::{IPV6_NEXT_HEADER_ICMPV6, Icmpv6Message};
+use crate::protocols::ip::ipv4_header::IPv4Header;
+use crate::protocols::ip::ipv6_extension;
+use crate::protocols::ip::ipv6_header::IPv6Header;
+use crate::protocols::tcp::tcp_header::TcpHeader;
+use crate::types::bit_stream::BitStream;
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// パケットログの1行分
+#[derive(Serialize, Deserialize)]
+pub struct PacketLogEntry {
+    /// 受信時刻（UNIXエポックからのミリ秒）
+    pub timestamp_ms: u128,
+    /// フレーム全体の長さ（バイト）
+    pub length: usize,
+    pub ipv4: Option<IPv4Header>,
+    pub ipv6: Option<IPv6Header>,
+    pub tcp: Option<TcpHeader>,
+    pub icmp: Option<IcmpMessage>,
+    pub icmpv6: Option<Icmpv6Message>,
+    pub http: Option<HttpRequest>,
+    /// TCPペイロードの長さ（バイト）
+    pub payload_length: usize,
+    /// 途中で解析に失敗した場合、その理由
+    pub error: Option<String>,
+}
+
+impl PacketLogEntry {
+    /// フレームを解析してログの1行分を作成する
+    pub fn from_frame(frame: &[u8]) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let mut entry = PacketLogEntry {
+            timestamp_ms,
+            length: frame.len(),
+            ipv4: None,
+            ipv6: None,
+            tcp: None,
+            icmp: None,
+            icmpv6: None,
+            http: None,
+            payload_length: 0,
+            error: None,
+        };
+        if let Err(e) = entry.fill(frame) {
+            entry.error = Some(e.to_string());
+        }
+        entry
+    }
+
+    fn fill(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut stream = BitStream::from_bytes(frame.to_vec());
+        match stream.try_view(4)?.to_u8() {
+            4 => {
+                let ipv4 = IPv4Header::from_stream(&mut stream)?;
+                stream.seek(ipv4.header_length() * 8)?;
+                let mut payload = stream.sub_stream(ipv4.payload_length() * 8)?;
+                let protocol = ipv4.protocol;
+                // 先頭以外のフラグメントには上位層のヘッダーがない
+                let first_fragment = ipv4.fragment_offset == 0;
+                self.ipv4 = Some(ipv4);
+                match protocol {
+                    1 if first_fragment => self.icmp = Some(IcmpMessage::from_stream(&mut payload)?),
+                    6 if first_fragment => self.fill_tcp(&mut payload)?,
+                    _ => {}
+                }
+            }
+            6 => {
+                let ipv6 = IPv6Header::from_stream(&mut stream)?;
+                let data = stream.sub_stream(ipv6.payload_length as usize * 8)?.read_remaining_bytes();
+                let chain = ipv6_extension::parse_chain(&ipv6, &data);
+                self.ipv6 = Some(ipv6);
+                let chain = chain?;
+                if matches!(chain.fragment(), Some((offset, _, _)) if offset != 0) {
+                    return Ok(());
+                }
+                let mut payload = BitStream::from_bytes(data[chain.offset..].to_vec());
+                match chain.upper_layer {
+                    IPV6_NEXT_HEADER_ICMPV6 => {
+                        self.icmpv6 = Some(Icmpv6Message::from_stream(&mut payload)?)
+                    }
+                    6 => self.fill_tcp(&mut payload)?,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn fill_tcp(&mut self, payload: &mut BitStream) -> Result<(), ParseError> {
+        let tcp = TcpHeader::from_stream(payload)?;
+        let data = payload.read_remaining_bytes();
+        self.payload_length = data.len();
+        if tcp.destination_port == 80 && !data.is_empty() {
+            self.http = HttpRequest::parse(&String::from_utf8_lossy(&data)).ok();
+        }
+        self.tcp = Some(tcp);
+        Ok(())
+    }
+
+    /// 改行で終わるJSONの1行として `writer` に書き込む
+    pub fn write_line(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        serde_json::to_writer(&mut *writer, self)?;
+        writer.write_all(b"\n")
+    }
+}
@@ -0,0 +1,150 @@
+//! インターネットチェックサム（RFC 1071）を扱うモジュール。
+//!
+//! IPv4ヘッダー、TCP/UDP/ICMPなどが共通で使う1の補数和の計算と、
+//! 疑似ヘッダーの加算、1つのフィールドだけを書き換えた際の差分更新（RFC 1624）を提供する。
+
+/// 16ビット単位の1の補数和を逐次計算するアキュムレータ
+///
+/// `add_bytes` は任意の長さのスライスを受け付け、奇数バイトで区切られた場合も
+/// 次の呼び出しと連結して計算する。
+#[derive(Clone, Default)]
+pub struct InternetChecksum {
+    sum: u64,
+    /// 直前の `add_bytes` で余った上位バイト
+    pending: Option<u8>,
+}
+
+impl InternetChecksum {
+    pub fn new() -> Self {
+        InternetChecksum::default()
+    }
+
+    /// バイト列を加算する
+    pub fn add_bytes(&mut self, mut data: &[u8]) {
+        if let Some(high) = self.pending.take() {
+            match data.split_first() {
+                Some((&low, rest)) => {
+                    self.sum += u16::from_be_bytes([high, low]) as u64;
+                    data = rest;
+                }
+                None => {
+                    self.pending = Some(high);
+                    return;
+                }
+            }
+        }
+
+        let mut chunks = data.chunks_exact(2);
+        for chunk in &mut chunks {
+            self.sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+        }
+        if let [last] = chunks.remainder() {
+            self.pending = Some(*last);
+        }
+    }
+
+    pub fn add_u8_pair(&mut self, high: u8, low: u8) {
+        self.add_bytes(&[high, low]);
+    }
+
+    pub fn add_u16(&mut self, value: u16) {
+        self.add_bytes(&value.to_be_bytes());
+    }
+
+    pub fn add_u32(&mut self, value: u32) {
+        self.add_bytes(&value.to_be_bytes());
+    }
+
+    /// キャリーを折り返した16ビットの和を返す（補数は取らない）
+    pub fn sum(&self) -> u16 {
+        let mut sum = self.sum;
+        if let Some(high) = self.pending {
+            // 奇数バイトの場合、最後のバイトの後に0を追加
+            sum += (high as u64) << 8;
+        }
+        fold(sum)
+    }
+
+    /// チェックサムフィールドに格納する値（和の1の補数）を返す
+    pub fn finish(&self) -> u16 {
+        !self.sum()
+    }
+}
+
+/// キャリーを下位16ビットに加算する
+fn fold(mut sum: u64) -> u16 {
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// バイト列のチェックサムを計算する
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut acc = InternetChecksum::new();
+    acc.add_bytes(data);
+    acc.finish()
+}
+
+/// チェックサムフィールドを含んだままのバイト列が正しいかどうかを検証する
+pub fn verify(data: &[u8]) -> bool {
+    checksum(data) == 0
+}
+
+/// IPv4疑似ヘッダー（送信元、宛先、プロトコル番号、上位層の長さ）を加算したアキュムレータを返す
+pub fn ipv4_pseudo_header(
+    source: [u8; 4],
+    destination: [u8; 4],
+    protocol: u8,
+    length: u16,
+) -> InternetChecksum {
+    let mut acc = InternetChecksum::new();
+    acc.add_bytes(&source);
+    acc.add_bytes(&destination);
+    acc.add_u8_pair(0, protocol);
+    acc.add_u16(length);
+    acc
+}
+
+/// IPv6疑似ヘッダー（RFC 8200 8.1）を加算したアキュムレータを返す
+pub fn ipv6_pseudo_header(
+    source: [u8; 16],
+    destination: [u8; 16],
+    next_header: u8,
+    length: u32,
+) -> InternetChecksum {
+    let mut acc = InternetChecksum::new();
+    acc.add_bytes(&source);
+    acc.add_bytes(&destination);
+    acc.add_u32(length);
+    acc.add_bytes(&[0, 0, 0, next_header]);
+    acc
+}
+
+/// 16ビットのフィールドが `old` から `new` に書き換わったときのチェックサムを求める
+///
+/// RFC 1624 の式3 `HC' = ~(~HC + ~m + m')` に従う。
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    let sum = (!checksum) as u64 + (!old) as u64 + new as u64;
+    !fold(sum)
+}
+
+/// 32ビットのフィールド（IPv4アドレス、シーケンス番号など）を書き換えたときのチェックサムを求める
+pub fn update_u32(checksum: u16, old: u32, new: u32) -> u16 {
+    let checksum = update(checksum, (old >> 16) as u16, (new >> 16) as u16);
+    update(checksum, old as u16, new as u16)
+}
+
+/// 偶数オフセットから始まる同じ長さのバイト列を書き換えたときのチェックサムを求める
+pub fn update_bytes(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    assert_eq!(old.len(), new.len(), "Replaced fields must have the same length");
+    let mut acc = InternetChecksum::new();
+    acc.add_u16(!checksum);
+    for (old, new) in old.chunks(2).zip(new.chunks(2)) {
+        let old = u16::from_be_bytes([old[0], old.get(1).copied().unwrap_or(0)]);
+        let new = u16::from_be_bytes([new[0], new.get(1).copied().unwrap_or(0)]);
+        acc.add_u16(!old);
+        acc.add_u16(new);
+    }
+    !acc.sum()
+}
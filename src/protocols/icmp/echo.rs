@@ -0,0 +1,56 @@
+//! ICMP Echo への応答（RFC 792, RFC 1122 3.2.2.6）。
+
+use crate::protocols::icmp::icmp_message::IcmpMessage;
+use crate::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use crate::protocols::ip::ipv4_header::IPv4Header;
+
+/// Echo Request に Echo Reply を返す
+///
+/// 既定ではブロードキャスト・マルチキャスト宛ての Echo Request には応答しない（RFC 1122 で任意とされている）。
+/// ローカルネットワークのブロードキャストアドレス宛ても同じように扱う。
+#[derive(Clone, Debug)]
+pub struct EchoResponder {
+    /// 自分のアドレスのネットワーク。ブロードキャストアドレスを求めるのに使う
+    local: Vec<Ipv4Cidr>,
+    reply_to_broadcast: bool,
+}
+
+impl EchoResponder {
+    pub fn new(local: Vec<Ipv4Cidr>) -> Self {
+        EchoResponder {
+            local,
+            reply_to_broadcast: false,
+        }
+    }
+
+    /// ブロードキャスト・マルチキャスト宛てにも応答するかどうかを設定する
+    pub fn reply_to_broadcast(mut self, reply: bool) -> Self {
+        self.reply_to_broadcast = reply;
+        self
+    }
+
+    /// `header` で受信した `message` が応答すべき Echo Request なら、Echo Reply を返す
+    ///
+    /// Identifier、Sequence Number、データはそのまま返す。
+    pub fn respond(&self, header: &IPv4Header, message: &IcmpMessage) -> Option<IcmpMessage> {
+        let IcmpMessage::EchoRequest {
+            identifier,
+            sequence,
+            data,
+        } = message
+        else {
+            return None;
+        };
+        let destination = header.destination_address;
+        let broadcast = destination.is_broadcast()
+            || self.local.iter().any(|network| network.is_broadcast(&destination));
+        if !self.reply_to_broadcast && (broadcast || destination.is_multicast()) {
+            return None;
+        }
+        Some(IcmpMessage::EchoReply {
+            identifier: *identifier,
+            sequence: *sequence,
+            data: data.clone(),
+        })
+    }
+}
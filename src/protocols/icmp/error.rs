@@ -0,0 +1,122 @@
+//! ICMPエラーメッセージの生成。
+//!
+//! エラーを返してはならないデータグラム（RFC 1122 3.2.2, RFC 1812 4.3.2.7）を除外し、
+//! レート制限を通ったものだけについて `IcmpMessage` を作る。
+
+use crate::protocols::icmp::icmp_message::{
+    ICMP_DESTINATION_UNREACHABLE, ICMP_PARAMETER_PROBLEM, ICMP_REDIRECT, ICMP_TIME_EXCEEDED,
+    IcmpMessage,
+};
+use crate::protocols::icmp::rate_limit::RateLimiter;
+use crate::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use crate::protocols::ip::ipv4_header::IPv4Header;
+use std::time::Instant;
+
+/// Destination Unreachable の Code（RFC 792）
+pub const ICMP_NET_UNREACHABLE: u8 = 0;
+pub const ICMP_HOST_UNREACHABLE: u8 = 1;
+pub const ICMP_PROTOCOL_UNREACHABLE: u8 = 2;
+pub const ICMP_PORT_UNREACHABLE: u8 = 3;
+pub const ICMP_FRAGMENTATION_NEEDED: u8 = 4;
+
+/// ICMPエラーを生成する
+#[derive(Clone, Debug, Default)]
+pub struct IcmpErrorGenerator {
+    limiter: RateLimiter,
+    /// 自分のアドレスのネットワーク。ブロードキャストアドレス宛てにはエラーを返さない
+    local: Vec<Ipv4Cidr>,
+    /// レート制限で送らなかったエラーの数
+    suppressed: u64,
+}
+
+impl IcmpErrorGenerator {
+    pub fn new(limiter: RateLimiter) -> Self {
+        IcmpErrorGenerator {
+            limiter,
+            local: Vec::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// 自分のアドレスのネットワークを設定する
+    pub fn local_networks(mut self, local: Vec<Ipv4Cidr>) -> Self {
+        self.local = local;
+        self
+    }
+
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// `header` と `payload` からなるデータグラムにエラーを返してよいかどうか
+    ///
+    /// ICMPエラーへのエラー、先頭以外のフラグメント、ブロードキャスト・マルチキャスト宛て、
+    /// 単一のホストを表さない送信元や、ループバック・予約済みの送信元からのデータグラムには返さない。
+    pub fn may_respond_to(&self, header: &IPv4Header, payload: &[u8]) -> bool {
+        let source = header.source_address;
+        let destination = header.destination_address;
+        let icmp_error = header.protocol == 1
+            && payload.first().is_some_and(|icmp_type| {
+                [
+                    ICMP_DESTINATION_UNREACHABLE,
+                    ICMP_REDIRECT,
+                    ICMP_TIME_EXCEEDED,
+                    ICMP_PARAMETER_PROBLEM,
+                ]
+                .contains(icmp_type)
+            });
+        !icmp_error
+            && header.fragment_offset == 0
+            && !destination.is_broadcast()
+            && !destination.is_multicast()
+            && !self.local.iter().any(|network| network.is_broadcast(&destination))
+            && !source.is_unspecified()
+            && !source.is_broadcast()
+            && !source.is_multicast()
+            && !source.is_loopback()
+            && !source.is_reserved()
+            && !self.local.iter().any(|network| network.is_broadcast(&source))
+    }
+
+    /// Destination Unreachable を作る。返してはならない場合やレート制限中は `None`
+    pub fn destination_unreachable(
+        &mut self,
+        code: u8,
+        header: &IPv4Header,
+        payload: &[u8],
+        now: Instant,
+    ) -> Option<IcmpMessage> {
+        self.admit(header, payload, now)
+            .then(|| IcmpMessage::DestinationUnreachable {
+                code,
+                next_hop_mtu: 0,
+                original: IcmpMessage::original_datagram(header, payload),
+            })
+    }
+
+    /// Time Exceeded を作る。返してはならない場合やレート制限中は `None`
+    pub fn time_exceeded(
+        &mut self,
+        code: u8,
+        header: &IPv4Header,
+        payload: &[u8],
+        now: Instant,
+    ) -> Option<IcmpMessage> {
+        self.admit(header, payload, now)
+            .then(|| IcmpMessage::TimeExceeded {
+                code,
+                original: IcmpMessage::original_datagram(header, payload),
+            })
+    }
+
+    fn admit(&mut self, header: &IPv4Header, payload: &[u8], now: Instant) -> bool {
+        if !self.may_respond_to(header, payload) {
+            return false;
+        }
+        if !self.limiter.allow(now) {
+            self.suppressed += 1;
+            return false;
+        }
+        true
+    }
+}
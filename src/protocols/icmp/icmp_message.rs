@@ -0,0 +1,325 @@
+use crate::protocols::checksum;
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv4_header::IPv4Header;
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+use std::fmt::{Display, Formatter};
+
+/// ICMPの Type（RFC 792）
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DESTINATION_UNREACHABLE: u8 = 3;
+pub const ICMP_REDIRECT: u8 = 5;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAMETER_PROBLEM: u8 = 12;
+
+/// ICMPメッセージ
+///
+/// チェックサムはフィールドとして持たず、書き出すときに計算する。
+/// 受信したメッセージのチェックサムは `checksum::verify` で生のバイト列に対して検証すること。
+/// エラーメッセージの `original` には、原因となったデータグラムのヘッダーとペイロードの先頭を入れる。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IcmpMessage {
+    EchoReply {
+        identifier: u16,
+        sequence: u16,
+        data: Vec<u8>,
+    },
+    DestinationUnreachable {
+        code: u8,
+        /// Fragmentation Needed（Code 4）のときのネクストホップMTU（RFC 1191）。それ以外は0
+        next_hop_mtu: u16,
+        original: Vec<u8>,
+    },
+    Redirect {
+        code: u8,
+        gateway: IPv4Address,
+        original: Vec<u8>,
+    },
+    EchoRequest {
+        identifier: u16,
+        sequence: u16,
+        data: Vec<u8>,
+    },
+    TimeExceeded {
+        code: u8,
+        original: Vec<u8>,
+    },
+    ParameterProblem {
+        code: u8,
+        /// 問題のあったバイトの、元のデータグラム先頭からの位置
+        pointer: u8,
+        original: Vec<u8>,
+    },
+    /// 解釈しない種別。Type と Code の後ろの4バイトを含めて `data` に入れる
+    Unknown {
+        icmp_type: u8,
+        code: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl IcmpMessage {
+    pub fn icmp_type(&self) -> u8 {
+        match self {
+            IcmpMessage::EchoReply { .. } => ICMP_ECHO_REPLY,
+            IcmpMessage::DestinationUnreachable { .. } => ICMP_DESTINATION_UNREACHABLE,
+            IcmpMessage::Redirect { .. } => ICMP_REDIRECT,
+            IcmpMessage::EchoRequest { .. } => ICMP_ECHO_REQUEST,
+            IcmpMessage::TimeExceeded { .. } => ICMP_TIME_EXCEEDED,
+            IcmpMessage::ParameterProblem { .. } => ICMP_PARAMETER_PROBLEM,
+            IcmpMessage::Unknown { icmp_type, .. } => *icmp_type,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            IcmpMessage::EchoReply { .. } | IcmpMessage::EchoRequest { .. } => 0,
+            IcmpMessage::DestinationUnreachable { code, .. }
+            | IcmpMessage::Redirect { code, .. }
+            | IcmpMessage::TimeExceeded { code, .. }
+            | IcmpMessage::ParameterProblem { code, .. }
+            | IcmpMessage::Unknown { code, .. } => *code,
+        }
+    }
+
+    /// エラーメッセージかどうか。エラーメッセージに対してエラーを返してはならない（RFC 1122 3.2.2）
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            IcmpMessage::DestinationUnreachable { .. }
+                | IcmpMessage::Redirect { .. }
+                | IcmpMessage::TimeExceeded { .. }
+                | IcmpMessage::ParameterProblem { .. }
+        )
+    }
+
+    /// エラーメッセージに入れる元のデータグラム。ヘッダーとペイロードの先頭8バイト（RFC 792）
+    pub fn original_datagram(header: &IPv4Header, payload: &[u8]) -> Vec<u8> {
+        let mut writer = BitWriter::with_capacity(header.header_length() + 8);
+        header.write_to(&mut writer);
+        writer.write_bytes(&payload[..payload.len().min(8)]);
+        writer.finish()
+    }
+}
+
+impl ByteObject for IcmpMessage {
+    /// ICMPにはメッセージ長のフィールドがないため、ストリームの残りを全て読む
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let icmp_type = src.try_pop(8)?.to_u8();
+        let code = src.try_pop(8)?.to_u8();
+        let _checksum = src.try_pop(16)?.to_u16();
+        // どの種別でも Checksum の後ろに4バイトのフィールドがある
+        src.try_view(32)?;
+        let message = match icmp_type {
+            ICMP_ECHO_REPLY | ICMP_ECHO_REQUEST => {
+                let identifier = src.try_pop(16)?.to_u16();
+                let sequence = src.try_pop(16)?.to_u16();
+                let data = src.pop_remaining_bytes();
+                if icmp_type == ICMP_ECHO_REPLY {
+                    IcmpMessage::EchoReply {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                } else {
+                    IcmpMessage::EchoRequest {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                }
+            }
+            ICMP_DESTINATION_UNREACHABLE => {
+                let _unused = src.try_pop(16)?;
+                IcmpMessage::DestinationUnreachable {
+                    code,
+                    next_hop_mtu: src.try_pop(16)?.to_u16(),
+                    original: src.pop_remaining_bytes(),
+                }
+            }
+            ICMP_REDIRECT => IcmpMessage::Redirect {
+                code,
+                gateway: IPv4Address::from_stream(src)?,
+                original: src.pop_remaining_bytes(),
+            },
+            ICMP_TIME_EXCEEDED => {
+                let _unused = src.try_pop(32)?;
+                IcmpMessage::TimeExceeded {
+                    code,
+                    original: src.pop_remaining_bytes(),
+                }
+            }
+            ICMP_PARAMETER_PROBLEM => {
+                let pointer = src.try_pop(8)?.to_u8();
+                let _unused = src.try_pop(24)?;
+                IcmpMessage::ParameterProblem {
+                    code,
+                    pointer,
+                    original: src.pop_remaining_bytes(),
+                }
+            }
+            _ => IcmpMessage::Unknown {
+                icmp_type,
+                code,
+                data: src.pop_remaining_bytes(),
+            },
+        };
+        Ok(message)
+    }
+
+    /// チェックサムを計算して書き出す
+    fn write_to(&self, writer: &mut BitWriter) {
+        let mut message = BitWriter::new();
+        message.write_u8(self.icmp_type());
+        message.write_u8(self.code());
+        message.write_u16_be(0);
+        match self {
+            IcmpMessage::EchoReply {
+                identifier,
+                sequence,
+                data,
+            }
+            | IcmpMessage::EchoRequest {
+                identifier,
+                sequence,
+                data,
+            } => {
+                message.write_u16_be(*identifier);
+                message.write_u16_be(*sequence);
+                message.write_bytes(data);
+            }
+            IcmpMessage::DestinationUnreachable {
+                next_hop_mtu,
+                original,
+                ..
+            } => {
+                message.write_u16_be(0);
+                message.write_u16_be(*next_hop_mtu);
+                message.write_bytes(original);
+            }
+            IcmpMessage::Redirect {
+                gateway, original, ..
+            } => {
+                gateway.write_to(&mut message);
+                message.write_bytes(original);
+            }
+            IcmpMessage::TimeExceeded { original, .. } => {
+                message.write_u32_be(0);
+                message.write_bytes(original);
+            }
+            IcmpMessage::ParameterProblem {
+                pointer, original, ..
+            } => {
+                message.write_u8(*pointer);
+                message.write_bits(0, 24);
+                message.write_bytes(original);
+            }
+            IcmpMessage::Unknown { data, .. } => message.write_bytes(data),
+        }
+        let mut message = message.finish();
+        let icmp_checksum = checksum::checksum(&message);
+        message[2..4].copy_from_slice(&icmp_checksum.to_be_bytes());
+        writer.write_bytes(&message);
+    }
+}
+
+impl Display for IcmpMessage {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            IcmpMessage::EchoReply {
+                identifier,
+                sequence,
+                data,
+            } => write!(
+                f,
+                "ICMP Echo Reply {{ ID: {}, Seq: {}, Len: {} }}",
+                identifier,
+                sequence,
+                data.len()
+            ),
+            IcmpMessage::EchoRequest {
+                identifier,
+                sequence,
+                data,
+            } => write!(
+                f,
+                "ICMP Echo Request {{ ID: {}, Seq: {}, Len: {} }}",
+                identifier,
+                sequence,
+                data.len()
+            ),
+            IcmpMessage::DestinationUnreachable {
+                code, next_hop_mtu, ..
+            } => write!(
+                f,
+                "ICMP Destination Unreachable {{ Code: {}, MTU: {} }}",
+                code, next_hop_mtu
+            ),
+            IcmpMessage::Redirect { code, gateway, .. } => {
+                write!(f, "ICMP Redirect {{ Code: {}, Gateway: {} }}", code, gateway)
+            }
+            IcmpMessage::TimeExceeded { code, .. } => {
+                write!(f, "ICMP Time Exceeded {{ Code: {} }}", code)
+            }
+            IcmpMessage::ParameterProblem { code, pointer, .. } => write!(
+                f,
+                "ICMP Parameter Problem {{ Code: {}, Pointer: {} }}",
+                code, pointer
+            ),
+            IcmpMessage::Unknown {
+                icmp_type, code, ..
+            } => write!(f, "ICMP {{ Type: {}, Code: {} }}", icmp_type, code),
+        }
+    }
+}
+
+/// `Unknown` は既知の種別と重ならない Type（13以上）で、4バイト以上のデータを持つ
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IcmpMessage {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=6)? {
+            0 => IcmpMessage::EchoReply {
+                identifier: u.arbitrary()?,
+                sequence: u.arbitrary()?,
+                data: u.arbitrary()?,
+            },
+            1 => IcmpMessage::EchoRequest {
+                identifier: u.arbitrary()?,
+                sequence: u.arbitrary()?,
+                data: u.arbitrary()?,
+            },
+            2 => IcmpMessage::DestinationUnreachable {
+                code: u.arbitrary()?,
+                next_hop_mtu: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            3 => IcmpMessage::Redirect {
+                code: u.arbitrary()?,
+                gateway: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            4 => IcmpMessage::TimeExceeded {
+                code: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            5 => IcmpMessage::ParameterProblem {
+                code: u.arbitrary()?,
+                pointer: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            _ => {
+                let icmp_type = u.int_in_range(ICMP_PARAMETER_PROBLEM + 1..=u8::MAX)?;
+                let mut data: Vec<u8> = u.arbitrary()?;
+                data.resize(data.len().max(4), 0);
+                IcmpMessage::Unknown {
+                    icmp_type,
+                    code: u.arbitrary()?,
+                    data,
+                }
+            }
+        })
+    }
+}
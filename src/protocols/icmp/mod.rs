@@ -0,0 +1,4 @@
+pub mod echo;
+pub mod error;
+pub mod icmp_message;
+pub mod rate_limit;
@@ -0,0 +1,53 @@
+//! ICMPエラーの送信レートの制限（RFC 1812 4.3.2.8）。
+
+use std::time::{Duration, Instant};
+
+/// トークンバケットによるレート制限
+///
+/// `interval` ごとにトークンが1つ補充され、最大 `burst` 個まで貯まる。
+/// 送信のたびにトークンを1つ消費し、なければ送信しない。
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    burst: u32,
+    tokens: u32,
+    /// 最後にトークンを補充した時刻
+    refilled: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// 1秒あたり `per_second` 個、最大 `burst` 個まで続けて送れるレート制限を作る
+    ///
+    /// 補充の間隔は1ナノ秒より短くできないため、`per_second` が10億を超える場合は毎秒10億個になる。
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        RateLimiter {
+            interval: (Duration::from_secs(1) / per_second.max(1)).max(Duration::from_nanos(1)),
+            burst,
+            tokens: burst,
+            refilled: None,
+        }
+    }
+
+    /// 今送ってよければトークンを1つ消費して `true` を返す
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let refilled = *self.refilled.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(refilled);
+        let added = (elapsed.as_nanos() / self.interval.as_nanos()) as u64;
+        if added > 0 {
+            self.tokens = (self.tokens as u64 + added).min(self.burst as u64) as u32;
+            self.refilled = Some(refilled + self.interval * added.min(u32::MAX as u64) as u32);
+        }
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+impl Default for RateLimiter {
+    /// Linux の `icmp_msgs_per_sec` / `icmp_msgs_burst` の既定値に合わせる
+    fn default() -> Self {
+        RateLimiter::new(1000, 50)
+    }
+}
@@ -0,0 +1,555 @@
+use crate::protocols::icmpv6::ndp_option::NdpOption;
+use crate::protocols::ip::ip_address::IpAddress;
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_extension::{
+    IPV6_AUTHENTICATION, IPV6_DESTINATION_OPTIONS, IPV6_FRAGMENT, IPV6_HOP_BY_HOP, IPV6_ROUTING,
+};
+use crate::protocols::ip::ipv6_header::IPV6_HEADER_LENGTH;
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+use std::fmt::{Display, Formatter};
+
+/// ICMPv6 の Next Header の値
+pub const IPV6_NEXT_HEADER_ICMPV6: u8 = 58;
+
+/// ICMPv6の Type（RFC 4443, RFC 4861）
+pub const ICMPV6_DESTINATION_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_PARAMETER_PROBLEM: u8 = 4;
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+pub const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+pub const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+pub const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Destination Unreachable の Code
+pub const ICMPV6_NO_ROUTE: u8 = 0;
+pub const ICMPV6_ADDRESS_UNREACHABLE: u8 = 3;
+pub const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+
+/// エラーメッセージに入れる元のパケットの上限。最小MTU 1280 に収まるようにする（RFC 4443 2.4）
+pub const ICMPV6_ORIGINAL_PACKET_LIMIT: usize = 1280 - IPV6_HEADER_LENGTH - 8;
+
+/// Router Advertisement のフラグ
+pub const RA_FLAG_MANAGED: u8 = 0x80;
+pub const RA_FLAG_OTHER: u8 = 0x40;
+
+/// ICMPv6メッセージ
+///
+/// チェックサムはIPv6疑似ヘッダーを含むためフィールドとしては持たない。
+/// `ByteObject::write_to` はチェックサムを0のまま書き出すので、送信するときは `encode` を使い、
+/// 受信したメッセージは `verify` で生のバイト列に対して検証すること。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Icmpv6Message {
+    DestinationUnreachable {
+        code: u8,
+        original: Vec<u8>,
+    },
+    PacketTooBig {
+        mtu: u32,
+        original: Vec<u8>,
+    },
+    TimeExceeded {
+        code: u8,
+        original: Vec<u8>,
+    },
+    ParameterProblem {
+        code: u8,
+        /// 問題のあったバイトの、元のパケット先頭からの位置
+        pointer: u32,
+        original: Vec<u8>,
+    },
+    EchoRequest {
+        identifier: u16,
+        sequence: u16,
+        data: Vec<u8>,
+    },
+    EchoReply {
+        identifier: u16,
+        sequence: u16,
+        data: Vec<u8>,
+    },
+    RouterSolicitation {
+        options: Vec<NdpOption>,
+    },
+    RouterAdvertisement {
+        /// 0は未指定
+        hop_limit: u8,
+        /// `RA_FLAG_MANAGED` などのフラグ（下位ビットの予約領域を含む）
+        flags: u8,
+        /// 秒単位。0はデフォルトルーターではないことを表す
+        router_lifetime: u16,
+        /// ミリ秒単位
+        reachable_time: u32,
+        /// ミリ秒単位
+        retrans_timer: u32,
+        options: Vec<NdpOption>,
+    },
+    NeighborSolicitation {
+        target: IPv6Address,
+        options: Vec<NdpOption>,
+    },
+    NeighborAdvertisement {
+        router: bool,
+        solicited: bool,
+        override_flag: bool,
+        target: IPv6Address,
+        options: Vec<NdpOption>,
+    },
+    /// 解釈しない種別。Type と Code の後ろを全て `data` に入れる
+    Unknown {
+        icmp_type: u8,
+        code: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Icmpv6Message {
+    pub fn icmp_type(&self) -> u8 {
+        match self {
+            Icmpv6Message::DestinationUnreachable { .. } => ICMPV6_DESTINATION_UNREACHABLE,
+            Icmpv6Message::PacketTooBig { .. } => ICMPV6_PACKET_TOO_BIG,
+            Icmpv6Message::TimeExceeded { .. } => ICMPV6_TIME_EXCEEDED,
+            Icmpv6Message::ParameterProblem { .. } => ICMPV6_PARAMETER_PROBLEM,
+            Icmpv6Message::EchoRequest { .. } => ICMPV6_ECHO_REQUEST,
+            Icmpv6Message::EchoReply { .. } => ICMPV6_ECHO_REPLY,
+            Icmpv6Message::RouterSolicitation { .. } => ICMPV6_ROUTER_SOLICITATION,
+            Icmpv6Message::RouterAdvertisement { .. } => ICMPV6_ROUTER_ADVERTISEMENT,
+            Icmpv6Message::NeighborSolicitation { .. } => ICMPV6_NEIGHBOR_SOLICITATION,
+            Icmpv6Message::NeighborAdvertisement { .. } => ICMPV6_NEIGHBOR_ADVERTISEMENT,
+            Icmpv6Message::Unknown { icmp_type, .. } => *icmp_type,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Icmpv6Message::DestinationUnreachable { code, .. }
+            | Icmpv6Message::TimeExceeded { code, .. }
+            | Icmpv6Message::ParameterProblem { code, .. }
+            | Icmpv6Message::Unknown { code, .. } => *code,
+            _ => 0,
+        }
+    }
+
+    /// エラーメッセージかどうか（Type の最上位ビットが0）
+    pub fn is_error(&self) -> bool {
+        self.icmp_type() < 128
+    }
+
+    /// Neighbor Discovery のメッセージかどうか
+    pub fn is_neighbor_discovery(&self) -> bool {
+        matches!(
+            self,
+            Icmpv6Message::RouterSolicitation { .. }
+                | Icmpv6Message::RouterAdvertisement { .. }
+                | Icmpv6Message::NeighborSolicitation { .. }
+                | Icmpv6Message::NeighborAdvertisement { .. }
+        )
+    }
+
+    /// ND のオプション
+    pub fn options(&self) -> &[NdpOption] {
+        match self {
+            Icmpv6Message::RouterSolicitation { options }
+            | Icmpv6Message::RouterAdvertisement { options, .. }
+            | Icmpv6Message::NeighborSolicitation { options, .. }
+            | Icmpv6Message::NeighborAdvertisement { options, .. } => options,
+            _ => &[],
+        }
+    }
+
+    /// チェックサムを計算して書き出す
+    pub fn encode(&self, source: &IPv6Address, destination: &IPv6Address) -> Vec<u8> {
+        let mut writer = BitWriter::new();
+        self.write_to(&mut writer);
+        let mut message = writer.finish();
+        let checksum = checksum(&message, source, destination);
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+        message
+    }
+
+    /// エラーメッセージに入れる元のパケット。最小MTUに収まる範囲で切り詰める
+    pub fn original_packet(packet: &[u8]) -> Vec<u8> {
+        packet[..packet.len().min(ICMPV6_ORIGINAL_PACKET_LIMIT)].to_vec()
+    }
+
+    /// エラーメッセージが運ぶ元のパケット
+    pub fn original(&self) -> Option<&[u8]> {
+        match self {
+            Icmpv6Message::DestinationUnreachable { original, .. }
+            | Icmpv6Message::PacketTooBig { original, .. }
+            | Icmpv6Message::TimeExceeded { original, .. }
+            | Icmpv6Message::ParameterProblem { original, .. } => Some(original),
+            _ => None,
+        }
+    }
+}
+
+/// IPv6パケットの上位層がICMPv6エラーメッセージかどうか
+///
+/// エラーメッセージに対してエラーメッセージを返してはならない（RFC 4443 2.4(e.1)）。
+/// 拡張ヘッダーに誤りがあっても上位層を判定できるよう、長さだけを見てたどる。
+pub fn carries_error(packet: &[u8]) -> bool {
+    let Some(&first) = packet.get(6) else {
+        return false;
+    };
+    let mut next_header = first;
+    let mut pos = IPV6_HEADER_LENGTH;
+    loop {
+        let length = match next_header {
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DESTINATION_OPTIONS => {
+                packet.get(pos + 1).map(|&len| (len as usize + 1) * 8)
+            }
+            IPV6_AUTHENTICATION => packet.get(pos + 1).map(|&len| (len as usize + 2) * 4),
+            IPV6_FRAGMENT => {
+                // 先頭以外のフラグメントには上位層のヘッダーがない
+                let offset = packet.get(pos + 2..pos + 4);
+                match offset {
+                    Some(offset) if u16::from_be_bytes([offset[0], offset[1]]) >> 3 == 0 => Some(8),
+                    _ => return false,
+                }
+            }
+            IPV6_NEXT_HEADER_ICMPV6 => {
+                return packet.get(pos).is_some_and(|&icmp_type| icmp_type < 128);
+            }
+            // ESP の中身や他の上位層はエラーメッセージではない
+            _ => return false,
+        };
+        let Some(length) = length else {
+            return false;
+        };
+        next_header = packet[pos];
+        pos += length;
+    }
+}
+
+/// IPv6疑似ヘッダーを含めたチェックサムを計算する（RFC 4443 2.3）
+fn checksum(message: &[u8], source: &IPv6Address, destination: &IPv6Address) -> u16 {
+    let mut acc = IpAddress::pseudo_header(
+        &source.into(),
+        &destination.into(),
+        IPV6_NEXT_HEADER_ICMPV6,
+        message.len(),
+    );
+    acc.add_bytes(message);
+    acc.finish()
+}
+
+/// 受信したメッセージのチェックサムを検証する
+pub fn verify(message: &[u8], source: &IPv6Address, destination: &IPv6Address) -> bool {
+    checksum(message, source, destination) == 0
+}
+
+impl ByteObject for Icmpv6Message {
+    /// ICMPv6にはメッセージ長のフィールドがないため、ストリームの残りを全て読む
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let icmp_type = src.try_pop(8)?.to_u8();
+        let code = src.try_pop(8)?.to_u8();
+        let _checksum = src.try_pop(16)?.to_u16();
+        let message = match icmp_type {
+            ICMPV6_DESTINATION_UNREACHABLE | ICMPV6_TIME_EXCEEDED => {
+                let _unused = src.try_pop(32)?;
+                let original = src.pop_remaining_bytes();
+                if icmp_type == ICMPV6_DESTINATION_UNREACHABLE {
+                    Icmpv6Message::DestinationUnreachable { code, original }
+                } else {
+                    Icmpv6Message::TimeExceeded { code, original }
+                }
+            }
+            ICMPV6_PACKET_TOO_BIG => Icmpv6Message::PacketTooBig {
+                mtu: src.try_pop(32)?.to_u32(),
+                original: src.pop_remaining_bytes(),
+            },
+            ICMPV6_PARAMETER_PROBLEM => Icmpv6Message::ParameterProblem {
+                code,
+                pointer: src.try_pop(32)?.to_u32(),
+                original: src.pop_remaining_bytes(),
+            },
+            ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY => {
+                let identifier = src.try_pop(16)?.to_u16();
+                let sequence = src.try_pop(16)?.to_u16();
+                let data = src.pop_remaining_bytes();
+                if icmp_type == ICMPV6_ECHO_REQUEST {
+                    Icmpv6Message::EchoRequest {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                } else {
+                    Icmpv6Message::EchoReply {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                }
+            }
+            ICMPV6_ROUTER_SOLICITATION => {
+                let _reserved = src.try_pop(32)?;
+                Icmpv6Message::RouterSolicitation {
+                    options: NdpOption::parse_list(src)?,
+                }
+            }
+            ICMPV6_ROUTER_ADVERTISEMENT => Icmpv6Message::RouterAdvertisement {
+                hop_limit: src.try_pop(8)?.to_u8(),
+                flags: src.try_pop(8)?.to_u8(),
+                router_lifetime: src.try_pop(16)?.to_u16(),
+                reachable_time: src.try_pop(32)?.to_u32(),
+                retrans_timer: src.try_pop(32)?.to_u32(),
+                options: NdpOption::parse_list(src)?,
+            },
+            ICMPV6_NEIGHBOR_SOLICITATION => {
+                let _reserved = src.try_pop(32)?;
+                Icmpv6Message::NeighborSolicitation {
+                    target: IPv6Address::from_stream(src)?,
+                    options: NdpOption::parse_list(src)?,
+                }
+            }
+            ICMPV6_NEIGHBOR_ADVERTISEMENT => {
+                let router = src.try_pop(1)?.to_u8() == 1;
+                let solicited = src.try_pop(1)?.to_u8() == 1;
+                let override_flag = src.try_pop(1)?.to_u8() == 1;
+                let _reserved = src.try_pop(29)?;
+                Icmpv6Message::NeighborAdvertisement {
+                    router,
+                    solicited,
+                    override_flag,
+                    target: IPv6Address::from_stream(src)?,
+                    options: NdpOption::parse_list(src)?,
+                }
+            }
+            _ => Icmpv6Message::Unknown {
+                icmp_type,
+                code,
+                data: src.pop_remaining_bytes(),
+            },
+        };
+        Ok(message)
+    }
+
+    /// Checksum フィールドは0として書き出す。送信には `encode` を使うこと
+    fn write_to(&self, writer: &mut BitWriter) {
+        writer.write_u8(self.icmp_type());
+        writer.write_u8(self.code());
+        writer.write_u16_be(0);
+        match self {
+            Icmpv6Message::DestinationUnreachable { original, .. }
+            | Icmpv6Message::TimeExceeded { original, .. } => {
+                writer.write_u32_be(0);
+                writer.write_bytes(original);
+            }
+            Icmpv6Message::PacketTooBig { mtu, original } => {
+                writer.write_u32_be(*mtu);
+                writer.write_bytes(original);
+            }
+            Icmpv6Message::ParameterProblem {
+                pointer, original, ..
+            } => {
+                writer.write_u32_be(*pointer);
+                writer.write_bytes(original);
+            }
+            Icmpv6Message::EchoRequest {
+                identifier,
+                sequence,
+                data,
+            }
+            | Icmpv6Message::EchoReply {
+                identifier,
+                sequence,
+                data,
+            } => {
+                writer.write_u16_be(*identifier);
+                writer.write_u16_be(*sequence);
+                writer.write_bytes(data);
+            }
+            Icmpv6Message::RouterSolicitation { options } => {
+                writer.write_u32_be(0);
+                write_options(writer, options);
+            }
+            Icmpv6Message::RouterAdvertisement {
+                hop_limit,
+                flags,
+                router_lifetime,
+                reachable_time,
+                retrans_timer,
+                options,
+            } => {
+                writer.write_u8(*hop_limit);
+                writer.write_u8(*flags);
+                writer.write_u16_be(*router_lifetime);
+                writer.write_u32_be(*reachable_time);
+                writer.write_u32_be(*retrans_timer);
+                write_options(writer, options);
+            }
+            Icmpv6Message::NeighborSolicitation { target, options } => {
+                writer.write_u32_be(0);
+                target.write_to(writer);
+                write_options(writer, options);
+            }
+            Icmpv6Message::NeighborAdvertisement {
+                router,
+                solicited,
+                override_flag,
+                target,
+                options,
+            } => {
+                writer.write_bool(*router);
+                writer.write_bool(*solicited);
+                writer.write_bool(*override_flag);
+                writer.write_bits(0, 29);
+                target.write_to(writer);
+                write_options(writer, options);
+            }
+            Icmpv6Message::Unknown { data, .. } => writer.write_bytes(data),
+        }
+    }
+}
+
+fn write_options(writer: &mut BitWriter, options: &[NdpOption]) {
+    for option in options {
+        option.write_to(writer);
+    }
+}
+
+impl Display for Icmpv6Message {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Icmpv6Message::DestinationUnreachable { code, .. } => {
+                write!(f, "ICMPv6 Destination Unreachable {{ Code: {} }}", code)
+            }
+            Icmpv6Message::PacketTooBig { mtu, .. } => {
+                write!(f, "ICMPv6 Packet Too Big {{ MTU: {} }}", mtu)
+            }
+            Icmpv6Message::TimeExceeded { code, .. } => {
+                write!(f, "ICMPv6 Time Exceeded {{ Code: {} }}", code)
+            }
+            Icmpv6Message::ParameterProblem { code, pointer, .. } => write!(
+                f,
+                "ICMPv6 Parameter Problem {{ Code: {}, Pointer: {} }}",
+                code, pointer
+            ),
+            Icmpv6Message::EchoRequest {
+                identifier,
+                sequence,
+                data,
+            } => write!(
+                f,
+                "ICMPv6 Echo Request {{ ID: {}, Seq: {}, Len: {} }}",
+                identifier,
+                sequence,
+                data.len()
+            ),
+            Icmpv6Message::EchoReply {
+                identifier,
+                sequence,
+                data,
+            } => write!(
+                f,
+                "ICMPv6 Echo Reply {{ ID: {}, Seq: {}, Len: {} }}",
+                identifier,
+                sequence,
+                data.len()
+            ),
+            Icmpv6Message::RouterSolicitation { options } => write!(
+                f,
+                "ICMPv6 Router Solicitation {{ Options: {} }}",
+                options.len()
+            ),
+            Icmpv6Message::RouterAdvertisement {
+                hop_limit,
+                router_lifetime,
+                options,
+                ..
+            } => write!(
+                f,
+                "ICMPv6 Router Advertisement {{ Hop Limit: {}, Lifetime: {}, Options: {} }}",
+                hop_limit,
+                router_lifetime,
+                options.len()
+            ),
+            Icmpv6Message::NeighborSolicitation { target, .. } => {
+                write!(f, "ICMPv6 Neighbor Solicitation {{ Target: {} }}", target)
+            }
+            Icmpv6Message::NeighborAdvertisement {
+                target, solicited, ..
+            } => write!(
+                f,
+                "ICMPv6 Neighbor Advertisement {{ Target: {}, Solicited: {} }}",
+                target, solicited
+            ),
+            Icmpv6Message::Unknown {
+                icmp_type, code, ..
+            } => write!(f, "ICMPv6 {{ Type: {}, Code: {} }}", icmp_type, code),
+        }
+    }
+}
+
+/// `Unknown` は既知の種別と重ならない Type で生成する
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Icmpv6Message {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let options = |u: &mut arbitrary::Unstructured<'a>| -> arbitrary::Result<Vec<NdpOption>> {
+            let len = u.int_in_range(0..=3)?;
+            (0..len).map(|_| u.arbitrary()).collect()
+        };
+        Ok(match u.int_in_range(0..=10)? {
+            0 => Icmpv6Message::DestinationUnreachable {
+                code: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            1 => Icmpv6Message::PacketTooBig {
+                mtu: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            2 => Icmpv6Message::TimeExceeded {
+                code: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            3 => Icmpv6Message::ParameterProblem {
+                code: u.arbitrary()?,
+                pointer: u.arbitrary()?,
+                original: u.arbitrary()?,
+            },
+            4 => Icmpv6Message::EchoRequest {
+                identifier: u.arbitrary()?,
+                sequence: u.arbitrary()?,
+                data: u.arbitrary()?,
+            },
+            5 => Icmpv6Message::EchoReply {
+                identifier: u.arbitrary()?,
+                sequence: u.arbitrary()?,
+                data: u.arbitrary()?,
+            },
+            6 => Icmpv6Message::RouterSolicitation {
+                options: options(u)?,
+            },
+            7 => Icmpv6Message::RouterAdvertisement {
+                hop_limit: u.arbitrary()?,
+                flags: u.arbitrary()?,
+                router_lifetime: u.arbitrary()?,
+                reachable_time: u.arbitrary()?,
+                retrans_timer: u.arbitrary()?,
+                options: options(u)?,
+            },
+            8 => Icmpv6Message::NeighborSolicitation {
+                target: u.arbitrary()?,
+                options: options(u)?,
+            },
+            9 => Icmpv6Message::NeighborAdvertisement {
+                router: u.arbitrary()?,
+                solicited: u.arbitrary()?,
+                override_flag: u.arbitrary()?,
+                target: u.arbitrary()?,
+                options: options(u)?,
+            },
+            _ => Icmpv6Message::Unknown {
+                icmp_type: u.int_in_range(ICMPV6_NEIGHBOR_ADVERTISEMENT + 1..=u8::MAX)?,
+                code: u.arbitrary()?,
+                data: u.arbitrary()?,
+            },
+        })
+    }
+}
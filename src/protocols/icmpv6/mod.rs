@@ -0,0 +1,4 @@
+pub mod icmpv6_message;
+pub mod ndp_option;
+pub mod neighbor_discovery;
+pub mod slaac;
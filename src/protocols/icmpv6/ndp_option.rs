@@ -0,0 +1,189 @@
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+
+/// Neighbor Discovery のオプションの種別（RFC 4861 4.6）
+pub const NDP_OPTION_SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+pub const NDP_OPTION_TARGET_LINK_LAYER_ADDRESS: u8 = 2;
+pub const NDP_OPTION_PREFIX_INFORMATION: u8 = 3;
+pub const NDP_OPTION_MTU: u8 = 5;
+
+/// 寿命が無限であることを表す値
+pub const NDP_INFINITE_LIFETIME: u32 = u32::MAX;
+
+/// Neighbor Discovery のオプション
+///
+/// 長さは種別と長さのフィールドを含めて8バイト単位で、書き出すときは0で埋める。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NdpOption {
+    /// 送信元のリンク層アドレス。8バイト境界までの埋め草を含む
+    SourceLinkLayerAddress(Vec<u8>),
+    /// 対象のリンク層アドレス。8バイト境界までの埋め草を含む
+    TargetLinkLayerAddress(Vec<u8>),
+    PrefixInformation {
+        prefix_len: u8,
+        /// L フラグ。プレフィックスがリンク上にある
+        on_link: bool,
+        /// A フラグ。アドレスの自動設定に使ってよい
+        autonomous: bool,
+        /// 秒単位。`NDP_INFINITE_LIFETIME` は無限
+        valid_lifetime: u32,
+        preferred_lifetime: u32,
+        prefix: IPv6Address,
+    },
+    Mtu(u32),
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl NdpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            NdpOption::SourceLinkLayerAddress(_) => NDP_OPTION_SOURCE_LINK_LAYER_ADDRESS,
+            NdpOption::TargetLinkLayerAddress(_) => NDP_OPTION_TARGET_LINK_LAYER_ADDRESS,
+            NdpOption::PrefixInformation { .. } => NDP_OPTION_PREFIX_INFORMATION,
+            NdpOption::Mtu(_) => NDP_OPTION_MTU,
+            NdpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// 書き出したときのバイト長（8の倍数）
+    pub fn encoded_len(&self) -> usize {
+        let body = match self {
+            NdpOption::SourceLinkLayerAddress(address) | NdpOption::TargetLinkLayerAddress(address) => {
+                address.len()
+            }
+            NdpOption::PrefixInformation { .. } => 30,
+            NdpOption::Mtu(_) => 6,
+            NdpOption::Unknown { data, .. } => data.len(),
+        };
+        (2 + body).div_ceil(8) * 8
+    }
+
+    /// ストリームの残りをオプションの並びとして読む
+    pub fn parse_list(src: &mut BitStream) -> Result<Vec<NdpOption>, ParseError> {
+        let mut options = Vec::new();
+        while src.remaining > 0 {
+            options.push(NdpOption::from_stream(src)?);
+        }
+        Ok(options)
+    }
+}
+
+impl ByteObject for NdpOption {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let kind = src.try_pop(8)?.to_u8();
+        let length = src.try_pop(8)?.to_u8();
+        // 長さ0のオプションは破棄しなければならない（RFC 4861 4.6）
+        if length == 0 {
+            return Err(ParseError::BadOptionLength { kind, length });
+        }
+        let mut body = src.sub_stream((length as usize * 8 - 2) * 8)?;
+        let option = match kind {
+            NDP_OPTION_SOURCE_LINK_LAYER_ADDRESS => {
+                NdpOption::SourceLinkLayerAddress(body.read_remaining_bytes())
+            }
+            NDP_OPTION_TARGET_LINK_LAYER_ADDRESS => {
+                NdpOption::TargetLinkLayerAddress(body.read_remaining_bytes())
+            }
+            NDP_OPTION_PREFIX_INFORMATION if length == 4 => {
+                let prefix_len = body.try_pop(8)?.to_u8();
+                let on_link = body.try_pop(1)?.to_u8() == 1;
+                let autonomous = body.try_pop(1)?.to_u8() == 1;
+                let _reserved1 = body.try_pop(6)?;
+                let valid_lifetime = body.try_pop(32)?.to_u32();
+                let preferred_lifetime = body.try_pop(32)?.to_u32();
+                let _reserved2 = body.try_pop(32)?;
+                NdpOption::PrefixInformation {
+                    prefix_len,
+                    on_link,
+                    autonomous,
+                    valid_lifetime,
+                    preferred_lifetime,
+                    prefix: IPv6Address::from_stream(&mut body)?,
+                }
+            }
+            NDP_OPTION_MTU if length == 1 => {
+                let _reserved = body.try_pop(16)?;
+                NdpOption::Mtu(body.try_pop(32)?.to_u32())
+            }
+            NDP_OPTION_PREFIX_INFORMATION | NDP_OPTION_MTU => {
+                return Err(ParseError::BadOptionLength { kind, length });
+            }
+            _ => NdpOption::Unknown {
+                kind,
+                data: body.read_remaining_bytes(),
+            },
+        };
+        Ok(option)
+    }
+
+    fn write_to(&self, writer: &mut BitWriter) {
+        let start = writer.byte_len();
+        let len = self.encoded_len();
+        writer.write_u8(self.kind());
+        writer.write_u8((len / 8) as u8);
+        match self {
+            NdpOption::SourceLinkLayerAddress(address) | NdpOption::TargetLinkLayerAddress(address) => {
+                writer.write_bytes(address);
+            }
+            NdpOption::PrefixInformation {
+                prefix_len,
+                on_link,
+                autonomous,
+                valid_lifetime,
+                preferred_lifetime,
+                prefix,
+            } => {
+                writer.write_u8(*prefix_len);
+                writer.write_bool(*on_link);
+                writer.write_bool(*autonomous);
+                writer.write_bits(0, 6);
+                writer.write_u32_be(*valid_lifetime);
+                writer.write_u32_be(*preferred_lifetime);
+                writer.write_u32_be(0);
+                prefix.write_to(writer);
+            }
+            NdpOption::Mtu(mtu) => {
+                writer.write_u16_be(0);
+                writer.write_u32_be(*mtu);
+            }
+            NdpOption::Unknown { data, .. } => writer.write_bytes(data),
+        }
+        while writer.byte_len() - start < len {
+            writer.write_u8(0);
+        }
+    }
+}
+
+/// リンク層アドレスと `Unknown` のデータは、埋め草なしで8バイト境界に揃う長さで生成する
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for NdpOption {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let padded = |u: &mut arbitrary::Unstructured<'a>| -> arbitrary::Result<Vec<u8>> {
+            let len = u.int_in_range(0..=3)? * 8 + 6;
+            (0..len).map(|_| u.arbitrary()).collect()
+        };
+        Ok(match u.int_in_range(0..=4)? {
+            0 => NdpOption::SourceLinkLayerAddress(padded(u)?),
+            1 => NdpOption::TargetLinkLayerAddress(padded(u)?),
+            2 => NdpOption::PrefixInformation {
+                prefix_len: u.int_in_range(0..=128)?,
+                on_link: u.arbitrary()?,
+                autonomous: u.arbitrary()?,
+                valid_lifetime: u.arbitrary()?,
+                preferred_lifetime: u.arbitrary()?,
+                prefix: u.arbitrary()?,
+            },
+            3 => NdpOption::Mtu(u.arbitrary()?),
+            _ => NdpOption::Unknown {
+                kind: u.int_in_range(NDP_OPTION_MTU + 1..=u8::MAX)?,
+                data: padded(u)?,
+            },
+        })
+    }
+}
@@ -0,0 +1,83 @@
+//! Neighbor Discovery（RFC 4861）の受信時の検証と応答。
+
+use crate::protocols::icmpv6::icmpv6_message::Icmpv6Message;
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_header::IPv6Header;
+
+/// ND メッセージの Hop Limit。ルーターを越えてきたメッセージを弾くため、送受信とも255に固定する
+pub const ND_HOP_LIMIT: u8 = 255;
+
+/// 全ノードマルチキャストアドレス（ff02::1）
+pub const ALL_NODES: IPv6Address =
+    IPv6Address::from_octets([0xFF, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+/// 全ルーターマルチキャストアドレス（ff02::2）
+pub const ALL_ROUTERS: IPv6Address =
+    IPv6Address::from_octets([0xFF, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+/// `address` の要請ノードマルチキャストアドレス（ff02::1:ffXX:XXXX、RFC 4291 2.7.1）
+pub fn solicited_node_multicast(address: &IPv6Address) -> IPv6Address {
+    let octets = address.octets();
+    IPv6Address::from_octets([
+        0xFF, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xFF, octets[13], octets[14], octets[15],
+    ])
+}
+
+/// 受信した ND メッセージを受け入れてよいかどうか（RFC 4861 6.1, 7.1）
+///
+/// Hop Limit が255であること、種別ごとの送信元・宛先の制約を満たすことを確かめる。
+pub fn is_valid(header: &IPv6Header, message: &Icmpv6Message) -> bool {
+    if header.hop_limit != ND_HOP_LIMIT {
+        return false;
+    }
+    let source = header.source_address;
+    match message {
+        // ルーターはリンクローカルアドレスから広告する
+        Icmpv6Message::RouterAdvertisement { .. } => source.is_link_local(),
+        // 重複アドレス検出（送信元が未指定）の場合は要請ノードマルチキャスト宛てに限る
+        Icmpv6Message::NeighborSolicitation { target, .. } => {
+            !target.is_multicast()
+                && (!source.is_unspecified()
+                    || header.destination_address == solicited_node_multicast(target))
+        }
+        Icmpv6Message::NeighborAdvertisement {
+            target, solicited, ..
+        } => {
+            // 要請への応答はユニキャストで返される
+            !target.is_multicast() && (!*solicited || !header.destination_address.is_multicast())
+        }
+        Icmpv6Message::RouterSolicitation { .. } => true,
+        _ => false,
+    }
+}
+
+/// 自分のアドレスを対象とする Neighbor Solicitation に応える Neighbor Advertisement を作る
+///
+/// 返り値は (宛先, メッセージ)。送信元が未指定（重複アドレス検出）の場合は全ノード宛てに、
+/// Solicited フラグを立てずに返す（RFC 4861 7.2.4）。
+pub fn advertise(
+    header: &IPv6Header,
+    solicitation: &Icmpv6Message,
+    is_local: impl Fn(&IPv6Address) -> bool,
+) -> Option<(IPv6Address, Icmpv6Message)> {
+    let Icmpv6Message::NeighborSolicitation { target, .. } = solicitation else {
+        return None;
+    };
+    if !is_local(target) {
+        return None;
+    }
+    let duplicate_detection = header.source_address.is_unspecified();
+    let destination = if duplicate_detection {
+        ALL_NODES
+    } else {
+        header.source_address
+    };
+    // リンク層アドレスを持たないため Target Link-Layer Address オプションは付けない
+    let advertisement = Icmpv6Message::NeighborAdvertisement {
+        router: false,
+        solicited: !duplicate_detection,
+        override_flag: true,
+        target: *target,
+        options: Vec::new(),
+    };
+    Some((destination, advertisement))
+}
@@ -0,0 +1,162 @@
+//! ステートレスアドレス自動設定（SLAAC、RFC 4862）。
+//!
+//! Router Advertisement の Prefix Information から /64 のアドレスを作る。
+//! インターフェースIDはリンク層アドレスを持たないため、起動ごとの秘密値とプレフィックスの
+//! ハッシュから決める（RFC 7217 と同じ考え方）。重複アドレス検出は行わない。
+
+use crate::protocols::icmpv6::icmpv6_message::Icmpv6Message;
+use crate::protocols::icmpv6::ndp_option::{NDP_INFINITE_LIFETIME, NdpOption};
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_cidr::Ipv6Cidr;
+use std::hash::{BuildHasher, RandomState};
+use std::time::{Duration, Instant};
+
+/// 自動設定できるプレフィックス長
+const SLAAC_PREFIX_LEN: u8 = 64;
+/// 既存アドレスの有効期間を短くする広告を受け入れる下限（RFC 4862 5.5.3 e）
+const TWO_HOURS: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// 自動設定したアドレス
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlaacAddress {
+    pub cidr: Ipv6Cidr,
+    /// `None` は無期限
+    pub valid_until: Option<Instant>,
+    pub preferred_until: Option<Instant>,
+}
+
+impl SlaacAddress {
+    /// 推奨期間を過ぎて非推奨になっているかどうか
+    pub fn is_deprecated(&self, now: Instant) -> bool {
+        self.preferred_until.is_some_and(|until| now >= until)
+    }
+}
+
+pub struct Slaac {
+    secret: RandomState,
+    link_local: IPv6Address,
+    addresses: Vec<SlaacAddress>,
+}
+
+impl Slaac {
+    /// fe80::/64 のリンクローカルアドレスを持った状態で始める
+    pub fn new() -> Self {
+        let secret = RandomState::new();
+        let link_local_prefix = IPv6Address::from_octets([0xFE, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let link_local = IPv6Address::from_u128(
+            link_local_prefix.to_u128() | secret.hash_one(link_local_prefix) as u128,
+        );
+        Slaac {
+            secret,
+            link_local,
+            addresses: Vec::new(),
+        }
+    }
+
+    pub fn link_local(&self) -> IPv6Address {
+        self.link_local
+    }
+
+    /// 自動設定したアドレス（リンクローカルアドレスを含まない）
+    pub fn addresses(&self) -> &[SlaacAddress] {
+        &self.addresses
+    }
+
+    /// 自分のアドレスかどうか
+    pub fn is_local(&self, address: &IPv6Address) -> bool {
+        *address == self.link_local || self.addresses.iter().any(|a| a.cidr.address() == *address)
+    }
+
+    /// Router Advertisement を処理し、新しく設定したアドレスを返す（RFC 4862 5.5.3）
+    pub fn process_router_advertisement(
+        &mut self,
+        advertisement: &Icmpv6Message,
+        now: Instant,
+    ) -> Vec<IPv6Address> {
+        let mut configured = Vec::new();
+        for option in advertisement.options() {
+            let NdpOption::PrefixInformation {
+                prefix_len,
+                autonomous: true,
+                valid_lifetime,
+                preferred_lifetime,
+                prefix,
+                ..
+            } = option
+            else {
+                continue;
+            };
+            if *prefix_len != SLAAC_PREFIX_LEN
+                || prefix.is_link_local()
+                || preferred_lifetime > valid_lifetime
+            {
+                continue;
+            }
+            let Some(prefix) = Ipv6Cidr::new(*prefix, SLAAC_PREFIX_LEN) else {
+                continue;
+            };
+            let network = prefix.network();
+            let preferred_until = deadline(now, *preferred_lifetime);
+            let received_valid = deadline(now, *valid_lifetime);
+
+            if let Some(existing) = self
+                .addresses
+                .iter_mut()
+                .find(|address| address.cidr.network() == network)
+            {
+                existing.preferred_until = preferred_until;
+                // 偽の広告で有効期間を縮められないよう、2時間を下回る短縮は受け付けない
+                let remaining = existing
+                    .valid_until
+                    .map(|until| until.saturating_duration_since(now));
+                let received = received_valid.map(|until| until - now);
+                existing.valid_until = match (received, remaining) {
+                    (None, _) => None,
+                    (Some(received), Some(remaining))
+                        if received > TWO_HOURS || received > remaining =>
+                    {
+                        received_valid
+                    }
+                    (Some(_), Some(remaining)) if remaining <= TWO_HOURS => existing.valid_until,
+                    (Some(received), None) if received > TWO_HOURS => received_valid,
+                    _ => Some(now + TWO_HOURS),
+                };
+                continue;
+            }
+            if *valid_lifetime == 0 {
+                continue;
+            }
+            let address = IPv6Address::from_u128(
+                network.to_u128() | self.secret.hash_one(network) as u128,
+            );
+            self.addresses.push(SlaacAddress {
+                cidr: Ipv6Cidr::new(address, SLAAC_PREFIX_LEN).expect("64 is a valid prefix length"),
+                valid_until: received_valid,
+                preferred_until,
+            });
+            configured.push(address);
+        }
+        configured
+    }
+
+    /// 有効期間の切れたアドレスを取り除いて返す
+    pub fn expire(&mut self, now: Instant) -> Vec<SlaacAddress> {
+        let (expired, kept) = self
+            .addresses
+            .drain(..)
+            .partition(|address| address.valid_until.is_some_and(|until| now >= until));
+        self.addresses = kept;
+        expired
+    }
+}
+
+impl Default for Slaac {
+    fn default() -> Self {
+        Slaac::new()
+    }
+}
+
+/// 秒単位の寿命から期限を求める。`NDP_INFINITE_LIFETIME` は無期限
+fn deadline(now: Instant, lifetime: u32) -> Option<Instant> {
+    (lifetime != NDP_INFINITE_LIFETIME).then(|| now + Duration::from_secs(lifetime as u64))
+}
@@ -0,0 +1,133 @@
+//! 送信時のIPv4フラグメント化と Identification の採番。
+
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv4_header::{IPV4_FLAG_MF, IPv4Header};
+use crate::protocols::ip::ipv4_option::IPv4Option;
+use crate::types::bit_stream::BitWriter;
+use crate::types::byte_object::ByteObject;
+use std::fmt::{Display, Formatter};
+use std::hash::{BuildHasher, RandomState};
+
+/// 全てのフラグメントにコピーするオプションかどうか（種別の最上位ビット）
+const IPV4_OPTION_COPIED: u8 = 0x80;
+
+/// フラグメント化できなかった理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// Don't Fragment が立っているが、MTUを超えている
+    DontFragment { mtu: usize, length: usize },
+    /// ヘッダーだけでMTUを使い切ってしまい、8バイトのデータも載せられない
+    MtuTooSmall { mtu: usize },
+}
+
+impl Display for FragmentError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            FragmentError::DontFragment { mtu, length } => write!(
+                f,
+                "{} byte datagram exceeds MTU {} with Don't Fragment set",
+                length, mtu
+            ),
+            FragmentError::MtuTooSmall { mtu } => write!(f, "MTU {} is too small", mtu),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+/// `header` と `payload` からなるデータグラムを、MTUに収まるパケットに分割して書き出す
+///
+/// `header` の `total_length` とチェックサムは各フラグメントに合わせて計算し直す。
+/// 2つ目以降のフラグメントには、コピーフラグの立ったオプションだけを載せる（RFC 791）。
+pub fn fragment(
+    header: &IPv4Header,
+    payload: &[u8],
+    mtu: usize,
+) -> Result<Vec<Vec<u8>>, FragmentError> {
+    let length = header.header_length() + payload.len();
+    if length <= mtu {
+        return Ok(vec![packet(header.clone(), payload)]);
+    }
+    if header.dont_fragment() {
+        return Err(FragmentError::DontFragment { mtu, length });
+    }
+    if payload.is_empty() {
+        return Err(FragmentError::MtuTooSmall { mtu });
+    }
+
+    let mut rest = header.clone();
+    rest.options = header
+        .options
+        .iter()
+        .filter(|option| option.kind() & IPV4_OPTION_COPIED != 0)
+        .cloned()
+        .collect::<Vec<IPv4Option>>();
+
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while offset < payload.len() {
+        let mut fragment = if offset == 0 { header.clone() } else { rest.clone() };
+        // 最後以外のフラグメントのデータ長は8バイトの倍数にする
+        let room = mtu.saturating_sub(fragment.header_length()) / 8 * 8;
+        if room == 0 {
+            return Err(FragmentError::MtuTooSmall { mtu });
+        }
+        let end = (offset + room).min(payload.len());
+        fragment.fragment_offset = header.fragment_offset + (offset / 8) as u16;
+        if end < payload.len() {
+            fragment.flags |= IPV4_FLAG_MF;
+        }
+        packets.push(packet(fragment, &payload[offset..end]));
+        offset = end;
+    }
+    Ok(packets)
+}
+
+fn packet(mut header: IPv4Header, payload: &[u8]) -> Vec<u8> {
+    header.total_length = (header.header_length() + payload.len()) as u16;
+    header.update_checksum();
+    let mut writer = BitWriter::with_capacity(header.total_length as usize);
+    header.write_to(&mut writer);
+    writer.write_bytes(payload);
+    writer.finish()
+}
+
+/// Identification のカウンターの数
+const IDENTIFICATION_BUCKETS: usize = 4096;
+
+/// 宛先ごとの Identification の採番
+///
+/// 宛先のハッシュで選んだバケットのカウンターに、宛先ごとのオフセットを足して決める（RFC 7739 5.3）。
+/// カウンターの数は固定なので、宛先が増えてもメモリは増えない。
+pub struct IdentificationGenerator {
+    /// バケットを選ぶための秘密値
+    index_secret: RandomState,
+    /// オフセットを決めるための秘密値
+    offset_secret: RandomState,
+    counters: Box<[u16; IDENTIFICATION_BUCKETS]>,
+}
+
+impl IdentificationGenerator {
+    pub fn new() -> Self {
+        IdentificationGenerator {
+            index_secret: RandomState::new(),
+            offset_secret: RandomState::new(),
+            counters: Box::new([0; IDENTIFICATION_BUCKETS]),
+        }
+    }
+
+    /// `destination` 宛ての次の Identification
+    pub fn next(&mut self, destination: IPv4Address) -> u16 {
+        let index = self.index_secret.hash_one(destination) as usize % IDENTIFICATION_BUCKETS;
+        let offset = self.offset_secret.hash_one(destination) as u16;
+        let counter = &mut self.counters[index];
+        *counter = counter.wrapping_add(1);
+        offset.wrapping_add(*counter)
+    }
+}
+
+impl Default for IdentificationGenerator {
+    fn default() -> Self {
+        IdentificationGenerator::new()
+    }
+}
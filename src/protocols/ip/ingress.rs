@@ -0,0 +1,136 @@
+//! 受信したIPv4データグラムの検証（RFC 1122 3.2.1）。
+//!
+//! ヘッダーの形式、全長、チェックサム、TTL、送信元・宛先アドレスを検査し、
+//! 問題があれば破棄の理由を返す。カウンターの更新は `IpStats::record_drop` で行う。
+
+use crate::protocols::checksum;
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use crate::protocols::ip::ipv4_header::IPv4Header;
+use crate::types::bit_stream::BitStream;
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+use bytes::Bytes;
+use std::fmt::{Display, Formatter};
+
+/// 受信したデータグラムを破棄した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// ヘッダー、または全長フィールドの示す長さに対してデータが足りない
+    Truncated,
+    /// バージョンが4でない
+    BadVersion,
+    /// IHLが5未満
+    BadHeaderLength,
+    /// 全長フィールドがヘッダー長より短い
+    BadTotalLength,
+    /// オプションの形式が不正
+    BadOptions,
+    /// ヘッダーチェックサムが一致しない
+    BadChecksum,
+    /// TTLが0
+    TtlExpired,
+    /// 送信元がブロードキャスト・マルチキャスト・ループバック・0.0.0.0 など、送信元になり得ないアドレス
+    BadSourceAddress,
+    /// 宛先がローカルネットワークのネットワークアドレス、またはそのブロードキャストアドレス
+    BadDestinationAddress,
+    /// 宛先が自分のアドレスでない
+    NotForUs,
+}
+
+impl Display for DropReason {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let text = match self {
+            DropReason::Truncated => "truncated datagram",
+            DropReason::BadVersion => "bad version",
+            DropReason::BadHeaderLength => "bad header length",
+            DropReason::BadTotalLength => "bad total length",
+            DropReason::BadOptions => "malformed options",
+            DropReason::BadChecksum => "bad header checksum",
+            DropReason::TtlExpired => "TTL is zero",
+            DropReason::BadSourceAddress => "invalid source address",
+            DropReason::BadDestinationAddress => "destination is a network or broadcast address",
+            DropReason::NotForUs => "destination is not a local address",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl From<ParseError> for DropReason {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Truncated { .. } => DropReason::Truncated,
+            ParseError::InvalidVersion { .. } => DropReason::BadVersion,
+            ParseError::BadHeaderLength(_) => DropReason::BadHeaderLength,
+            ParseError::BadTotalLength { .. } => DropReason::BadTotalLength,
+            ParseError::BadOptionLength { .. } => DropReason::BadOptions,
+        }
+    }
+}
+
+/// 自分宛てとして受け入れるアドレスの設定と検証
+pub struct Ipv4Ingress {
+    /// 自分のアドレスのネットワーク。ネットワーク内の全アドレスを自分宛てとして扱う
+    local: Vec<Ipv4Cidr>,
+}
+
+impl Ipv4Ingress {
+    pub fn new(local: Vec<Ipv4Cidr>) -> Self {
+        Ipv4Ingress { local }
+    }
+
+    /// 自分宛て（ローカルアドレス、またはリミテッドブロードキャスト）かどうか
+    ///
+    /// ローカルネットワークのネットワークアドレスとブロードキャストアドレスはホストのアドレスではないため含めない。
+    pub fn is_local(&self, address: &IPv4Address) -> bool {
+        address.is_broadcast()
+            || self.local.iter().any(|network| {
+                network.contains(address)
+                    && !network.is_network(address)
+                    && !network.is_broadcast(address)
+            })
+    }
+
+    /// データグラムを検証し、ヘッダーと全長までに限定したペイロードを返す
+    pub fn validate(&self, frame: &[u8]) -> Result<(IPv4Header, BitStream), DropReason> {
+        let mut stream = BitStream::from_bytes(Bytes::copy_from_slice(frame));
+        let header = IPv4Header::from_stream(&mut stream)?;
+        if header.total_length as usize > frame.len() {
+            return Err(DropReason::Truncated);
+        }
+        // 受信したバイト列のまま検証する
+        if !checksum::verify(&frame[..header.header_length()]) {
+            return Err(DropReason::BadChecksum);
+        }
+        // RFC 1122 により TTL が2未満というだけで破棄してはならないが、0で送ることは禁止されている
+        if header.ttl == 0 {
+            return Err(DropReason::TtlExpired);
+        }
+        // ループバックと 0.0.0.0 は外から届くことのないマーシャンアドレス（RFC 1122 3.2.1.3）
+        let source = header.source_address;
+        if source.is_broadcast()
+            || source.is_multicast()
+            || source.is_loopback()
+            || source.is_unspecified()
+            || self.local.iter().any(|network| network.is_broadcast(&source))
+        {
+            return Err(DropReason::BadSourceAddress);
+        }
+        let destination = header.destination_address;
+        if self
+            .local
+            .iter()
+            .any(|network| network.is_network(&destination) || network.is_broadcast(&destination))
+        {
+            return Err(DropReason::BadDestinationAddress);
+        }
+        if !self.is_local(&destination) {
+            return Err(DropReason::NotForUs);
+        }
+
+        // 全長より後ろはリンク層のパディングなので含めない
+        stream.seek(header.header_length() * 8)?;
+        let payload = stream.sub_stream(header.payload_length() * 8)?;
+        Ok((header, payload))
+    }
+}
@@ -0,0 +1,108 @@
+use crate::protocols::checksum::{self, InternetChecksum};
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+/// IPv4またはIPv6のアドレス
+///
+/// TCPなど両方のバージョンの上で動く上位層が、疑似ヘッダーの計算などで使う。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IpAddress {
+    V4(IPv4Address),
+    V6(IPv6Address),
+}
+
+impl IpAddress {
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpAddress::V4(_))
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, IpAddress::V6(_))
+    }
+
+    /// 上位層のチェックサムに使う疑似ヘッダーを加算したアキュムレータを返す
+    ///
+    /// 送信元と宛先のバージョンが異なる場合はパニックする。
+    pub fn pseudo_header(
+        source: &IpAddress,
+        destination: &IpAddress,
+        protocol: u8,
+        length: usize,
+    ) -> InternetChecksum {
+        match (source, destination) {
+            (IpAddress::V4(source), IpAddress::V4(destination)) => checksum::ipv4_pseudo_header(
+                source.octets(),
+                destination.octets(),
+                protocol,
+                length as u16,
+            ),
+            (IpAddress::V6(source), IpAddress::V6(destination)) => checksum::ipv6_pseudo_header(
+                source.octets(),
+                destination.octets(),
+                protocol,
+                length as u32,
+            ),
+            _ => panic!("source and destination address families differ"),
+        }
+    }
+}
+
+impl From<IPv4Address> for IpAddress {
+    fn from(address: IPv4Address) -> Self {
+        IpAddress::V4(address)
+    }
+}
+
+impl From<IPv6Address> for IpAddress {
+    fn from(address: IPv6Address) -> Self {
+        IpAddress::V6(address)
+    }
+}
+
+impl From<&IPv4Address> for IpAddress {
+    fn from(address: &IPv4Address) -> Self {
+        IpAddress::V4(*address)
+    }
+}
+
+impl From<&IPv6Address> for IpAddress {
+    fn from(address: &IPv6Address) -> Self {
+        IpAddress::V6(*address)
+    }
+}
+
+impl From<&IpAddress> for IpAddress {
+    fn from(address: &IpAddress) -> Self {
+        *address
+    }
+}
+
+impl From<IpAddr> for IpAddress {
+    fn from(address: IpAddr) -> Self {
+        match address {
+            IpAddr::V4(address) => IpAddress::V4(address.into()),
+            IpAddr::V6(address) => IpAddress::V6(address.into()),
+        }
+    }
+}
+
+impl From<IpAddress> for IpAddr {
+    fn from(address: IpAddress) -> Self {
+        match address {
+            IpAddress::V4(address) => IpAddr::V4(address.into()),
+            IpAddress::V6(address) => IpAddr::V6(address.into()),
+        }
+    }
+}
+
+impl Display for IpAddress {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            IpAddress::V4(address) => address.fmt(f),
+            IpAddress::V6(address) => address.fmt(f),
+        }
+    }
+}
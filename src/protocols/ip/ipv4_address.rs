@@ -1,33 +1,151 @@
-use crate::types::bit_stream::{BitStream, Bits};
+use crate::types::bit_stream::{BitStream, BitWriter};
 use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
 use std::fmt::{Display, Formatter};
+use std::net::{AddrParseError, Ipv4Addr};
+use std::str::FromStr;
 
-#[derive(Clone)]
+/// IPv4アドレス
+///
+/// ネットワークバイトオーダーの4バイトで保持する。`std::net::Ipv4Addr` と相互に変換できる。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct IPv4Address {
-    pub address: Bits,
+    pub address: [u8; 4],
 }
 
 impl ByteObject for IPv4Address {
-    fn from_stream(src: &mut BitStream) -> Self {
-        let bits = src.pop(32);
-        let address = bits.try_into().expect("Invalid IPv4 address length");
-        IPv4Address { address }
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let address = src.try_pop(32)?.to_u32().to_be_bytes();
+        Ok(IPv4Address { address })
     }
 
-    fn to_bits(&self) -> Bits {
-        self.address.clone()
+    fn write_to(&self, writer: &mut BitWriter) {
+        writer.write_bytes(&self.address);
+    }
+}
+
+impl IPv4Address {
+    /// 0.0.0.0
+    pub const UNSPECIFIED: IPv4Address = IPv4Address::new(0, 0, 0, 0);
+    /// 255.255.255.255
+    pub const BROADCAST: IPv4Address = IPv4Address::new(255, 255, 255, 255);
+    /// 127.0.0.1
+    pub const LOCALHOST: IPv4Address = IPv4Address::new(127, 0, 0, 1);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        IPv4Address {
+            address: [a, b, c, d],
+        }
+    }
+
+    /// 4つのオクテットから作成する
+    pub const fn from_octets(octets: [u8; 4]) -> Self {
+        IPv4Address { address: octets }
+    }
+
+    /// 4つのオクテットとして返す
+    pub const fn octets(&self) -> [u8; 4] {
+        self.address
+    }
+
+    /// ビッグエンディアンの32ビット整数として返す
+    pub const fn to_u32(&self) -> u32 {
+        u32::from_be_bytes(self.address)
+    }
+
+    pub const fn from_u32(value: u32) -> Self {
+        IPv4Address {
+            address: value.to_be_bytes(),
+        }
+    }
+
+    /// 0.0.0.0 かどうか
+    pub fn is_unspecified(&self) -> bool {
+        self.address == [0, 0, 0, 0]
+    }
+
+    /// ループバックアドレス（127.0.0.0/8）かどうか
+    pub fn is_loopback(&self) -> bool {
+        self.address[0] == 127
+    }
+
+    /// マルチキャストアドレス（224.0.0.0/4）かどうか
+    pub fn is_multicast(&self) -> bool {
+        self.address[0] & 0xF0 == 224
+    }
+
+    /// プライベートアドレス（RFC 1918: 10/8, 172.16/12, 192.168/16）かどうか
+    pub fn is_private(&self) -> bool {
+        match self.address {
+            [10, ..] => true,
+            [172, b, ..] => b & 0xF0 == 16,
+            [192, 168, ..] => true,
+            _ => false,
+        }
+    }
+
+    /// リミテッドブロードキャストアドレス（255.255.255.255）かどうか
+    pub fn is_broadcast(&self) -> bool {
+        *self == IPv4Address::BROADCAST
+    }
+
+    /// 将来のために予約されたアドレス（240.0.0.0/4、旧クラスE）かどうか。255.255.255.255 も含む
+    pub fn is_reserved(&self) -> bool {
+        self.address[0] & 0xF0 == 240
+    }
+
+    /// リンクローカルアドレス（169.254.0.0/16）かどうか
+    pub fn is_link_local(&self) -> bool {
+        matches!(self.address, [169, 254, ..])
+    }
+}
+
+impl From<Ipv4Addr> for IPv4Address {
+    fn from(address: Ipv4Addr) -> Self {
+        IPv4Address::from_octets(address.octets())
+    }
+}
+
+impl From<IPv4Address> for Ipv4Addr {
+    fn from(address: IPv4Address) -> Self {
+        Ipv4Addr::from(address.address)
+    }
+}
+
+impl From<[u8; 4]> for IPv4Address {
+    fn from(octets: [u8; 4]) -> Self {
+        IPv4Address::from_octets(octets)
+    }
+}
+
+impl FromStr for IPv4Address {
+    type Err = AddrParseError;
+
+    /// `"10.0.0.1"` のようなドット区切りの文字列を解析する
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse::<Ipv4Addr>()?.into())
     }
 }
 
 impl Display for IPv4Address {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "ipv4({}.{}.{}.{})",
-            self.address.to_u8s()[0],
-            self.address.to_u8s()[1],
-            self.address.to_u8s()[2],
-            self.address.to_u8s()[3]
-        )
+        write!(f, "ipv4({})", Ipv4Addr::from(*self))
+    }
+}
+
+/// `"10.0.0.1"` のようなドット区切りの文字列としてシリアライズする
+#[cfg(feature = "serde")]
+impl serde::Serialize for IPv4Address {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Ipv4Addr::from(*self))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for IPv4Address {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = <String as serde::Deserialize>::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
     }
 }
@@ -0,0 +1,128 @@
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// `10.0.0.0/24` のようなIPv4のアドレスブロック
+///
+/// ホスト部を含んだアドレスのまま保持し、`network` / `broadcast` で境界のアドレスを求める。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ipv4Cidr {
+    address: IPv4Address,
+    prefix_len: u8,
+}
+
+impl Ipv4Cidr {
+    /// プレフィックス長が32を超える場合は `None` を返す
+    pub fn new(address: IPv4Address, prefix_len: u8) -> Option<Self> {
+        (prefix_len <= 32).then_some(Ipv4Cidr {
+            address,
+            prefix_len,
+        })
+    }
+
+    /// アドレスとネットマスクから作成する。マスクのビットが連続していない場合は `None` を返す
+    pub fn from_netmask(address: IPv4Address, netmask: IPv4Address) -> Option<Self> {
+        let mask = netmask.to_u32();
+        let prefix_len = mask.leading_ones();
+        if mask.checked_shl(prefix_len).unwrap_or(0) != 0 {
+            return None;
+        }
+        Ipv4Cidr::new(address, prefix_len as u8)
+    }
+
+    pub fn address(&self) -> IPv4Address {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// ネットマスク（例: /24 なら 255.255.255.0）
+    pub fn netmask(&self) -> IPv4Address {
+        IPv4Address::from_u32(self.mask())
+    }
+
+    /// ネットワークアドレス（ホスト部をすべて0にしたもの）
+    pub fn network(&self) -> IPv4Address {
+        IPv4Address::from_u32(self.address.to_u32() & self.mask())
+    }
+
+    /// ブロードキャストアドレス（ホスト部をすべて1にしたもの）
+    pub fn broadcast(&self) -> IPv4Address {
+        IPv4Address::from_u32(self.address.to_u32() | !self.mask())
+    }
+
+    /// `address` がこのブロックのブロードキャストアドレスかどうか
+    ///
+    /// /31 と /32 にはブロードキャストアドレスがない（RFC 3021）。
+    pub fn is_broadcast(&self, address: &IPv4Address) -> bool {
+        self.prefix_len < 31 && *address == self.broadcast()
+    }
+
+    /// `address` がこのブロックのネットワークアドレスかどうか
+    ///
+    /// /31 と /32 ではネットワークアドレスもホストに割り当てられる（RFC 3021）。
+    pub fn is_network(&self, address: &IPv4Address) -> bool {
+        self.prefix_len < 31 && *address == self.network()
+    }
+
+    /// `address` がこのブロックに含まれるかどうか
+    pub fn contains(&self, address: &IPv4Address) -> bool {
+        address.to_u32() & self.mask() == self.address.to_u32() & self.mask()
+    }
+
+    fn mask(&self) -> u32 {
+        u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0)
+    }
+}
+
+/// CIDR表記の解析に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CidrParseError {
+    /// `/` がない
+    MissingPrefix,
+    /// アドレス部分が不正
+    InvalidAddress,
+    /// プレフィックス長が数値でない、または範囲外
+    InvalidPrefix,
+}
+
+impl Display for CidrParseError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CidrParseError::MissingPrefix => write!(f, "missing prefix length"),
+            CidrParseError::InvalidAddress => write!(f, "invalid address"),
+            CidrParseError::InvalidPrefix => write!(f, "invalid prefix length"),
+        }
+    }
+}
+
+impl std::error::Error for CidrParseError {}
+
+impl FromStr for Ipv4Cidr {
+    type Err = CidrParseError;
+
+    /// `"10.0.0.0/24"` の形式を解析する
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = s.split_once('/').ok_or(CidrParseError::MissingPrefix)?;
+        let address = address
+            .parse()
+            .map_err(|_| CidrParseError::InvalidAddress)?;
+        let prefix_len = prefix_len
+            .parse()
+            .map_err(|_| CidrParseError::InvalidPrefix)?;
+        Ipv4Cidr::new(address, prefix_len).ok_or(CidrParseError::InvalidPrefix)
+    }
+}
+
+impl Display for Ipv4Cidr {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            std::net::Ipv4Addr::from(self.address),
+            self.prefix_len
+        )
+    }
+}
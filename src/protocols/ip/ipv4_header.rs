@@ -1,11 +1,19 @@
+use crate::protocols::checksum;
 use crate::protocols::ip::ipv4_address::IPv4Address;
-use crate::types::bit_stream::{BitStream, Bits, BitsCompatible};
+use crate::protocols::ip::ipv4_option::IPv4Option;
+use crate::types::bit_stream::{BitStream, BitWriter};
 use crate::types::byte_object::ByteObject;
+use crate::types::field_layout::{read_fields, write_fields};
+use crate::types::parse_error::ParseError;
 use std::fmt::{Display, Formatter};
 
+/// IPv4ヘッダー
+///
+/// IHLはオプションから導出するためフィールドとしては持たない。`ihl()` を参照すること。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IPv4Header {
     pub version: u8,
-    pub ihl: u8,
     pub dscp: u8,
     pub ecn: u8,
     pub total_length: u16,
@@ -17,31 +25,56 @@ pub struct IPv4Header {
     pub header_checksum: u16,
     pub source_address: IPv4Address,
     pub destination_address: IPv4Address,
+    /// オプション。書き出すときは4バイト境界までEnd（0）で埋める
+    pub options: Vec<IPv4Option>,
 }
 
-impl ByteObject for IPv4Header {
-    fn from_stream(src: &mut BitStream) -> Self {
-        let version = src.pop(4).to_u8();
-        let ihl = src.pop(4).to_u8();
-        let dscp = src.pop(6).to_u8();
-        let ecn = src.pop(2).to_u8();
-        let total_length = src.pop(16).to_u16();
-        let identification = src.pop(16).to_u16();
-        let flags = src.pop(3).to_u8();
-        let fragment_offset = src.pop(13).to_u16();
-        let ttl = src.pop(8).to_u8();
-        let protocol = src.pop(8).to_u8();
-        let header_checksum = src.pop(16).to_u16();
-        let source_address = IPv4Address::from_stream(src);
-        let destination_address = IPv4Address::from_stream(src);
-
-        // オプションフィールドのスキップ
-        let header_len_bytes = ihl as usize * 4;
-        if header_len_bytes > 20 * 8 {
-            src.pop(header_len_bytes - 20 * 8);
-        }
+/// オプションを含めたヘッダー長の上限（バイト）
+pub const IPV4_MAX_HEADER_LENGTH: usize = 60;
+
+/// ヘッダーの固定部分（オプションより前）のフィールド配置
+pub mod layout {
+    use crate::types::field_layout::FieldSpec;
+
+    pub const VERSION: FieldSpec = FieldSpec::new("Version", 4);
+    pub const HEADER_LENGTH: FieldSpec = FieldSpec::new("Header Length", 4);
+    pub const DSCP: FieldSpec = FieldSpec::new("Differentiated Services Codepoint", 6);
+    pub const ECN: FieldSpec = FieldSpec::new("Explicit Congestion Notification", 2);
+    pub const TOTAL_LENGTH: FieldSpec = FieldSpec::new("Total Length", 16);
+    pub const IDENTIFICATION: FieldSpec = FieldSpec::new("Identification", 16);
+    pub const FLAGS: FieldSpec = FieldSpec::new("Flags", 3);
+    pub const FRAGMENT_OFFSET: FieldSpec = FieldSpec::new("Fragment Offset", 13);
+    pub const TTL: FieldSpec = FieldSpec::new("Time to Live", 8);
+    pub const PROTOCOL: FieldSpec = FieldSpec::new("Protocol", 8);
+    pub const HEADER_CHECKSUM: FieldSpec = FieldSpec::new("Header Checksum", 16);
+    pub const SOURCE_ADDRESS: FieldSpec = FieldSpec::new("Source Address", 32);
+    pub const DESTINATION_ADDRESS: FieldSpec = FieldSpec::new("Destination Address", 32);
+
+    pub const FIXED: [FieldSpec; 13] = [
+        VERSION,
+        HEADER_LENGTH,
+        DSCP,
+        ECN,
+        TOTAL_LENGTH,
+        IDENTIFICATION,
+        FLAGS,
+        FRAGMENT_OFFSET,
+        TTL,
+        PROTOCOL,
+        HEADER_CHECKSUM,
+        SOURCE_ADDRESS,
+        DESTINATION_ADDRESS,
+    ];
+}
 
-        IPv4Header {
+/// Don't Fragment フラグ
+pub const IPV4_FLAG_DF: u8 = 0b010;
+/// More Fragments フラグ
+pub const IPV4_FLAG_MF: u8 = 0b001;
+
+impl ByteObject for IPv4Header {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let [
             version,
             ihl,
             dscp,
@@ -55,24 +88,74 @@ impl ByteObject for IPv4Header {
             header_checksum,
             source_address,
             destination_address,
+        ] = read_fields(src, &layout::FIXED)?;
+        let (version, ihl, total_length) = (version as u8, ihl as u8, total_length as u16);
+        if version != 4 {
+            return Err(ParseError::InvalidVersion {
+                expected: 4,
+                found: version,
+            });
+        }
+        if ihl < 5 {
+            return Err(ParseError::BadHeaderLength(ihl));
         }
+        if (total_length as usize) < ihl as usize * 4 {
+            return Err(ParseError::BadTotalLength {
+                total_length,
+                header_length: ihl as usize * 4,
+            });
+        }
+
+        let mut options_stream = src.sub_stream((ihl as usize - 5) * 32)?;
+        let options = IPv4Option::parse_list(&mut options_stream)?;
+
+        Ok(IPv4Header {
+            version,
+            dscp: dscp as u8,
+            ecn: ecn as u8,
+            total_length,
+            identification: identification as u16,
+            flags: flags as u8,
+            fragment_offset: fragment_offset as u16,
+            ttl: ttl as u8,
+            protocol: protocol as u8,
+            header_checksum: header_checksum as u16,
+            source_address: IPv4Address::from_u32(source_address as u32),
+            destination_address: IPv4Address::from_u32(destination_address as u32),
+            options,
+        })
     }
-    fn to_bits(&self) -> Bits {
-        let mut bits = Bits::new();
-        bits.append(&self.version.to_bits()[4..].to_bits());
-        bits.append(&self.ihl.to_bits()[4..].to_bits());
-        bits.append(&self.dscp.to_bits()[2..].to_bits());
-        bits.append(&self.ecn.to_bits()[6..].to_bits());
-        bits.append(&self.total_length.to_bits());
-        bits.append(&self.identification.to_bits());
-        bits.append(&self.flags.to_bits()[5..].to_bits());
-        bits.append(&self.fragment_offset.to_bits()[3..].to_bits());
-        bits.append(&self.ttl.to_bits());
-        bits.append(&self.protocol.to_bits());
-        bits.append(&self.header_checksum.to_bits());
-        bits.append(&self.source_address.to_bits());
-        bits.append(&self.destination_address.to_bits());
-        bits
+    fn write_to(&self, writer: &mut BitWriter) {
+        assert!(
+            self.header_length() <= IPV4_MAX_HEADER_LENGTH,
+            "IPv4 options exceed 40 bytes"
+        );
+        let start = writer.byte_len();
+        write_fields(
+            writer,
+            &layout::FIXED,
+            [
+                self.version as u64,
+                self.ihl() as u64,
+                self.dscp as u64,
+                self.ecn as u64,
+                self.total_length as u64,
+                self.identification as u64,
+                self.flags as u64,
+                self.fragment_offset as u64,
+                self.ttl as u64,
+                self.protocol as u64,
+                self.header_checksum as u64,
+                u32::from_be_bytes(self.source_address.octets()) as u64,
+                u32::from_be_bytes(self.destination_address.octets()) as u64,
+            ],
+        );
+        for option in &self.options {
+            option.write_to(writer);
+        }
+        while writer.byte_len() - start < self.header_length() {
+            writer.write_u8(0);
+        }
     }
 }
 
@@ -80,48 +163,57 @@ impl IPv4Header {
     /// IPv4ヘッダのチェックサムを計算する
     /// RFC 791に従って、ヘッダの16ビット単位の1の補数の和を計算する
     pub fn calculate_checksum(&self) -> u16 {
-        let mut sum: u32 = 0;
-
-        // Version (4 bits) + IHL (4 bits) + DSCP (6 bits) + ECN (2 bits)
-        let version_ihl = ((self.version as u16) << 4) | (self.ihl as u16);
-        let dscp_ecn = ((self.dscp as u16) << 2) | (self.ecn as u16);
-        let first_word = (version_ihl << 8) | dscp_ecn;
-        sum += first_word as u32;
+        let mut writer = BitWriter::with_capacity(self.header_length());
+        self.write_to(&mut writer);
+        let mut bytes = writer.finish();
 
-        // Total Length
-        sum += self.total_length as u32;
+        // Header Checksum フィールドは0として計算
+        bytes[10] = 0;
+        bytes[11] = 0;
+        checksum::checksum(&bytes)
+    }
 
-        // Identification
-        sum += self.identification as u32;
+    /// オプションのバイト長（パディングを含まない）
+    pub fn options_length(&self) -> usize {
+        self.options.iter().map(IPv4Option::encoded_len).sum()
+    }
 
-        // Flags (3 bits) + Fragment Offset (13 bits)
-        let flags_fragment = ((self.flags as u16) << 13) | (self.fragment_offset & 0x1FFF);
-        sum += flags_fragment as u32;
+    /// ヘッダー長（バイト）。オプションを4バイト境界まで埋めた長さを含む
+    pub fn header_length(&self) -> usize {
+        20 + self.options_length().div_ceil(4) * 4
+    }
 
-        // TTL + Protocol
-        let ttl_protocol = ((self.ttl as u16) << 8) | (self.protocol as u16);
-        sum += ttl_protocol as u32;
+    /// IHL（32ビットワード単位のヘッダー長）
+    pub fn ihl(&self) -> u8 {
+        (self.header_length() / 4) as u8
+    }
 
-        // Header Checksum フィールドは0として計算
-        // sum += 0;
+    /// オプションを置き換える。ヘッダー長の変化に合わせて `total_length` とチェックサムも更新する
+    pub fn set_options(&mut self, options: Vec<IPv4Option>) {
+        let payload_length = self.payload_length();
+        self.options = options;
+        self.total_length = (self.header_length() + payload_length) as u16;
+        self.update_checksum();
+    }
 
-        // Source Address (32 bits = 2 x 16 bits)
-        let src_bytes = &self.source_address.address;
-        sum += src_bytes.to_u16s()[0] as u32;
-        sum += src_bytes.to_u16s()[1] as u32;
+    /// ペイロード長（バイト）。`total_length` からヘッダー長を引いたもの
+    pub fn payload_length(&self) -> usize {
+        (self.total_length as usize).saturating_sub(self.header_length())
+    }
 
-        // Destination Address (32 bits = 2 x 16 bits)
-        let dst_bytes = &self.destination_address.address;
-        sum += dst_bytes.to_u16s()[0] as u32;
-        sum += dst_bytes.to_u16s()[1] as u32;
+    /// Don't Fragment が立っているかどうか
+    pub fn dont_fragment(&self) -> bool {
+        self.flags & IPV4_FLAG_DF != 0
+    }
 
-        // キャリーを加算
-        while (sum >> 16) != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
-        }
+    /// More Fragments が立っているかどうか
+    pub fn more_fragments(&self) -> bool {
+        self.flags & IPV4_FLAG_MF != 0
+    }
 
-        // 1の補数を取る
-        !sum as u16
+    /// フラグメントの一部（先頭以外、または後続あり）かどうか
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset != 0
     }
 
     /// チェックサムが正しいかどうかを検証する
@@ -134,9 +226,37 @@ impl IPv4Header {
         self.header_checksum = self.calculate_checksum();
     }
 
+    /// TTLを書き換え、チェックサムを差分更新する
+    pub fn set_ttl(&mut self, ttl: u8) {
+        let old = u16::from_be_bytes([self.ttl, self.protocol]);
+        let new = u16::from_be_bytes([ttl, self.protocol]);
+        self.header_checksum = checksum::update(self.header_checksum, old, new);
+        self.ttl = ttl;
+    }
+
+    /// 送信元アドレスを書き換え、チェックサムを差分更新する
+    pub fn set_source_address(&mut self, address: IPv4Address) {
+        self.header_checksum = checksum::update_bytes(
+            self.header_checksum,
+            &self.source_address.octets(),
+            &address.octets(),
+        );
+        self.source_address = address;
+    }
+
+    /// 宛先アドレスを書き換え、チェックサムを差分更新する
+    pub fn set_destination_address(&mut self, address: IPv4Address) {
+        self.header_checksum = checksum::update_bytes(
+            self.header_checksum,
+            &self.destination_address.octets(),
+            &address.octets(),
+        );
+        self.destination_address = address;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new_with_checksum(
         version: u8,
-        ihl: u8,
         dscp: u8,
         ecn: u8,
         total_length: u16,
@@ -150,7 +270,6 @@ impl IPv4Header {
     ) -> Self {
         let mut header = IPv4Header {
             version,
-            ihl,
             dscp,
             ecn,
             total_length,
@@ -162,6 +281,7 @@ impl IPv4Header {
             header_checksum: 0, // 初期値は0
             source_address,
             destination_address,
+            options: Vec::new(),
         };
         header.update_checksum(); // チェックサムを計算して更新
         header
@@ -173,9 +293,9 @@ impl Display for IPv4Header {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         write!(
             f,
-            "IPv4 {{ Version: {}, IHL: {}, Total Length: {}, ID: {}, Flags: {:b}, Fragment Offset: {}, TTL: {}, Protocol: {}, Checksum: {}, Src: {}, Dst: {} }}",
+            "IPv4 {{ Version: {}, IHL: {}, Total Length: {}, ID: {}, Flags: {:b}, Fragment Offset: {}, TTL: {}, Protocol: {}, Checksum: {}, Src: {}, Dst: {}, Options: {} }}",
             self.version,
-            self.ihl,
+            self.ihl(),
             self.total_length,
             self.identification,
             self.flags,
@@ -184,7 +304,44 @@ impl Display for IPv4Header {
             self.protocol,
             self.header_checksum,
             self.source_address,
-            self.destination_address
+            self.destination_address,
+            self.options.len()
         )
     }
 }
+
+/// 各フィールドをビット幅に収まる範囲で生成し、チェックサムは正しい値にする
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IPv4Header {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // 合計40バイトに収まるところまでオプションを生成する
+        let mut options = Vec::new();
+        let mut options_length = 0;
+        while u.ratio(1, 2)? {
+            let option: IPv4Option = u.arbitrary()?;
+            if options_length + option.encoded_len() > IPV4_MAX_HEADER_LENGTH - 20 {
+                break;
+            }
+            options_length += option.encoded_len();
+            options.push(option);
+        }
+        let header_length = 20 + options_length.div_ceil(4) * 4;
+        let mut header = IPv4Header {
+            version: 4,
+            dscp: u.int_in_range(0..=0x3F)?,
+            ecn: u.int_in_range(0..=0x3)?,
+            total_length: u.int_in_range(header_length as u16..=u16::MAX)?,
+            identification: u.arbitrary()?,
+            flags: u.int_in_range(0..=0x7)?,
+            fragment_offset: u.int_in_range(0..=0x1FFF)?,
+            ttl: u.arbitrary()?,
+            protocol: u.arbitrary()?,
+            header_checksum: 0,
+            source_address: u.arbitrary()?,
+            destination_address: u.arbitrary()?,
+            options,
+        };
+        header.update_checksum();
+        Ok(header)
+    }
+}
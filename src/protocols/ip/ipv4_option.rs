@@ -0,0 +1,295 @@
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+
+/// オプションの種別（RFC 791 / RFC 2113）
+pub const IPV4_OPTION_END: u8 = 0;
+pub const IPV4_OPTION_NOP: u8 = 1;
+pub const IPV4_OPTION_RECORD_ROUTE: u8 = 7;
+pub const IPV4_OPTION_TIMESTAMP: u8 = 68;
+pub const IPV4_OPTION_LOOSE_SOURCE_ROUTE: u8 = 131;
+pub const IPV4_OPTION_STRICT_SOURCE_ROUTE: u8 = 137;
+pub const IPV4_OPTION_ROUTER_ALERT: u8 = 148;
+
+/// IPv4ヘッダーのオプション
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IPv4Option {
+    /// End of Option List
+    End,
+    /// No Operation
+    Nop,
+    /// Record Route
+    RecordRoute { pointer: u8, route: Vec<IPv4Address> },
+    /// Internet Timestamp。`flag` が0のときはタイムスタンプのみ、それ以外はアドレスとの組
+    Timestamp {
+        pointer: u8,
+        overflow: u8,
+        flag: u8,
+        entries: Vec<TimestampEntry>,
+    },
+    /// Loose Source and Record Route
+    LooseSourceRoute { pointer: u8, route: Vec<IPv4Address> },
+    /// Strict Source and Record Route
+    StrictSourceRoute { pointer: u8, route: Vec<IPv4Address> },
+    /// Router Alert（RFC 2113）
+    RouterAlert(u16),
+    /// 解釈しないオプション。種別と長さを除いた中身をそのまま保持する
+    Unknown { kind: u8, data: Vec<u8> },
+    /// End の後ろに続く、0でないバイトを含むパディング。受け取ったまま書き戻す
+    Padding(Vec<u8>),
+}
+
+/// Timestampオプションの1項目
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimestampEntry {
+    pub address: Option<IPv4Address>,
+    pub timestamp: u32,
+}
+
+impl IPv4Option {
+    /// 種別の番号
+    pub fn kind(&self) -> u8 {
+        match self {
+            IPv4Option::End => IPV4_OPTION_END,
+            IPv4Option::Nop => IPV4_OPTION_NOP,
+            IPv4Option::RecordRoute { .. } => IPV4_OPTION_RECORD_ROUTE,
+            IPv4Option::Timestamp { .. } => IPV4_OPTION_TIMESTAMP,
+            IPv4Option::LooseSourceRoute { .. } => IPV4_OPTION_LOOSE_SOURCE_ROUTE,
+            IPv4Option::StrictSourceRoute { .. } => IPV4_OPTION_STRICT_SOURCE_ROUTE,
+            IPv4Option::RouterAlert(_) => IPV4_OPTION_ROUTER_ALERT,
+            IPv4Option::Unknown { kind, .. } => *kind,
+            // パディングは End の一部として扱う
+            IPv4Option::Padding(_) => IPV4_OPTION_END,
+        }
+    }
+
+    /// 種別・長さのオクテットを含めたバイト長
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            IPv4Option::End | IPv4Option::Nop => 1,
+            IPv4Option::RecordRoute { route, .. }
+            | IPv4Option::LooseSourceRoute { route, .. }
+            | IPv4Option::StrictSourceRoute { route, .. } => 3 + route.len() * 4,
+            IPv4Option::Timestamp { flag, entries, .. } => {
+                4 + entries.len() * if *flag == 0 { 4 } else { 8 }
+            }
+            IPv4Option::RouterAlert(_) => 4,
+            IPv4Option::Unknown { data, .. } => 2 + data.len(),
+            IPv4Option::Padding(bytes) => bytes.len(),
+        }
+    }
+
+    /// オプション部を読み切るまで解析する
+    ///
+    /// End以降はパディングとして読み捨てる。ただし4バイト境界への切り上げだけでは
+    /// 元のヘッダー長にならない場合は、その分のEndを残してIHLが変わらないようにする。
+    /// パディングは0にすべきとされるだけなので（RFC 791）、0でないバイトがあれば
+    /// `Padding` として残し、受け取ったまま書き戻せるようにする。
+    pub fn parse_list(src: &mut BitStream) -> Result<Vec<IPv4Option>, ParseError> {
+        let area_len = src.remaining / 8;
+        let mut options = Vec::new();
+        let mut len: usize = 0;
+        while src.remaining > 0 {
+            let option = IPv4Option::from_stream(src)?;
+            if option == IPv4Option::End {
+                let padding = src.pop_remaining_bytes();
+                if padding.iter().any(|&byte| byte != 0) {
+                    options.push(IPv4Option::End);
+                    options.push(IPv4Option::Padding(padding));
+                } else if len.div_ceil(4) * 4 != area_len {
+                    options.resize(options.len() + area_len - len, IPv4Option::End);
+                }
+                break;
+            }
+            len += option.encoded_len();
+            options.push(option);
+        }
+        Ok(options)
+    }
+}
+
+impl ByteObject for IPv4Option {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let kind = src.try_pop(8)?.to_u8();
+        match kind {
+            IPV4_OPTION_END => return Ok(IPv4Option::End),
+            IPV4_OPTION_NOP => return Ok(IPv4Option::Nop),
+            _ => {}
+        }
+        let length = src.try_pop(8)?.to_u8();
+        if length < 2 {
+            return Err(ParseError::BadOptionLength { kind, length });
+        }
+        let mut body = src.sub_stream((length as usize - 2) * 8)?;
+        let bad_length = ParseError::BadOptionLength { kind, length };
+
+        let option = match kind {
+            IPV4_OPTION_RECORD_ROUTE
+            | IPV4_OPTION_LOOSE_SOURCE_ROUTE
+            | IPV4_OPTION_STRICT_SOURCE_ROUTE => {
+                if length < 3 || (length - 3) % 4 != 0 {
+                    return Err(bad_length);
+                }
+                let pointer = body.try_pop(8)?.to_u8();
+                let mut route = Vec::new();
+                while body.remaining > 0 {
+                    route.push(IPv4Address::from_stream(&mut body)?);
+                }
+                match kind {
+                    IPV4_OPTION_RECORD_ROUTE => IPv4Option::RecordRoute { pointer, route },
+                    IPV4_OPTION_LOOSE_SOURCE_ROUTE => {
+                        IPv4Option::LooseSourceRoute { pointer, route }
+                    }
+                    _ => IPv4Option::StrictSourceRoute { pointer, route },
+                }
+            }
+            IPV4_OPTION_TIMESTAMP => {
+                if length < 4 {
+                    return Err(bad_length);
+                }
+                let pointer = body.try_pop(8)?.to_u8();
+                let overflow = body.try_pop(4)?.to_u8();
+                let flag = body.try_pop(4)?.to_u8();
+                let entry_len = if flag == 0 { 4 } else { 8 };
+                if !(length as usize - 4).is_multiple_of(entry_len) {
+                    return Err(bad_length);
+                }
+                let mut entries = Vec::new();
+                while body.remaining > 0 {
+                    let address = if flag == 0 {
+                        None
+                    } else {
+                        Some(IPv4Address::from_stream(&mut body)?)
+                    };
+                    let timestamp = body.try_pop(32)?.to_u32();
+                    entries.push(TimestampEntry { address, timestamp });
+                }
+                IPv4Option::Timestamp {
+                    pointer,
+                    overflow,
+                    flag,
+                    entries,
+                }
+            }
+            IPV4_OPTION_ROUTER_ALERT => {
+                if length != 4 {
+                    return Err(bad_length);
+                }
+                IPv4Option::RouterAlert(body.try_pop(16)?.to_u16())
+            }
+            _ => IPv4Option::Unknown {
+                kind,
+                data: body.read_remaining_bytes(),
+            },
+        };
+        Ok(option)
+    }
+
+    fn write_to(&self, writer: &mut BitWriter) {
+        if let IPv4Option::Padding(bytes) = self {
+            writer.write_bytes(bytes);
+            return;
+        }
+        writer.write_u8(self.kind());
+        if matches!(self, IPv4Option::End | IPv4Option::Nop) {
+            return;
+        }
+        writer.write_u8(self.encoded_len() as u8);
+        match self {
+            IPv4Option::RecordRoute { pointer, route }
+            | IPv4Option::LooseSourceRoute { pointer, route }
+            | IPv4Option::StrictSourceRoute { pointer, route } => {
+                writer.write_u8(*pointer);
+                for address in route {
+                    address.write_to(writer);
+                }
+            }
+            IPv4Option::Timestamp {
+                pointer,
+                overflow,
+                flag,
+                entries,
+            } => {
+                writer.write_u8(*pointer);
+                writer.write_bits(*overflow as u64, 4);
+                writer.write_bits(*flag as u64, 4);
+                for entry in entries {
+                    if *flag != 0 {
+                        entry.address.unwrap_or(IPv4Address::UNSPECIFIED).write_to(writer);
+                    }
+                    writer.write_u32_be(entry.timestamp);
+                }
+            }
+            IPv4Option::RouterAlert(value) => writer.write_u16_be(*value),
+            IPv4Option::Unknown { data, .. } => writer.write_bytes(data),
+            IPv4Option::End | IPv4Option::Nop | IPv4Option::Padding(_) => unreachable!(),
+        }
+    }
+}
+
+/// 書き戻したときに同じ値として読めるオプションだけを生成する
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IPv4Option {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let route = |u: &mut arbitrary::Unstructured<'a>| -> arbitrary::Result<Vec<IPv4Address>> {
+            let len = u.int_in_range(0..=4)?;
+            (0..len).map(|_| u.arbitrary()).collect()
+        };
+        Ok(match u.int_in_range(0..=6)? {
+            0 => IPv4Option::Nop,
+            1 => IPv4Option::RecordRoute {
+                pointer: u.arbitrary()?,
+                route: route(u)?,
+            },
+            2 => {
+                let flag = u.int_in_range(0..=0xF)?;
+                let len = u.int_in_range(0..=3)?;
+                let entries = (0..len)
+                    .map(|_| {
+                        Ok(TimestampEntry {
+                            address: if flag == 0 { None } else { Some(u.arbitrary()?) },
+                            timestamp: u.arbitrary()?,
+                        })
+                    })
+                    .collect::<arbitrary::Result<_>>()?;
+                IPv4Option::Timestamp {
+                    pointer: u.arbitrary()?,
+                    overflow: u.int_in_range(0..=0xF)?,
+                    flag,
+                    entries,
+                }
+            }
+            3 => IPv4Option::LooseSourceRoute {
+                pointer: u.arbitrary()?,
+                route: route(u)?,
+            },
+            4 => IPv4Option::StrictSourceRoute {
+                pointer: u.arbitrary()?,
+                route: route(u)?,
+            },
+            5 => IPv4Option::RouterAlert(u.arbitrary()?),
+            _ => {
+                let mut kind = u.int_in_range(2..=u8::MAX)?;
+                while [
+                    IPV4_OPTION_RECORD_ROUTE,
+                    IPV4_OPTION_TIMESTAMP,
+                    IPV4_OPTION_LOOSE_SOURCE_ROUTE,
+                    IPV4_OPTION_STRICT_SOURCE_ROUTE,
+                    IPV4_OPTION_ROUTER_ALERT,
+                ]
+                .contains(&kind)
+                {
+                    kind += 1;
+                }
+                let len = u.int_in_range(0..=8)?;
+                IPv4Option::Unknown {
+                    kind,
+                    data: (0..len).map(|_| u.arbitrary()).collect::<arbitrary::Result<_>>()?,
+                }
+            }
+        })
+    }
+}
@@ -2,60 +2,182 @@
 //!
 //! IPv6アドレスを表す `IPv6Address` 構造体と、
 //! そのバイトストリームとの変換、表示機能を提供する。
+//! 文字列表現は RFC 5952 の正規形（`::` による省略、小文字、先頭の0を省く）に従う。
 
-use std::fmt::{Display, Formatter};
-use crate::types::bit_stream::BitStream;
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::types::bit_stream::{BitStream, BitWriter};
 use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+use std::fmt::{Display, Formatter};
+use std::net::{AddrParseError, Ipv6Addr};
+use std::str::FromStr;
 
 /// IPv6アドレスを表す構造体。
 ///
 /// 16バイトの配列でIPv6アドレスを保持する。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct IPv6Address {
     pub address: [u8; 16],
 }
 
 impl ByteObject for IPv6Address {
     /// バイトストリームから `IPv6Address` を生成する。
-    ///
-    /// # 引数
-    /// * `stream` - 読み取り元の `ByteStream`。
-    ///
-    /// # 戻り値
-    /// 生成された `IPv6Address`。
-    fn from_bytes(stream: &mut BitStream) -> Self {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let bits = src.try_pop(128)?;
+        let mut address = [0; 16];
+        address.copy_from_slice(&bits.to_u8s());
+        Ok(IPv6Address { address })
+    }
+
+    /// `IPv6Address` を書き出す。
+    fn write_to(&self, writer: &mut BitWriter) {
+        writer.write_bytes(&self.address);
+    }
+}
+
+impl IPv6Address {
+    /// ::
+    pub const UNSPECIFIED: IPv6Address = IPv6Address { address: [0; 16] };
+    /// ::1
+    pub const LOCALHOST: IPv6Address = IPv6Address {
+        address: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+    };
+
+    /// 16個のオクテットから作成する
+    pub const fn from_octets(octets: [u8; 16]) -> Self {
+        IPv6Address { address: octets }
+    }
+
+    /// 16個のオクテットとして返す
+    pub const fn octets(&self) -> [u8; 16] {
+        self.address
+    }
+
+    /// ビッグエンディアンの128ビット整数として返す
+    pub const fn to_u128(&self) -> u128 {
+        u128::from_be_bytes(self.address)
+    }
+
+    pub const fn from_u128(value: u128) -> Self {
         IPv6Address {
-            address: stream.pop(16).try_into().unwrap(),
+            address: value.to_be_bytes(),
         }
     }
 
-    /// `IPv6Address` をバイトストリームに追加する。
-    ///
-    /// # 引数
-    /// * `dst` - 書き込み先の `ByteStream`。
+    /// 8個の16ビットセグメントとして返す
+    pub fn segments(&self) -> [u16; 8] {
+        Ipv6Addr::from(*self).segments()
+    }
+
+    /// :: かどうか
+    pub fn is_unspecified(&self) -> bool {
+        *self == IPv6Address::UNSPECIFIED
+    }
+
+    /// ::1 かどうか
+    pub fn is_loopback(&self) -> bool {
+        *self == IPv6Address::LOCALHOST
+    }
+
+    /// マルチキャストアドレス（ff00::/8）かどうか
+    pub fn is_multicast(&self) -> bool {
+        self.address[0] == 0xFF
+    }
+
+    /// リンクローカルユニキャストアドレス（fe80::/10）かどうか
+    pub fn is_link_local(&self) -> bool {
+        self.address[0] == 0xFE && self.address[1] & 0xC0 == 0x80
+    }
+
+    /// IPv4射影アドレス（::ffff:0:0/96）であれば埋め込まれたIPv4アドレスを返す
+    pub fn to_ipv4_mapped(&self) -> Option<IPv4Address> {
+        Ipv6Addr::from(*self).to_ipv4_mapped().map(IPv4Address::from)
+    }
+
+    /// `"fe80::1%eth0"` のようにゾーンID（RFC 4007 11）の付いたアドレスを解析する
     ///
-    /// # 戻り値
-    /// 追加されたバイト数 (常に16)。
-    fn append_to(&self, dst: &mut BitStream) -> usize {
-        dst.append(&self.address);
-        16
+    /// ゾーンIDがなければ `None` を返す。`%` の後ろが空の場合はエラーにする。
+    pub fn parse_with_zone(s: &str) -> Result<(IPv6Address, Option<&str>), ZonedAddrParseError> {
+        let (address, zone) = match s.split_once('%') {
+            Some((_, "")) => return Err(ZonedAddrParseError::EmptyZone),
+            Some((address, zone)) => (address, Some(zone)),
+            None => (s, None),
+        };
+        let address = address
+            .parse()
+            .map_err(|_| ZonedAddrParseError::InvalidAddress)?;
+        Ok((address, zone))
+    }
+}
+
+/// ゾーンID付きのアドレスの解析に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZonedAddrParseError {
+    /// アドレス部分が不正
+    InvalidAddress,
+    /// `%` の後ろにゾーンIDがない
+    EmptyZone,
+}
+
+impl Display for ZonedAddrParseError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ZonedAddrParseError::InvalidAddress => write!(f, "invalid address"),
+            ZonedAddrParseError::EmptyZone => write!(f, "empty zone id"),
+        }
+    }
+}
+
+impl std::error::Error for ZonedAddrParseError {}
+
+impl From<Ipv6Addr> for IPv6Address {
+    fn from(address: Ipv6Addr) -> Self {
+        IPv6Address::from_octets(address.octets())
+    }
+}
+
+impl From<IPv6Address> for Ipv6Addr {
+    fn from(address: IPv6Address) -> Self {
+        Ipv6Addr::from(address.address)
+    }
+}
+
+impl From<[u8; 16]> for IPv6Address {
+    fn from(octets: [u8; 16]) -> Self {
+        IPv6Address::from_octets(octets)
+    }
+}
+
+impl FromStr for IPv6Address {
+    type Err = AddrParseError;
+
+    /// RFC 4291 2.2 のテキスト表現を解析する。`::ffff:1.2.3.4` のような末尾のIPv4表記も受け付ける
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse::<Ipv6Addr>()?.into())
     }
 }
 
 impl Display for IPv6Address {
-    /// `IPv6Address` を人間が読める形式でフォーマットする。
-    /// 例: `ipv6(fe80::1)`
+    /// RFC 5952 の正規形でフォーマットする。
+    /// 例: `ipv6(fe80::1)`、IPv4射影アドレスは `ipv6(::ffff:192.0.2.1)`
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "ipv6({:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x}:{:02x}{:02x})",
-            self.address[0], self.address[1],
-            self.address[2], self.address[3],
-            self.address[4], self.address[5],
-            self.address[6], self.address[7],
-            self.address[8], self.address[9],
-            self.address[10], self.address[11],
-            self.address[12], self.address[13],
-            self.address[14], self.address[15]
-        )
-    }
-}
\ No newline at end of file
+        write!(f, "ipv6({})", Ipv6Addr::from(*self))
+    }
+}
+
+/// `"fe80::1"` のような文字列としてシリアライズする
+#[cfg(feature = "serde")]
+impl serde::Serialize for IPv6Address {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Ipv6Addr::from(*self))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for IPv6Address {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = <String as serde::Deserialize>::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
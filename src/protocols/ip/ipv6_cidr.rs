@@ -0,0 +1,77 @@
+use crate::protocols::ip::ipv4_cidr::CidrParseError;
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// `2001:db8::/32` のようなIPv6のアドレスプレフィックス
+///
+/// `Ipv4Cidr` と同じく、ホスト部（インターフェースID）を含んだアドレスのまま保持する。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ipv6Cidr {
+    address: IPv6Address,
+    prefix_len: u8,
+}
+
+impl Ipv6Cidr {
+    /// プレフィックス長が128を超える場合は `None` を返す
+    pub fn new(address: IPv6Address, prefix_len: u8) -> Option<Self> {
+        (prefix_len <= 128).then_some(Ipv6Cidr {
+            address,
+            prefix_len,
+        })
+    }
+
+    pub fn address(&self) -> IPv6Address {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// プレフィックス部分のビットを立てたマスク（例: /64 なら ffff:ffff:ffff:ffff::）
+    pub fn netmask(&self) -> IPv6Address {
+        IPv6Address::from_u128(self.mask())
+    }
+
+    /// プレフィックス（ホスト部をすべて0にしたもの）
+    pub fn network(&self) -> IPv6Address {
+        IPv6Address::from_u128(self.address.to_u128() & self.mask())
+    }
+
+    /// `address` がこのプレフィックスに含まれるかどうか
+    pub fn contains(&self, address: &IPv6Address) -> bool {
+        address.to_u128() & self.mask() == self.address.to_u128() & self.mask()
+    }
+
+    fn mask(&self) -> u128 {
+        u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0)
+    }
+}
+
+impl FromStr for Ipv6Cidr {
+    type Err = CidrParseError;
+
+    /// `"2001:db8::1/64"` の形式を解析する
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = s.split_once('/').ok_or(CidrParseError::MissingPrefix)?;
+        let address = address
+            .parse()
+            .map_err(|_| CidrParseError::InvalidAddress)?;
+        let prefix_len = prefix_len
+            .parse()
+            .map_err(|_| CidrParseError::InvalidPrefix)?;
+        Ipv6Cidr::new(address, prefix_len).ok_or(CidrParseError::InvalidPrefix)
+    }
+}
+
+impl Display for Ipv6Cidr {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            std::net::Ipv6Addr::from(self.address),
+            self.prefix_len
+        )
+    }
+}
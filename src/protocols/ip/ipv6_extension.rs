@@ -0,0 +1,398 @@
+//! IPv6拡張ヘッダーの連鎖の解析（RFC 8200 4章）。
+//!
+//! `ExtensionHeaders` は基本ヘッダーの `next_header` から順に拡張ヘッダーをたどり、
+//! 型付きの拡張ヘッダーを1つずつ返すイテレーター。たどり終えると上位層のプロトコル番号と
+//! そのペイロード内での位置がわかる。
+//!
+//! 未知のオプションの扱いなどでICMPv6 Parameter Problem を返すべき場合は、
+//! その Code と Pointer（パケット先頭からのオフセット）をエラーとして返す。
+
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_header::{IPV6_HEADER_LENGTH, IPv6Header};
+use crate::types::parse_error::ParseError;
+use std::fmt::{Display, Formatter};
+
+/// Next Header の値
+pub const IPV6_HOP_BY_HOP: u8 = 0;
+pub const IPV6_ROUTING: u8 = 43;
+pub const IPV6_FRAGMENT: u8 = 44;
+pub const IPV6_ESP: u8 = 50;
+pub const IPV6_AUTHENTICATION: u8 = 51;
+pub const IPV6_NO_NEXT_HEADER: u8 = 59;
+pub const IPV6_DESTINATION_OPTIONS: u8 = 60;
+
+/// Parameter Problem の Code（RFC 4443 3.4）
+pub const PARAMETER_PROBLEM_ERRONEOUS_FIELD: u8 = 0;
+pub const PARAMETER_PROBLEM_UNRECOGNIZED_NEXT_HEADER: u8 = 1;
+pub const PARAMETER_PROBLEM_UNRECOGNIZED_OPTION: u8 = 2;
+
+/// Hop-by-Hop / Destination Options ヘッダー内のオプション
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ipv6Option {
+    Pad1,
+    /// 中身を含めた長さ（バイト）
+    PadN(usize),
+    /// Router Alert（RFC 2711）
+    RouterAlert(u16),
+    /// 解釈しないオプション。未知の種別でも処理を続けてよいもの（上位2ビットが00）だけがここに入る
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+/// 拡張ヘッダー
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ipv6ExtensionHeader {
+    HopByHop(Vec<Ipv6Option>),
+    Routing {
+        routing_type: u8,
+        segments_left: u8,
+        data: Vec<u8>,
+    },
+    Fragment {
+        /// 8バイト単位のオフセット
+        offset: u16,
+        more_fragments: bool,
+        identification: u32,
+    },
+    DestinationOptions(Vec<Ipv6Option>),
+    Authentication {
+        spi: u32,
+        sequence: u32,
+        icv: Vec<u8>,
+    },
+    /// ESP。以降は暗号化されているため解析を終える
+    EncapsulatingSecurityPayload { spi: u32, sequence: u32 },
+}
+
+/// 拡張ヘッダーの連鎖を処理できなかった理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// 形式が壊れている。黙って破棄する
+    Malformed(ParseError),
+    /// 未知のオプションの指示により、通知せずに破棄する
+    Discard,
+    /// 破棄して ICMPv6 Parameter Problem を返す。`pointer` はパケット先頭からのオフセット
+    ParameterProblem { code: u8, pointer: u32 },
+}
+
+impl Display for ChainError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ChainError::Malformed(e) => write!(f, "malformed extension header: {}", e),
+            ChainError::Discard => write!(f, "discarded by unrecognized option"),
+            ChainError::ParameterProblem { code, pointer } => {
+                write!(f, "parameter problem (code {}) at byte {}", code, pointer)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl From<ParseError> for ChainError {
+    fn from(error: ParseError) -> Self {
+        ChainError::Malformed(error)
+    }
+}
+
+/// 拡張ヘッダーとして連鎖をたどる Next Header かどうか
+pub fn is_extension_header(next_header: u8) -> bool {
+    matches!(
+        next_header,
+        IPV6_HOP_BY_HOP
+            | IPV6_ROUTING
+            | IPV6_FRAGMENT
+            | IPV6_ESP
+            | IPV6_AUTHENTICATION
+            | IPV6_DESTINATION_OPTIONS
+    )
+}
+
+/// 拡張ヘッダーを順にたどるイテレーター
+pub struct ExtensionHeaders<'a> {
+    data: &'a [u8],
+    /// `data` 内の現在位置
+    pos: usize,
+    /// `data` の先頭がパケット先頭から何バイト目か
+    base: usize,
+    next_header: u8,
+    /// 現在の `next_header` を格納していたフィールドの、パケット先頭からの位置
+    next_header_pointer: usize,
+    destination_multicast: bool,
+    first: bool,
+    done: bool,
+}
+
+impl<'a> ExtensionHeaders<'a> {
+    /// 基本ヘッダーに続くペイロードをたどる
+    pub fn new(header: &IPv6Header, payload: &'a [u8]) -> Self {
+        ExtensionHeaders {
+            data: payload,
+            pos: 0,
+            base: IPV6_HEADER_LENGTH,
+            next_header: header.next_header,
+            next_header_pointer: 6,
+            destination_multicast: header.destination_address.is_multicast(),
+            first: true,
+            done: false,
+        }
+    }
+
+    /// 再構築した断片化可能部分など、Fragment ヘッダーの直後から解析を続ける
+    ///
+    /// `base` は先頭フラグメントでの `data` の開始位置。Next Header のフィールドは
+    /// その直前にある Fragment ヘッダーの先頭にあるものとして扱う。
+    pub fn resume(next_header: u8, data: &'a [u8], destination: &IPv6Address, base: usize) -> Self {
+        ExtensionHeaders {
+            data,
+            pos: 0,
+            base,
+            next_header,
+            next_header_pointer: base - 8,
+            destination_multicast: destination.is_multicast(),
+            first: false,
+            done: false,
+        }
+    }
+
+    /// 上位層のプロトコル番号（たどり終えた後に有効）
+    pub fn upper_layer(&self) -> u8 {
+        self.next_header
+    }
+
+    /// 上位層のデータの `data` 内での開始位置（たどり終えた後に有効）
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// 上位層のプロトコル番号を格納していたフィールドの、パケット先頭からの位置
+    pub fn next_header_pointer(&self) -> usize {
+        self.next_header_pointer
+    }
+
+    /// 現在位置から `len` バイト
+    fn peek_bytes(&self, len: usize) -> Result<&'a [u8], ChainError> {
+        let rest = &self.data[self.pos..];
+        rest.get(..len).ok_or(ChainError::Malformed(ParseError::Truncated {
+            needed: len * 8,
+            available: rest.len() * 8,
+        }))
+    }
+
+    /// パケット先頭からの位置
+    fn pointer(&self, offset_in_header: usize) -> u32 {
+        (self.base + self.pos + offset_in_header) as u32
+    }
+
+    /// オプション領域を解析する。`start` はヘッダー内でのオプション領域の開始位置
+    fn parse_options(&self, header: &[u8], start: usize) -> Result<Vec<Ipv6Option>, ChainError> {
+        let mut options = Vec::new();
+        let mut i = start;
+        while i < header.len() {
+            let kind = header[i];
+            if kind == 0 {
+                options.push(Ipv6Option::Pad1);
+                i += 1;
+                continue;
+            }
+            let len = *header.get(i + 1).ok_or(ChainError::Malformed(ParseError::BadOptionLength {
+                kind,
+                length: 0,
+            }))? as usize;
+            let data = header.get(i + 2..i + 2 + len).ok_or(ChainError::Malformed(
+                ParseError::BadOptionLength {
+                    kind,
+                    length: len as u8,
+                },
+            ))?;
+            let option = match kind {
+                1 => Ipv6Option::PadN(2 + len),
+                5 if len == 2 => Ipv6Option::RouterAlert(u16::from_be_bytes([data[0], data[1]])),
+                _ => {
+                    // 上位2ビットが未知のオプションに対する動作を表す
+                    match kind >> 6 {
+                        0b00 => Ipv6Option::Unknown {
+                            kind,
+                            data: data.to_vec(),
+                        },
+                        0b01 => return Err(ChainError::Discard),
+                        0b11 if self.destination_multicast => return Err(ChainError::Discard),
+                        _ => {
+                            return Err(ChainError::ParameterProblem {
+                                code: PARAMETER_PROBLEM_UNRECOGNIZED_OPTION,
+                                pointer: self.pointer(i),
+                            });
+                        }
+                    }
+                }
+            };
+            options.push(option);
+            i += 2 + len;
+        }
+        Ok(options)
+    }
+
+    fn parse_next(&mut self) -> Result<Ipv6ExtensionHeader, ChainError> {
+        let kind = self.next_header;
+        if kind == IPV6_HOP_BY_HOP && !self.first {
+            // Hop-by-Hop は基本ヘッダーの直後にしか置けない
+            return Err(ChainError::ParameterProblem {
+                code: PARAMETER_PROBLEM_UNRECOGNIZED_NEXT_HEADER,
+                pointer: self.next_header_pointer as u32,
+            });
+        }
+        self.first = false;
+
+        let (header, len) = match kind {
+            IPV6_HOP_BY_HOP | IPV6_DESTINATION_OPTIONS => {
+                let len = (self.peek_bytes(2)?[1] as usize + 1) * 8;
+                let bytes = self.peek_bytes(len)?;
+                let options = self.parse_options(bytes, 2)?;
+                let header = if kind == IPV6_HOP_BY_HOP {
+                    Ipv6ExtensionHeader::HopByHop(options)
+                } else {
+                    Ipv6ExtensionHeader::DestinationOptions(options)
+                };
+                (header, len)
+            }
+            IPV6_ROUTING => {
+                let len = (self.peek_bytes(2)?[1] as usize + 1) * 8;
+                let bytes = self.peek_bytes(len)?;
+                let segments_left = bytes[3];
+                // 中継する経路種別には対応していないため、残りのセグメントがあれば誤りとして扱う
+                if segments_left != 0 {
+                    return Err(ChainError::ParameterProblem {
+                        code: PARAMETER_PROBLEM_ERRONEOUS_FIELD,
+                        pointer: self.pointer(2),
+                    });
+                }
+                let header = Ipv6ExtensionHeader::Routing {
+                    routing_type: bytes[2],
+                    segments_left,
+                    data: bytes[4..].to_vec(),
+                };
+                (header, len)
+            }
+            IPV6_FRAGMENT => {
+                let bytes = self.peek_bytes(8)?;
+                let field = u16::from_be_bytes([bytes[2], bytes[3]]);
+                let offset = field >> 3;
+                let more_fragments = field & 1 != 0;
+                let fragment_len = self.data.len() - self.pos - 8;
+                if more_fragments && !fragment_len.is_multiple_of(8) {
+                    // 最後以外のフラグメントの長さは8バイトの倍数でなければならない
+                    return Err(ChainError::ParameterProblem {
+                        code: PARAMETER_PROBLEM_ERRONEOUS_FIELD,
+                        pointer: 4,
+                    });
+                }
+                if offset as usize * 8 + fragment_len > 65535 {
+                    return Err(ChainError::ParameterProblem {
+                        code: PARAMETER_PROBLEM_ERRONEOUS_FIELD,
+                        pointer: self.pointer(2),
+                    });
+                }
+                // 分割されている場合、以降は再構築してからでないと解析できない
+                if offset != 0 || more_fragments {
+                    self.done = true;
+                }
+                let header = Ipv6ExtensionHeader::Fragment {
+                    offset,
+                    more_fragments,
+                    identification: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                };
+                (header, 8)
+            }
+            IPV6_AUTHENTICATION => {
+                let len = (self.peek_bytes(2)?[1] as usize + 2) * 4;
+                if len < 12 {
+                    return Err(ChainError::Malformed(ParseError::BadHeaderLength(len as u8)));
+                }
+                let bytes = self.peek_bytes(len)?;
+                let header = Ipv6ExtensionHeader::Authentication {
+                    spi: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                    sequence: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+                    icv: bytes[12..].to_vec(),
+                };
+                (header, len)
+            }
+            IPV6_ESP => {
+                let bytes = self.peek_bytes(8)?;
+                // 以降は暗号化されているため、上位層は ESP 自身として扱う
+                self.done = true;
+                let header = Ipv6ExtensionHeader::EncapsulatingSecurityPayload {
+                    spi: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                    sequence: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+                };
+                return Ok(header);
+            }
+            _ => unreachable!("not an extension header"),
+        };
+
+        self.next_header_pointer = self.base + self.pos;
+        self.next_header = self.data[self.pos];
+        self.pos += len;
+        Ok(header)
+    }
+}
+
+impl Iterator for ExtensionHeaders<'_> {
+    type Item = Result<Ipv6ExtensionHeader, ChainError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || !is_extension_header(self.next_header) {
+            return None;
+        }
+        let result = self.parse_next();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// 拡張ヘッダーの連鎖をたどった結果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionChain {
+    pub headers: Vec<Ipv6ExtensionHeader>,
+    /// 上位層のプロトコル番号。分割されたパケットでは Fragment ヘッダーの Next Header
+    pub upper_layer: u8,
+    /// 上位層のデータのペイロード内での開始位置
+    pub offset: usize,
+    /// `upper_layer` を格納していたフィールドの、パケット先頭からの位置
+    pub next_header_pointer: usize,
+}
+
+impl ExtensionChain {
+    /// 連鎖をたどり終えるまで解析する
+    pub fn parse(mut headers: ExtensionHeaders) -> Result<Self, ChainError> {
+        let collected = headers.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok(ExtensionChain {
+            headers: collected,
+            upper_layer: headers.upper_layer(),
+            offset: headers.offset(),
+            next_header_pointer: headers.next_header_pointer(),
+        })
+    }
+
+    /// Fragment ヘッダーがあれば (オフセット（8バイト単位）, More Fragments, Identification)
+    pub fn fragment(&self) -> Option<(u16, bool, u32)> {
+        self.headers.iter().find_map(|header| match header {
+            Ipv6ExtensionHeader::Fragment {
+                offset,
+                more_fragments,
+                identification,
+            } => Some((*offset, *more_fragments, *identification)),
+            _ => None,
+        })
+    }
+
+    /// 分割されていて再構築が必要かどうか。オフセット0かつ後続なしのアトミックフラグメントは含まない（RFC 6946）
+    pub fn is_fragmented(&self) -> bool {
+        matches!(self.fragment(), Some((offset, more, _)) if offset != 0 || more)
+    }
+}
+
+/// 基本ヘッダーに続くペイロードの拡張ヘッダーを解析する
+pub fn parse_chain(header: &IPv6Header, payload: &[u8]) -> Result<ExtensionChain, ChainError> {
+    ExtensionChain::parse(ExtensionHeaders::new(header, payload))
+}
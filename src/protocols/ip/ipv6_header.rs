@@ -3,14 +3,17 @@
 //! IPv6パケットのヘッダーを表す `IPv6Header` 構造体と、
 //! バイトストリームとの変換、表示機能を提供する。
 
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
 use std::fmt::{Display, Formatter};
-use crate::bit_stream::BitStream;
-use crate::byte_object::ByteObject;
-use crate::ipv6_address::IPv6Address;
 
 /// IPv6 (Internet Protocol version 6) ヘッダーを表す構造体。
 ///
-/// IPv6パケットの様々なフィールドを含む。
+/// IPv6パケットの様々なフィールドを含む。拡張ヘッダーは含まない。
+#[derive(ByteObject, Clone, Debug, PartialEq, Eq)]
+#[byte_object(validate = Self::validate)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IPv6Header {
     pub version_traffic_class_flow_label: u32,
     pub payload_length: u16,
@@ -20,7 +23,38 @@ pub struct IPv6Header {
     pub destination_address: IPv6Address,
 }
 
+/// IPv6ヘッダーの長さ（バイト）
+pub const IPV6_HEADER_LENGTH: usize = 40;
+
 impl IPv6Header {
+    /// 新しいヘッダーを作成する。トラフィッククラスとフローラベルは0
+    pub fn new(
+        payload_length: u16,
+        next_header: u8,
+        hop_limit: u8,
+        source_address: IPv6Address,
+        destination_address: IPv6Address,
+    ) -> Self {
+        IPv6Header {
+            version_traffic_class_flow_label: 6 << 28,
+            payload_length,
+            next_header,
+            hop_limit,
+            source_address,
+            destination_address,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ParseError> {
+        if self.version() != 6 {
+            return Err(ParseError::InvalidVersion {
+                expected: 6,
+                found: self.version(),
+            });
+        }
+        Ok(())
+    }
+
     /// バージョンを取得する。
     pub fn version(&self) -> u8 {
         ((self.version_traffic_class_flow_label >> 28) & 0xF) as u8
@@ -37,51 +71,6 @@ impl IPv6Header {
     }
 }
 
-impl ByteObject for IPv6Header {
-    /// バイトストリームから `IPv6Header` を生成する。
-    ///
-    /// # 引数
-    /// * `stream` - 読み取り元の `ByteStream`。
-    ///
-    /// # 戻り値
-    /// 生成された `IPv6Header`。
-    fn from_bytes(stream: &mut BitStream) -> Self {
-        let version_traffic_class_flow_label = u32::from_be_bytes(stream.pop(4).try_into().unwrap());
-        let payload_length = u16::from_be_bytes(stream.pop(2).try_into().unwrap());
-        let next_header = stream.pop(1)[0];
-        let hop_limit = stream.pop(1)[0];
-        let source_address = IPv6Address::from_bytes(stream);
-        let destination_address = IPv6Address::from_bytes(stream);
-
-        IPv6Header {
-            version_traffic_class_flow_label,
-            payload_length,
-            next_header,
-            hop_limit,
-            source_address,
-            destination_address,
-        }
-    }
-
-    /// `IPv6Header` をバイトストリームに追加する。
-    ///
-    /// # 引数
-    /// * `dst` - 書き込み先の `ByteStream`。
-    ///
-    /// # 戻り値
-    /// 追加されたバイト数。
-    fn append_to(&self, dst: &mut BitStream) -> usize {
-        let mut total_len = 0;
-        total_len += dst.append(&self.version_traffic_class_flow_label.to_be_bytes());
-        total_len += dst.append(&self.payload_length.to_be_bytes());
-        total_len += dst.append(&[self.next_header]);
-        total_len += dst.append(&[self.hop_limit]);
-        total_len += self.source_address.append_to(dst);
-        total_len += self.destination_address.append_to(dst);
-        total_len
-    }
-}
-
 impl Display for IPv6Header {
     /// `IPv6Header` を人間が読める形式でフォーマットする。
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
@@ -98,4 +87,19 @@ impl Display for IPv6Header {
             self.destination_address
         )
     }
-}
\ No newline at end of file
+}
+
+/// バージョンを6に固定して生成する
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IPv6Header {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(IPv6Header {
+            version_traffic_class_flow_label: (6 << 28) | u.int_in_range(0..=0x0FFF_FFFF)?,
+            payload_length: u.arbitrary()?,
+            next_header: u.arbitrary()?,
+            hop_limit: u.arbitrary()?,
+            source_address: u.arbitrary()?,
+            destination_address: u.arbitrary()?,
+        })
+    }
+}
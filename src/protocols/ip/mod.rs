@@ -1,4 +1,13 @@
+pub mod fragmentation;
+pub mod ingress;
+pub mod ip_address;
 pub mod ipv4_address;
+pub mod ipv4_cidr;
 pub mod ipv4_header;
-// pub mod ipv6_address;
-// pub mod ipv6_header;
+pub mod ipv4_option;
+pub mod ipv6_address;
+pub mod ipv6_cidr;
+pub mod ipv6_extension;
+pub mod ipv6_header;
+pub mod reassembly;
+pub mod stats;
@@ -0,0 +1,487 @@
+//! フラグメント化されたデータグラムの再構築。
+//!
+//! 受信済みでない範囲を「穴」のリストとして管理する RFC 815 の方式で、
+//! 到着順に関係なくフラグメントを組み立てる。データグラムごとにタイムアウトを持ち、
+//! 全体のメモリ使用量と同時に保持するデータグラム数に上限を設ける。
+//!
+//! キーと先頭フラグメントのヘッダーの型は呼び出し側が決める。IPv4では
+//! (送信元, 宛先, プロトコル, Identification) の組、IPv6では (送信元, 宛先, Identification)
+//! の組をキーにする。
+
+use crate::protocols::ip::ipv4_address::IPv4Address;
+use crate::protocols::ip::ipv4_header::{IPV4_FLAG_MF, IPv4Header};
+use crate::protocols::ip::ipv6_address::IPv6Address;
+use crate::protocols::ip::ipv6_extension::ExtensionChain;
+use crate::protocols::ip::ipv6_header::{IPV6_HEADER_LENGTH, IPv6Header};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// IPv4データグラムを識別するキー（RFC 791）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub source: IPv4Address,
+    pub destination: IPv4Address,
+    pub protocol: u8,
+    pub identification: u16,
+}
+
+impl FragmentKey {
+    pub fn from_header(header: &IPv4Header) -> Self {
+        FragmentKey {
+            source: header.source_address,
+            destination: header.destination_address,
+            protocol: header.protocol,
+            identification: header.identification,
+        }
+    }
+}
+
+/// 既に受信した範囲と重なるフラグメントを受け取ったときの扱い
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OverlapPolicy {
+    /// データグラム全体を破棄する（RFC 5722 と同じ扱い）
+    #[default]
+    Drop,
+    /// 先に受信したデータを残す
+    FirstWins,
+    /// 後から受信したデータで上書きする
+    LastWins,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReassemblyConfig {
+    /// 最初のフラグメントを受信してから破棄するまでの時間
+    pub timeout: Duration,
+    /// 再構築後のペイロードの最大長（バイト）
+    pub max_datagram_size: usize,
+    /// 組み立て中のデータの合計の上限（バイト）
+    pub max_memory: usize,
+    /// 同時に組み立てるデータグラム数の上限
+    pub max_datagrams: usize,
+    pub overlap_policy: OverlapPolicy,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        ReassemblyConfig {
+            timeout: Duration::from_secs(30),
+            max_datagram_size: 65535,
+            max_memory: 4 * 1024 * 1024,
+            max_datagrams: 256,
+            overlap_policy: OverlapPolicy::default(),
+        }
+    }
+}
+
+/// フラグメントを受け入れられなかった理由。いずれの場合も該当データグラムは破棄される
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReassemblyError {
+    /// 受信済みの範囲と重なった（`OverlapPolicy::Drop` の場合）
+    Overlap,
+    /// 再構築後の長さが上限を超える
+    TooLarge,
+    /// 最終フラグメントの位置が食い違う、またはその後ろにデータがある
+    Inconsistent,
+    /// メモリ上限を超えるため受け入れられない
+    OutOfMemory,
+    /// 最後以外のフラグメントの長さが8バイトの倍数でない
+    Misaligned,
+}
+
+impl Display for ReassemblyError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ReassemblyError::Overlap => write!(f, "overlapping fragment"),
+            ReassemblyError::TooLarge => write!(f, "reassembled datagram too large"),
+            ReassemblyError::Inconsistent => write!(f, "inconsistent fragment length"),
+            ReassemblyError::OutOfMemory => write!(f, "reassembly memory exhausted"),
+            ReassemblyError::Misaligned => write!(f, "fragment length not a multiple of 8"),
+        }
+    }
+}
+
+impl std::error::Error for ReassemblyError {}
+
+/// 再構築が完了したデータグラム
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reassembled<H> {
+    /// 先頭フラグメントのヘッダー
+    pub header: H,
+    pub payload: Vec<u8>,
+}
+
+/// タイムアウトで破棄したデータグラム
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expired<K, H> {
+    pub key: K,
+    /// 先頭フラグメントを受信していればそのヘッダー
+    pub header: Option<H>,
+    /// 先頭から途切れずに受信できていた部分
+    pub payload: Vec<u8>,
+}
+
+/// 未受信の範囲 `[start, end)`。最終フラグメントが届くまで末尾は `usize::MAX`
+#[derive(Clone, Copy, Debug)]
+struct Hole {
+    start: usize,
+    end: usize,
+}
+
+struct Datagram<H> {
+    holes: Vec<Hole>,
+    data: Vec<u8>,
+    total_length: Option<usize>,
+    header: Option<H>,
+    started: Instant,
+}
+
+impl<H> Datagram<H> {
+    fn new(now: Instant) -> Self {
+        Datagram {
+            holes: vec![Hole {
+                start: 0,
+                end: usize::MAX,
+            }],
+            data: Vec::new(),
+            total_length: None,
+            header: None,
+            started: now,
+        }
+    }
+
+    /// `[start, end)` のうち穴に含まれる（未受信の）バイト数
+    fn missing_bytes(&self, start: usize, end: usize) -> usize {
+        self.holes
+            .iter()
+            .map(|hole| hole.end.min(end).saturating_sub(hole.start.max(start)))
+            .sum()
+    }
+
+    /// 先頭から途切れずに受信できている部分
+    fn contiguous_prefix(&self) -> &[u8] {
+        let end = self
+            .holes
+            .iter()
+            .map(|hole| hole.start)
+            .min()
+            .unwrap_or(self.data.len());
+        &self.data[..end.min(self.data.len())]
+    }
+
+    /// `[start, end)` を受信済みにする（RFC 815 の手順 1〜6）
+    fn fill(&mut self, start: usize, end: usize, more_fragments: bool) {
+        let mut holes = Vec::with_capacity(self.holes.len() + 1);
+        for hole in self.holes.drain(..) {
+            if start >= hole.end || end <= hole.start {
+                holes.push(hole);
+                continue;
+            }
+            if start > hole.start {
+                holes.push(Hole {
+                    start: hole.start,
+                    end: start,
+                });
+            }
+            if end < hole.end && more_fragments {
+                holes.push(Hole {
+                    start: end,
+                    end: hole.end,
+                });
+            }
+        }
+        // 最終フラグメントより後ろの穴は埋める必要がない
+        if let Some(total) = self.total_length {
+            holes.retain_mut(|hole| {
+                hole.end = hole.end.min(total);
+                hole.start < hole.end
+            });
+        }
+        self.holes = holes;
+    }
+}
+
+/// フラグメントの再構築を行う
+pub struct Reassembler<K, H> {
+    config: ReassemblyConfig,
+    datagrams: HashMap<K, Datagram<H>>,
+    /// 組み立て中のデータの合計（バイト）
+    memory: usize,
+}
+
+impl<K: Clone + Eq + Hash, H> Reassembler<K, H> {
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Reassembler {
+            config,
+            datagrams: HashMap::new(),
+            memory: 0,
+        }
+    }
+
+    pub fn config(&self) -> &ReassemblyConfig {
+        &self.config
+    }
+
+    /// 組み立て中のデータグラム数
+    pub fn len(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    /// 組み立て中のデータの合計（バイト）
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
+    /// フラグメントを1つ受け入れる
+    ///
+    /// `offset` はデータグラム先頭からのバイト位置。`offset` が0のフラグメントの
+    /// `header` を再構築後のヘッダーとして使う。全ての穴が埋まったら組み立てた結果を返す。
+    pub fn insert(
+        &mut self,
+        key: K,
+        header: H,
+        offset: usize,
+        more_fragments: bool,
+        payload: &[u8],
+        now: Instant,
+    ) -> Result<Option<Reassembled<H>>, ReassemblyError> {
+        let end = offset + payload.len();
+        if end > self.config.max_datagram_size {
+            self.remove(&key);
+            return Err(ReassemblyError::TooLarge);
+        }
+
+        if !self.datagrams.contains_key(&key) {
+            while self.datagrams.len() >= self.config.max_datagrams {
+                if !self.evict_oldest(&key) {
+                    return Err(ReassemblyError::OutOfMemory);
+                }
+            }
+            self.datagrams.insert(key.clone(), Datagram::new(now));
+        }
+        let datagram = self.datagrams.get_mut(&key).expect("inserted above");
+
+        // 最終フラグメントの位置は一度決まったら変わらない
+        let consistent = match datagram.total_length {
+            Some(total) if more_fragments => end <= total,
+            Some(total) => end == total,
+            None => more_fragments || datagram.data.len() <= end,
+        };
+        if !consistent {
+            self.remove(&key);
+            return Err(ReassemblyError::Inconsistent);
+        }
+
+        let missing = datagram.missing_bytes(offset, end);
+        let overlaps = missing < payload.len();
+        if overlaps {
+            // 受信済みと同じ内容の再送は無視する
+            let duplicate = missing == 0
+                && datagram.data.get(offset..end) == Some(payload)
+                && (more_fragments || datagram.total_length.is_some());
+            if duplicate {
+                return Ok(None);
+            }
+            if self.config.overlap_policy == OverlapPolicy::Drop {
+                self.remove(&key);
+                return Err(ReassemblyError::Overlap);
+            }
+        }
+
+        let growth = end.saturating_sub(datagram.data.len());
+        while self.memory + growth > self.config.max_memory {
+            if !self.evict_oldest(&key) {
+                self.remove(&key);
+                return Err(ReassemblyError::OutOfMemory);
+            }
+        }
+        self.memory += growth;
+
+        let policy = self.config.overlap_policy;
+        let datagram = self.datagrams.get_mut(&key).expect("not evicted");
+        if datagram.data.len() < end {
+            datagram.data.resize(end, 0);
+        }
+        if overlaps && policy == OverlapPolicy::FirstWins {
+            // 穴の部分だけを書き込む
+            for hole in &datagram.holes {
+                let start = hole.start.max(offset);
+                let stop = hole.end.min(end);
+                if start < stop {
+                    datagram.data[start..stop]
+                        .copy_from_slice(&payload[start - offset..stop - offset]);
+                }
+            }
+        } else {
+            datagram.data[offset..end].copy_from_slice(payload);
+        }
+        if offset == 0 && (datagram.header.is_none() || policy == OverlapPolicy::LastWins) {
+            datagram.header = Some(header);
+        }
+        if !more_fragments {
+            datagram.total_length = Some(end);
+        }
+        datagram.fill(offset, end, more_fragments);
+
+        if !datagram.holes.is_empty() {
+            return Ok(None);
+        }
+        let datagram = self.datagrams.remove(&key).expect("present");
+        self.memory -= datagram.data.len();
+        Ok(Some(Reassembled {
+            header: datagram.header.expect("offset 0 fragment has been received"),
+            payload: datagram.data,
+        }))
+    }
+
+    /// タイムアウトしたデータグラムを破棄して返す
+    pub fn expire(&mut self, now: Instant) -> Vec<Expired<K, H>> {
+        let timeout = self.config.timeout;
+        let keys: Vec<K> = self
+            .datagrams
+            .iter()
+            .filter(|(_, datagram)| now.duration_since(datagram.started) >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let datagram = self.remove(&key)?;
+                Some(Expired {
+                    payload: datagram.contiguous_prefix().to_vec(),
+                    header: datagram.header,
+                    key,
+                })
+            })
+            .collect()
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Datagram<H>> {
+        let datagram = self.datagrams.remove(key)?;
+        self.memory -= datagram.data.len();
+        Some(datagram)
+    }
+
+    /// `keep` 以外で最も古いデータグラムを破棄する。破棄できるものがなければ `false`
+    fn evict_oldest(&mut self, keep: &K) -> bool {
+        let oldest = self
+            .datagrams
+            .iter()
+            .filter(|(key, _)| *key != keep)
+            .min_by_key(|(_, datagram)| datagram.started)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => {
+                self.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// IPv4用の再構築器
+pub type Ipv4Reassembler = Reassembler<FragmentKey, IPv4Header>;
+
+impl Reassembler<FragmentKey, IPv4Header> {
+    /// IPv4フラグメントを受け入れる。完了したらフラグメント情報を消したヘッダーとペイロードを返す
+    pub fn insert_ipv4(
+        &mut self,
+        header: &IPv4Header,
+        payload: &[u8],
+        now: Instant,
+    ) -> Result<Option<(IPv4Header, Vec<u8>)>, ReassemblyError> {
+        let offset = header.fragment_offset as usize * 8;
+        if header.more_fragments() && !payload.len().is_multiple_of(8) {
+            // 最後以外のフラグメントの長さは8バイトの倍数でなければならない（RFC 791）
+            return Err(ReassemblyError::Misaligned);
+        }
+        if header.header_length() + offset + payload.len() > u16::MAX as usize {
+            self.remove(&FragmentKey::from_header(header));
+            return Err(ReassemblyError::TooLarge);
+        }
+        let reassembled = self.insert(
+            FragmentKey::from_header(header),
+            header.clone(),
+            offset,
+            header.more_fragments(),
+            payload,
+            now,
+        )?;
+        Ok(reassembled.map(|Reassembled { mut header, payload }| {
+            header.flags &= !IPV4_FLAG_MF;
+            header.fragment_offset = 0;
+            header.total_length = (header.header_length() + payload.len()) as u16;
+            header.update_checksum();
+            (header, payload)
+        }))
+    }
+}
+
+/// IPv6データグラムを識別するキー（RFC 8200 4.5）
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ipv6FragmentKey {
+    pub source: IPv6Address,
+    pub destination: IPv6Address,
+    pub identification: u32,
+}
+
+/// ICMPv6エラーに含める元パケットの上限（最小MTU 1280 から IPv6ヘッダーとICMPv6ヘッダーを引いた長さ）
+const IPV6_INVOKING_PACKET_LIMIT: usize = 1280 - IPV6_HEADER_LENGTH - 8;
+
+/// IPv6の先頭フラグメントから取っておく情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv6FragmentHead {
+    pub header: IPv6Header,
+    /// Fragment ヘッダーの Next Header。断片化可能部分の先頭のヘッダーを表す
+    pub next_header: u8,
+    /// 断片化可能部分の、先頭フラグメントのパケット先頭からの位置
+    pub fragmentable_offset: usize,
+    /// ICMPv6エラーに含めるための先頭フラグメント（上限で切り詰める）
+    pub packet: Vec<u8>,
+}
+
+/// IPv6用の再構築器。重なったフラグメントは常にデータグラムごと破棄する（RFC 5722）
+pub type Ipv6Reassembler = Reassembler<Ipv6FragmentKey, Ipv6FragmentHead>;
+
+impl Reassembler<Ipv6FragmentKey, Ipv6FragmentHead> {
+    /// IPv6フラグメントを受け入れる。完了したら先頭フラグメントの情報と断片化可能部分を返す
+    ///
+    /// `chain` は `payload` を解析した結果で、Fragment ヘッダーを含んでいること。
+    /// `packet` は受信したパケット全体で、タイムアウト時のICMPv6エラーに使う。
+    pub fn insert_ipv6(
+        &mut self,
+        header: &IPv6Header,
+        chain: &ExtensionChain,
+        payload: &[u8],
+        packet: &[u8],
+        now: Instant,
+    ) -> Result<Option<(Ipv6FragmentHead, Vec<u8>)>, ReassemblyError> {
+        let (offset, more_fragments, identification) =
+            chain.fragment().expect("extension chain has a fragment header");
+        let key = Ipv6FragmentKey {
+            source: header.source_address,
+            destination: header.destination_address,
+            identification,
+        };
+        let head = Ipv6FragmentHead {
+            header: header.clone(),
+            next_header: chain.upper_layer,
+            fragmentable_offset: IPV6_HEADER_LENGTH + chain.offset,
+            packet: packet[..packet.len().min(IPV6_INVOKING_PACKET_LIMIT)].to_vec(),
+        };
+        let reassembled = self.insert(
+            key,
+            head,
+            offset as usize * 8,
+            more_fragments,
+            &payload[chain.offset..],
+            now,
+        )?;
+        Ok(reassembled.map(|Reassembled { header, payload }| (header, payload)))
+    }
+}
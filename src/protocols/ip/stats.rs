@@ -0,0 +1,119 @@
+//! IP層の統計カウンター。
+//!
+//! Linux の `/proc/net/snmp` の `Ip:` 行（RFC 4293 の ipSystemStatsTable）に倣った項目を数える。
+
+use crate::protocols::ip::ingress::DropReason;
+use crate::protocols::ip::reassembly::ReassemblyError;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IpStats {
+    /// 受信したデータグラム数（エラーを含む）
+    pub in_receives: u64,
+    /// ヘッダーの誤り（チェックサム、バージョン、長さ、TTLなど）で破棄した数
+    pub in_hdr_errors: u64,
+    /// 宛先・送信元アドレスが不正で破棄した数
+    pub in_addr_errors: u64,
+    /// 上位プロトコルが未対応で破棄した数
+    pub in_unknown_protos: u64,
+    /// 問題はないが資源不足などで破棄した数
+    pub in_discards: u64,
+    /// 上位層に渡した数
+    pub in_delivers: u64,
+    /// 送信を要求されたデータグラム数
+    pub out_requests: u64,
+    /// 送信できずに破棄した数
+    pub out_discards: u64,
+    /// 再構築がタイムアウトした数
+    pub reasm_timeout: u64,
+    /// 再構築が必要だったフラグメント数
+    pub reasm_reqds: u64,
+    /// 再構築に成功したデータグラム数
+    pub reasm_oks: u64,
+    /// 再構築に失敗した数
+    pub reasm_fails: u64,
+    /// フラグメント化に成功したデータグラム数
+    pub frag_oks: u64,
+    /// フラグメント化できずに破棄した数
+    pub frag_fails: u64,
+    /// フラグメント化で生成したフラグメント数
+    pub frag_creates: u64,
+    /// 実際の長さが全長フィールドより短かった数（IpExt）
+    pub in_truncated_pkts: u64,
+    /// チェックサムの誤りの数（IpExt）。`in_hdr_errors` にも数える
+    pub in_csum_errors: u64,
+}
+
+impl IpStats {
+    pub fn new() -> Self {
+        IpStats::default()
+    }
+
+    /// 受信時に破棄した理由に応じたカウンターを増やす
+    pub fn record_drop(&mut self, reason: &DropReason) {
+        match reason {
+            DropReason::Truncated => self.in_truncated_pkts += 1,
+            DropReason::BadChecksum => {
+                self.in_hdr_errors += 1;
+                self.in_csum_errors += 1;
+            }
+            DropReason::BadVersion
+            | DropReason::BadHeaderLength
+            | DropReason::BadTotalLength
+            | DropReason::BadOptions
+            | DropReason::TtlExpired => self.in_hdr_errors += 1,
+            DropReason::BadSourceAddress
+            | DropReason::BadDestinationAddress
+            | DropReason::NotForUs => self.in_addr_errors += 1,
+        }
+    }
+
+    /// フラグメントの再構築に失敗したときのカウンターを増やす
+    ///
+    /// メモリ上限による破棄はデータグラム自体に問題がないため、`in_discards` にも数える。
+    pub fn record_reassembly_failure(&mut self, error: &ReassemblyError) {
+        self.reasm_fails += 1;
+        if *error == ReassemblyError::OutOfMemory {
+            self.in_discards += 1;
+        }
+    }
+
+    /// `(名前, 値)` の組を `/proc/net/snmp` と同じ順で返す
+    pub fn entries(&self) -> [(&'static str, u64); 17] {
+        [
+            ("InReceives", self.in_receives),
+            ("InHdrErrors", self.in_hdr_errors),
+            ("InAddrErrors", self.in_addr_errors),
+            ("InUnknownProtos", self.in_unknown_protos),
+            ("InDiscards", self.in_discards),
+            ("InDelivers", self.in_delivers),
+            ("OutRequests", self.out_requests),
+            ("OutDiscards", self.out_discards),
+            ("ReasmTimeout", self.reasm_timeout),
+            ("ReasmReqds", self.reasm_reqds),
+            ("ReasmOKs", self.reasm_oks),
+            ("ReasmFails", self.reasm_fails),
+            ("FragOKs", self.frag_oks),
+            ("FragFails", self.frag_fails),
+            ("FragCreates", self.frag_creates),
+            ("InTruncatedPkts", self.in_truncated_pkts),
+            ("InCsumErrors", self.in_csum_errors),
+        ]
+    }
+}
+
+impl Display for IpStats {
+    /// `/proc/net/snmp` と同じく、名前の行と値の行の2行で出力する
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let entries = self.entries();
+        write!(f, "Ip:")?;
+        for (name, _) in &entries {
+            write!(f, " {}", name)?;
+        }
+        write!(f, "\nIp:")?;
+        for (_, value) in &entries {
+            write!(f, " {}", value)?;
+        }
+        writeln!(f)
+    }
+}
@@ -1,2 +1,5 @@
+pub mod checksum;
+pub mod icmp;
+pub mod icmpv6;
 pub mod ip;
 pub mod tcp;
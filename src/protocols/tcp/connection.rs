@@ -0,0 +1,361 @@
+use crate::protocols::ip::ip_address::IpAddress;
+use crate::protocols::tcp::isn::IsnGenerator;
+use crate::protocols::tcp::retransmission::DEFAULT_MAX_RETRIES;
+use crate::protocols::tcp::rtt::CLOCK_GRANULARITY;
+use crate::protocols::tcp::tcb::{Tcb, segment_len};
+use crate::protocols::tcp::tcp_flags::{TCP_ACK, TCP_RST, TCP_SYN};
+use crate::protocols::tcp::tcp_header::TcpHeader;
+use crate::protocols::tcp::tcp_state::TcpState;
+use crate::protocols::tcp::timer_wheel::TimerWheel;
+use crate::types::bit_stream::BitWriter;
+use crate::types::byte_object::ByteObject;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::time::Instant;
+
+/// コネクションを識別する4つ組。こちら側を `local` とする
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionKey {
+    pub local_address: IpAddress,
+    pub local_port: u16,
+    pub remote_address: IpAddress,
+    pub remote_port: u16,
+}
+
+impl ConnectionKey {
+    /// 受信したセグメントが属するコネクション
+    pub fn incoming(source: IpAddress, destination: IpAddress, header: &TcpHeader) -> Self {
+        ConnectionKey {
+            local_address: destination,
+            local_port: header.destination_port,
+            remote_address: source,
+            remote_port: header.source_port,
+        }
+    }
+}
+
+impl Display for ConnectionKey {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{} <-> {}:{}",
+            self.local_address, self.local_port, self.remote_address, self.remote_port
+        )
+    }
+}
+
+/// 送信するTCPセグメント。チェックサムは `encode` で計算する
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TcpSegment {
+    pub source: IpAddress,
+    pub destination: IpAddress,
+    pub header: TcpHeader,
+    pub payload: Vec<u8>,
+}
+
+impl TcpSegment {
+    /// `key` のこちら側から相手へのセグメントを作る
+    pub fn new(
+        key: &ConnectionKey,
+        seq: u32,
+        ack: u32,
+        flags: u8,
+        window: u16,
+        payload: Vec<u8>,
+    ) -> Self {
+        TcpSegment {
+            source: key.local_address,
+            destination: key.remote_address,
+            header: TcpHeader {
+                source_port: key.local_port,
+                destination_port: key.remote_port,
+                sequence_number: seq,
+                acknowledgment_number: ack,
+                reserved: 0,
+                flags,
+                window_size: window,
+                checksum: 0,
+                urgent_pointer: 0,
+                options: Vec::new(),
+            },
+            payload,
+        }
+    }
+
+    /// TCBのないコネクションへのセグメントに返すRST（RFC 9293 3.10.7.1）。RSTには何も返さない
+    pub fn reset_for(key: &ConnectionKey, header: &TcpHeader, payload_len: usize) -> Option<Self> {
+        if header.flags & TCP_RST != 0 {
+            return None;
+        }
+        Some(if header.flags & TCP_ACK != 0 {
+            TcpSegment::new(key, header.acknowledgment_number, 0, TCP_RST, 0, Vec::new())
+        } else {
+            let ack = header
+                .sequence_number
+                .wrapping_add(segment_len(header, payload_len));
+            TcpSegment::new(key, 0, ack, TCP_RST | TCP_ACK, 0, Vec::new())
+        })
+    }
+
+    /// チェックサムを計算し、ヘッダーとデータを続けたバイト列にする
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = self.header.clone();
+        header.update_checksum(self.source, self.destination, &self.payload);
+        let mut writer = BitWriter::with_capacity(header.header_length() + self.payload.len());
+        header.write_to(&mut writer);
+        writer.write_bytes(&self.payload);
+        writer.finish()
+    }
+}
+
+/// アプリケーションからの操作が受け付けられない理由（RFC 9293 3.10）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpError {
+    ConnectionDoesNotExist,
+    ConnectionAlreadyExists,
+    /// SYNの交換が終わっておらず、まだデータを送れない
+    NotSynchronized,
+    ConnectionClosing,
+}
+
+impl Display for TcpError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            TcpError::ConnectionDoesNotExist => write!(f, "connection does not exist"),
+            TcpError::ConnectionAlreadyExists => write!(f, "connection already exists"),
+            TcpError::NotSynchronized => write!(f, "connection not yet synchronized"),
+            TcpError::ConnectionClosing => write!(f, "connection closing"),
+        }
+    }
+}
+
+impl std::error::Error for TcpError {}
+
+/// 待ち受けているポートと、4つ組ごとのTCB
+#[derive(Debug)]
+pub struct ConnectionTable {
+    listening: HashSet<u16>,
+    connections: HashMap<ConnectionKey, Tcb>,
+    isn: IsnGenerator,
+    /// インターフェースのMTU。こちらのMSSを決める
+    mtu: usize,
+    /// 再送・持続タイマーの期限。TCBの期限が変わるたびに登録し、古い登録は期限が来たときに無視する
+    timers: TimerWheel<ConnectionKey>,
+    /// 続けて再送する回数の上限。超えたらコネクションを中断する
+    max_retries: u32,
+}
+
+impl ConnectionTable {
+    pub fn new() -> Self {
+        ConnectionTable {
+            listening: HashSet::new(),
+            connections: HashMap::new(),
+            isn: IsnGenerator::new(),
+            mtu: 1500,
+            // 100ms刻みで約51秒分のスロットを持つ
+            timers: TimerWheel::new(CLOCK_GRANULARITY, 512, Instant::now()),
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    /// インターフェースのMTUを設定する（既定は1500）
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// 再送回数の上限を設定する（既定は `DEFAULT_MAX_RETRIES`）
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// MTUからIPとTCPの基本ヘッダーを除いた、1セグメントで受け取れるデータの最大長
+    pub fn local_mss(&self, key: &ConnectionKey) -> u16 {
+        let ip_header = match key.local_address {
+            IpAddress::V4(_) => 20,
+            IpAddress::V6(_) => 40,
+        };
+        self.mtu
+            .saturating_sub(ip_header + 20)
+            .min(u16::MAX as usize) as u16
+    }
+
+    /// `port` へのSYNを受け付ける
+    pub fn listen(&mut self, port: u16) {
+        self.listening.insert(port);
+    }
+
+    pub fn get(&self, key: &ConnectionKey) -> Option<&Tcb> {
+        self.connections.get(key)
+    }
+
+    /// コネクションの状態。TCBがなければ待ち受けの有無で LISTEN か CLOSED になる
+    pub fn state(&self, key: &ConnectionKey) -> TcpState {
+        match self.connections.get(key) {
+            Some(tcb) => tcb.state,
+            None if self.listening.contains(&key.local_port) => TcpState::Listen,
+            None => TcpState::Closed,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    /// 能動的に開き、送るSYNを返す
+    pub fn connect(&mut self, key: ConnectionKey, now: Instant) -> Result<TcpSegment, TcpError> {
+        if self.connections.contains_key(&key) {
+            return Err(TcpError::ConnectionAlreadyExists);
+        }
+        let (tcb, syn) = Tcb::connect(key, self.isn.generate(&key, now), self.local_mss(&key), now);
+        self.connections.insert(key, tcb);
+        self.schedule(&key, None);
+        Ok(syn)
+    }
+
+    /// 受信したセグメントを処理し、送り返すセグメントを返す
+    pub fn receive(
+        &mut self,
+        source: IpAddress,
+        destination: IpAddress,
+        header: &TcpHeader,
+        payload: &[u8],
+        now: Instant,
+    ) -> Vec<TcpSegment> {
+        let key = ConnectionKey::incoming(source, destination, header);
+        let Some(tcb) = self.connections.get_mut(&key) else {
+            return self.receive_without_tcb(key, header, payload, now);
+        };
+        let deadline = tcb.deadline();
+        let segments = tcb.on_segment(header, payload, now);
+        self.schedule(&key, deadline);
+        segments
+    }
+
+    /// LISTEN か CLOSED でのセグメントの処理
+    fn receive_without_tcb(
+        &mut self,
+        key: ConnectionKey,
+        header: &TcpHeader,
+        payload: &[u8],
+        now: Instant,
+    ) -> Vec<TcpSegment> {
+        let listening = self.listening.contains(&key.local_port);
+        // LISTEN ではACKを伴わないSYNだけが新しいコネクションを作る
+        if listening && header.flags & (TCP_SYN | TCP_ACK | TCP_RST) == TCP_SYN {
+            let iss = self.isn.generate(&key, now);
+            let (tcb, syn_ack) = Tcb::accept(key, header, iss, self.local_mss(&key), now);
+            self.connections.insert(key, tcb);
+            self.schedule(&key, None);
+            return vec![syn_ack];
+        }
+        // LISTEN ではACKのないセグメントを黙って捨てる
+        if listening && header.flags & TCP_ACK == 0 {
+            return Vec::new();
+        }
+        TcpSegment::reset_for(&key, header, payload.len())
+            .into_iter()
+            .collect()
+    }
+
+    /// 受信したデータを取り出す
+    pub fn read(&mut self, key: &ConnectionKey) -> Result<Vec<u8>, TcpError> {
+        let tcb = self
+            .connections
+            .get_mut(key)
+            .ok_or(TcpError::ConnectionDoesNotExist)?;
+        Ok(tcb.read())
+    }
+
+    /// データを送信バッファに追加し、今送れるセグメントを返す
+    pub fn send(
+        &mut self,
+        key: &ConnectionKey,
+        data: &[u8],
+        now: Instant,
+    ) -> Result<Vec<TcpSegment>, TcpError> {
+        let tcb = self
+            .connections
+            .get_mut(key)
+            .ok_or(TcpError::ConnectionDoesNotExist)?;
+        let deadline = tcb.deadline();
+        let segments = tcb.send(data, now)?;
+        self.schedule(key, deadline);
+        Ok(segments)
+    }
+
+    /// こちらから閉じる。データを送り終えていればFINを送るセグメントを返す
+    pub fn close(
+        &mut self,
+        key: &ConnectionKey,
+        now: Instant,
+    ) -> Result<Vec<TcpSegment>, TcpError> {
+        let tcb = self
+            .connections
+            .get_mut(key)
+            .ok_or(TcpError::ConnectionDoesNotExist)?;
+        let deadline = tcb.deadline();
+        let segments = tcb.close(now)?;
+        self.schedule(key, deadline);
+        Ok(segments)
+    }
+
+    /// 再送タイマーの期限が来たコネクションのセグメントを再送し、
+    /// 持続タイマーの期限が来たコネクションにはウィンドウのプローブを送る
+    ///
+    /// 再送回数の上限を超えたコネクションは取り除き、RSTを返す。
+    pub fn retransmit(&mut self, now: Instant) -> Vec<TcpSegment> {
+        let mut segments = Vec::new();
+        for key in self.timers.expire(now) {
+            let Some(tcb) = self.connections.get_mut(&key) else {
+                continue;
+            };
+            let deadline = tcb.deadline();
+            segments.extend(tcb.on_timer(now, self.max_retries));
+            self.schedule(&key, deadline);
+        }
+        segments
+    }
+
+    /// TCBの操作のあと、閉じていれば取り除き、タイマーの期限が
+    /// `before` から変わっていればタイマーホイールに登録する
+    fn schedule(&mut self, key: &ConnectionKey, before: Option<Instant>) {
+        let Some(tcb) = self.connections.get(key) else {
+            return;
+        };
+        if tcb.state == TcpState::Closed {
+            self.connections.remove(key);
+            return;
+        }
+        if let Some(deadline) = tcb.deadline()
+            && Some(deadline) != before
+        {
+            self.timers.schedule(*key, deadline);
+        }
+    }
+
+    /// TIME-WAIT を終えたコネクションを取り除き、そのキーを返す
+    pub fn expire(&mut self, now: Instant) -> Vec<ConnectionKey> {
+        let expired: Vec<ConnectionKey> = self
+            .connections
+            .values()
+            .filter(|tcb| tcb.is_expired(now))
+            .map(|tcb| tcb.key)
+            .collect();
+        for key in &expired {
+            self.connections.remove(key);
+        }
+        expired
+    }
+}
+
+impl Default for ConnectionTable {
+    fn default() -> Self {
+        ConnectionTable::new()
+    }
+}
@@ -0,0 +1,37 @@
+//! 初期シーケンス番号の生成（RFC 6528）。
+
+use crate::protocols::tcp::connection::ConnectionKey;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Instant;
+
+/// 初期シーケンス番号の生成器
+///
+/// `ISN = M + F(4つ組, 秘密値)` とする。M は4マイクロ秒ごとに1進む時計、
+/// F は起動ごとの秘密値を鍵にした4つ組のハッシュで、コネクションごとにシーケンス空間をずらす。
+#[derive(Clone, Debug)]
+pub struct IsnGenerator {
+    secret: RandomState,
+    /// 時計の起点
+    origin: Instant,
+}
+
+impl IsnGenerator {
+    pub fn new() -> Self {
+        IsnGenerator {
+            secret: RandomState::new(),
+            origin: Instant::now(),
+        }
+    }
+
+    /// `key` のコネクションで使う初期シーケンス番号
+    pub fn generate(&self, key: &ConnectionKey, now: Instant) -> u32 {
+        let clock = (now.saturating_duration_since(self.origin).as_micros() / 4) as u32;
+        clock.wrapping_add(self.secret.hash_one(key) as u32)
+    }
+}
+
+impl Default for IsnGenerator {
+    fn default() -> Self {
+        IsnGenerator::new()
+    }
+}
@@ -1,2 +1,12 @@
+pub mod connection;
+pub mod isn;
+pub mod retransmission;
+pub mod rtt;
+pub mod sequence;
+pub mod send_buffer;
+pub mod tcb;
 pub mod tcp_flags;
 pub mod tcp_header;
+pub mod tcp_option;
+pub mod tcp_state;
+pub mod timer_wheel;
@@ -0,0 +1,130 @@
+//! 再送キューと再送タイマー（RFC 6298）。
+
+use crate::protocols::tcp::rtt::RttEstimator;
+use crate::protocols::tcp::sequence::seq_le;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// 既定の再送回数の上限。RTO が1秒から倍になっていくと、諦めるまでおよそ3分かかる
+pub const DEFAULT_MAX_RETRIES: u32 = 8;
+
+/// 送信して確認応答を待っているセグメント
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SentSegment {
+    seq: u32,
+    /// シーケンス空間での長さ。SYNとFINを含む
+    len: u32,
+    sent_at: Instant,
+    /// 1度でも再送したかどうか。再送したものからは往復時間を測らない
+    retransmitted: bool,
+}
+
+impl SentSegment {
+    fn end(&self) -> u32 {
+        self.seq.wrapping_add(self.len)
+    }
+}
+
+/// コネクションごとの再送キューと再送タイマー
+///
+/// セグメントの中身は `SendBuffer` が持つので、ここではシーケンス番号と送信時刻だけを覚える。
+#[derive(Clone, Debug, Default)]
+pub struct RetransmissionQueue {
+    pub rtt: RttEstimator,
+    segments: VecDeque<SentSegment>,
+    /// 再送タイマーの期限。止まっていれば `None`
+    deadline: Option<Instant>,
+    /// 続けてタイムアウトした回数
+    retries: u32,
+}
+
+impl RetransmissionQueue {
+    pub fn new() -> Self {
+        RetransmissionQueue::default()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// 確認応答を待っているセグメントの数
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// シーケンス空間を使うセグメントを送った。タイマーが止まっていれば動かす（RFC 6298 5.1）
+    pub fn on_send(&mut self, seq: u32, len: u32, now: Instant) {
+        if len == 0 {
+            return;
+        }
+        self.segments.push_back(SentSegment {
+            seq,
+            len,
+            sent_at: now,
+            retransmitted: false,
+        });
+        if self.deadline.is_none() {
+            self.deadline = Some(now + self.rtt.rto());
+        }
+    }
+
+    /// SND.UNA が `ack` まで進んだ。確認応答されたセグメントをキューから外す
+    ///
+    /// 再送していないセグメントだけから往復時間を測る（Karn のアルゴリズム）。
+    /// 再送したセグメントが確認応答されていれば `true` を返す。
+    pub fn on_ack(&mut self, ack: u32, now: Instant) -> bool {
+        let mut acked = None;
+        let mut retransmitted = false;
+        while let Some(front) = self.segments.front()
+            && seq_le(front.end(), ack)
+        {
+            retransmitted |= front.retransmitted;
+            acked = self.segments.pop_front();
+        }
+        if let Some(segment) = acked
+            && !retransmitted
+        {
+            self.rtt
+                .sample(now.saturating_duration_since(segment.sent_at));
+        }
+        self.retries = 0;
+        // 全て確認応答されたら止め、そうでなければ動かし直す（RFC 6298 5.2, 5.3）
+        self.deadline = if self.segments.is_empty() {
+            None
+        } else {
+            Some(now + self.rtt.rto())
+        };
+        retransmitted
+    }
+
+    /// 再送タイマーの期限が来たかどうか
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+
+    /// 期限が来ていれば、先頭のセグメントを再送したことにして `true` を返す
+    ///
+    /// RTO を倍にしてタイマーを動かし直す（RFC 6298 5.4〜5.6）。
+    pub fn on_timeout(&mut self, now: Instant) -> bool {
+        if !self.is_due(now) {
+            return false;
+        }
+        let Some(front) = self.segments.front_mut() else {
+            self.deadline = None;
+            return false;
+        };
+        front.retransmitted = true;
+        self.retries += 1;
+        self.rtt.backoff();
+        self.deadline = Some(now + self.rtt.rto());
+        true
+    }
+}
@@ -0,0 +1,89 @@
+//! 往復時間の推定と再送タイムアウトの計算（RFC 6298）。
+
+use std::time::Duration;
+
+/// 最初の測定までの再送タイムアウト（RFC 6298 2.1）
+pub const INITIAL_RTO: Duration = Duration::from_secs(1);
+
+/// SYNを再送したあと、データを送り始めるときに使う再送タイムアウト（RFC 6298 5.7）
+pub const SYN_RETRANSMITTED_RTO: Duration = Duration::from_secs(3);
+
+/// 再送タイムアウトの下限（RFC 6298 2.4）
+pub const MIN_RTO: Duration = Duration::from_secs(1);
+
+/// 再送タイムアウトの上限（RFC 6298 2.5）
+pub const MAX_RTO: Duration = Duration::from_secs(60);
+
+/// 時計の粒度（G）。再送タイマーを確かめる間隔に合わせる
+pub const CLOCK_GRANULARITY: Duration = Duration::from_millis(100);
+
+/// SRTT、RTTVAR と再送タイムアウト（RTO）
+#[derive(Clone, Debug)]
+pub struct RttEstimator {
+    /// 平滑化した往復時間。まだ測っていなければ `None`
+    srtt: Option<Duration>,
+    /// 往復時間のばらつき
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+        }
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    /// 現在の再送タイムアウト
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// 往復時間の測定値を取り込む（RFC 6298 2.2, 2.3）
+    ///
+    /// 再送したセグメントの確認応答からは測らないこと（Karn のアルゴリズム）。
+    pub fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                // RTTVAR = 3/4 * RTTVAR + 1/4 * |SRTT - R'|
+                // SRTT = 7/8 * SRTT + 1/8 * R'
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(rtt)) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + CLOCK_GRANULARITY.max(self.rttvar * 4)).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    /// タイムアウトしたので再送タイムアウトを倍にする（RFC 6298 5.5）
+    pub fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+    }
+
+    /// SYNを再送して測定値が得られなかったときの初期化（RFC 6298 5.7）
+    pub fn reset_after_syn_retransmission(&mut self) {
+        if self.srtt.is_none() {
+            self.rto = SYN_RETRANSMITTED_RTO;
+        }
+    }
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        RttEstimator::new()
+    }
+}
@@ -0,0 +1,149 @@
+use crate::protocols::tcp::sequence::{seq_le, seq_lt};
+use std::collections::VecDeque;
+
+/// 相手が MSS オプションを送ってこなかったときに仮定する値（RFC 9293 3.7.1）
+pub const DEFAULT_IPV4_MSS: u16 = 536;
+pub const DEFAULT_IPV6_MSS: u16 = 1220;
+
+/// 送信するデータのバッファ
+///
+/// アプリケーションが書いたデータを確認応答されるまで保持し、MSS ごとに区切って
+/// 相手の受信ウィンドウに収まる分だけ送る。SYNとFINもシーケンス空間を1つずつ使う。
+#[derive(Clone, Debug)]
+pub struct SendBuffer {
+    /// 確認応答されていない最初のシーケンス番号（SND.UNA）
+    pub una: u32,
+    /// 次に送るシーケンス番号（SND.NXT）
+    pub nxt: u32,
+    /// 相手が通知した受信ウィンドウ（SND.WND）
+    pub wnd: u32,
+    /// 1つのセグメントに載せるデータの最大長
+    pub mss: u16,
+    /// `una` 以降のデータ。送信済みで確認応答待ちの分と未送信の分を含む
+    data: VecDeque<u8>,
+    /// データの後ろにFINを送るかどうか
+    fin_queued: bool,
+    fin_sent: bool,
+    syn_acked: bool,
+}
+
+/// `SendBuffer::next_segment` が切り出したセグメント
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutgoingData {
+    pub seq: u32,
+    pub data: Vec<u8>,
+    pub fin: bool,
+}
+
+impl SendBuffer {
+    /// SYNを `iss` で送った直後のバッファ
+    pub fn new(iss: u32, mss: u16) -> Self {
+        SendBuffer {
+            una: iss,
+            nxt: iss.wrapping_add(1),
+            wnd: 0,
+            mss,
+            data: VecDeque::new(),
+            fin_queued: false,
+            fin_sent: false,
+            syn_acked: false,
+        }
+    }
+
+    /// 送るデータを追加する
+    pub fn push(&mut self, data: &[u8]) {
+        self.data.extend(data);
+    }
+
+    /// データを送り終えたらFINを送る
+    pub fn queue_fin(&mut self) {
+        self.fin_queued = true;
+    }
+
+    /// 送信済みで確認応答されていないバイト数（SYNとFINを含む）
+    pub fn in_flight(&self) -> u32 {
+        self.nxt.wrapping_sub(self.una)
+    }
+
+    /// まだ送っていないデータのバイト数
+    pub fn unsent(&self) -> usize {
+        self.data.len().saturating_sub(self.in_flight() as usize)
+    }
+
+    /// FINを送り、それが確認応答されたかどうか
+    pub fn fin_acked(&self) -> bool {
+        self.fin_sent && self.in_flight() == 0
+    }
+
+    /// SYNが確認応答されたかどうか
+    pub fn syn_acked(&self) -> bool {
+        self.syn_acked
+    }
+
+    /// `una < ack <= nxt` なら確認応答されたデータを捨てて `true` を返す
+    pub fn acknowledge(&mut self, ack: u32) -> bool {
+        if !(seq_lt(self.una, ack) && seq_le(ack, self.nxt)) {
+            return false;
+        }
+        let acked = ack.wrapping_sub(self.una) as usize;
+        self.data.drain(..acked.min(self.data.len()));
+        self.una = ack;
+        self.syn_acked = true;
+        true
+    }
+
+    /// 次に送るセグメントを切り出し、`nxt` を進める
+    ///
+    /// データは MSS と送信ウィンドウの残りに収まる分だけ取り出す。
+    /// FINは全てのデータを送り終えたときに付ける。送るものがなければ `None` を返す。
+    pub fn next_segment(&mut self) -> Option<OutgoingData> {
+        let sent = self.in_flight() as usize;
+        if self.fin_sent || !self.syn_acked {
+            // FINまで送り終えたか、SYNの確認応答を待っている
+            return None;
+        }
+        let usable = self.wnd.saturating_sub(self.in_flight()) as usize;
+        let len = (self.data.len() - sent).min(self.mss as usize).min(usable);
+        let fin = self.fin_queued && sent + len == self.data.len();
+        if len == 0 && !fin {
+            return None;
+        }
+        let seq = self.nxt;
+        let data = self.data.range(sent..sent + len).copied().collect();
+        self.nxt = self.nxt.wrapping_add(len as u32 + fin as u32);
+        self.fin_sent = fin;
+        Some(OutgoingData { seq, data, fin })
+    }
+
+    /// 相手の受信ウィンドウが0のときに送るプローブ（RFC 9293 3.8.6.1）
+    ///
+    /// ウィンドウの外にある未送信データの1バイトを送り、`nxt` を進める。
+    /// 前に送ったプローブが確認応答されていなければ、それをもう一度切り出す。
+    pub fn window_probe(&mut self) -> Option<OutgoingData> {
+        if self.in_flight() == 0 {
+            if !self.syn_acked || self.unsent() == 0 {
+                return None;
+            }
+            self.nxt = self.nxt.wrapping_add(1);
+        }
+        self.retransmission()
+    }
+
+    /// 確認応答されていない先頭のセグメントを、`nxt` を動かさずにもう一度切り出す
+    ///
+    /// SYNの再送は扱わない。送信済みのものがなければ `None` を返す。
+    pub fn retransmission(&self) -> Option<OutgoingData> {
+        if !self.syn_acked || self.in_flight() == 0 {
+            return None;
+        }
+        // FINは最後に送るので、送っていれば確認応答されていない
+        let sent = self.in_flight() as usize - self.fin_sent as usize;
+        let len = sent.min(self.mss as usize);
+        let data = self.data.range(..len).copied().collect();
+        Some(OutgoingData {
+            seq: self.una,
+            data,
+            fin: self.fin_sent && len == sent,
+        })
+    }
+}
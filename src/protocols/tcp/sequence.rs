@@ -0,0 +1,26 @@
+//! 32ビットで一周するシーケンス番号の比較（RFC 9293 3.4）。
+
+/// `a < b`。差が 2^31 未満の範囲で比較する
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// `a <= b`
+pub fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+/// `a > b`
+pub fn seq_gt(a: u32, b: u32) -> bool {
+    seq_lt(b, a)
+}
+
+/// `a >= b`
+pub fn seq_ge(a: u32, b: u32) -> bool {
+    seq_le(b, a)
+}
+
+/// `start <= seq < start + len`
+pub fn seq_in_window(seq: u32, start: u32, len: u32) -> bool {
+    seq.wrapping_sub(start) < len
+}
@@ -0,0 +1,518 @@
+use crate::protocols::ip::ip_address::IpAddress;
+use crate::protocols::tcp::connection::{ConnectionKey, TcpError, TcpSegment};
+use crate::protocols::tcp::retransmission::RetransmissionQueue;
+use crate::protocols::tcp::rtt::MAX_RTO;
+use crate::protocols::tcp::send_buffer::{
+    DEFAULT_IPV4_MSS, DEFAULT_IPV6_MSS, OutgoingData, SendBuffer,
+};
+use crate::protocols::tcp::sequence::{seq_gt, seq_in_window, seq_le, seq_lt};
+use crate::protocols::tcp::tcp_flags::{TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN};
+use crate::protocols::tcp::tcp_header::TcpHeader;
+use crate::protocols::tcp::tcp_option::TcpOption;
+use crate::protocols::tcp::tcp_state::TcpState;
+use std::time::{Duration, Instant};
+
+/// セグメントの最大生存時間（MSL）。RFC 9293 は2分とするが、Linux に合わせて短くする
+pub const MAXIMUM_SEGMENT_LIFETIME: Duration = Duration::from_secs(30);
+
+/// TIME-WAIT に留まる時間（2MSL）
+pub const TIME_WAIT_DURATION: Duration =
+    Duration::from_secs(2 * MAXIMUM_SEGMENT_LIFETIME.as_secs());
+
+/// 受信バッファの大きさ。アプリケーションが読んでいない分だけ受信ウィンドウが縮む
+pub const RECEIVE_BUFFER_SIZE: u32 = 65535;
+
+/// 受信したセグメントのシーケンス空間での長さ（SEG.LEN）。SYNとFINもそれぞれ1つ数える
+pub fn segment_len(header: &TcpHeader, payload_len: usize) -> u32 {
+    let control = (header.flags & TCP_SYN != 0) as u32 + (header.flags & TCP_FIN != 0) as u32;
+    payload_len as u32 + control
+}
+
+/// コネクションごとの状態（Transmission Control Block, RFC 9293 3.3.1）
+///
+/// 変数名はRFCの SND.UNA などに対応する。
+#[derive(Clone, Debug)]
+pub struct Tcb {
+    pub key: ConnectionKey,
+    pub state: TcpState,
+    /// 初期送信シーケンス番号（ISS）
+    pub iss: u32,
+    /// SND.UNA、SND.NXT、SND.WND と送信するデータ
+    pub snd: SendBuffer,
+    /// 最後にウィンドウを更新したセグメントの SEG.SEQ
+    pub snd_wl1: u32,
+    /// 最後にウィンドウを更新したセグメントの SEG.ACK
+    pub snd_wl2: u32,
+    /// 相手の初期シーケンス番号（IRS）
+    pub irs: u32,
+    /// 次に受け取るシーケンス番号
+    pub rcv_nxt: u32,
+    /// 確認応答を待っているセグメントと再送タイマー
+    pub retransmission: RetransmissionQueue,
+    /// 相手の受信ウィンドウが0の間、プローブを送る時刻（持続タイマー）
+    persist_deadline: Option<Instant>,
+    /// 持続タイマーの間隔。プローブを送るたびに倍にする
+    persist_timeout: Duration,
+    /// こちらのMTUから決まるMSS。SYNで通知する
+    local_mss: u16,
+    /// LISTEN から開いたかどうか。SYN-RECEIVED でリセットされたら LISTEN に戻る
+    passive: bool,
+    /// 受信したがアプリケーションがまだ読んでいないデータ
+    received: Vec<u8>,
+    /// TIME-WAIT を抜ける時刻
+    time_wait_until: Option<Instant>,
+}
+
+impl Tcb {
+    fn new(key: ConnectionKey, state: TcpState, iss: u32, local_mss: u16, passive: bool) -> Self {
+        Tcb {
+            key,
+            state,
+            iss,
+            snd: SendBuffer::new(iss, local_mss),
+            snd_wl1: 0,
+            snd_wl2: 0,
+            irs: 0,
+            rcv_nxt: 0,
+            retransmission: RetransmissionQueue::new(),
+            persist_deadline: None,
+            persist_timeout: Duration::ZERO,
+            local_mss,
+            passive,
+            received: Vec::new(),
+            time_wait_until: None,
+        }
+    }
+
+    /// LISTEN でSYNを受け取り、SYN-RECEIVED のTCBとSYN-ACKを作る
+    ///
+    /// `local_mss` はこちらのMTUから決まるMSSで、相手のMSSオプションと小さい方を使う。
+    pub fn accept(
+        key: ConnectionKey,
+        syn: &TcpHeader,
+        iss: u32,
+        local_mss: u16,
+        now: Instant,
+    ) -> (Self, TcpSegment) {
+        let mut tcb = Tcb::new(key, TcpState::SynReceived, iss, local_mss, true);
+        tcb.irs = syn.sequence_number;
+        tcb.rcv_nxt = syn.sequence_number.wrapping_add(1);
+        tcb.negotiate_mss(syn);
+        tcb.update_window(syn);
+        tcb.retransmission.on_send(tcb.iss, 1, now);
+        let syn_ack = tcb.syn();
+        (tcb, syn_ack)
+    }
+
+    /// 能動的に開く。SYN-SENT のTCBとSYNを作る
+    pub fn connect(
+        key: ConnectionKey,
+        iss: u32,
+        local_mss: u16,
+        now: Instant,
+    ) -> (Self, TcpSegment) {
+        let mut tcb = Tcb::new(key, TcpState::SynSent, iss, local_mss, false);
+        tcb.retransmission.on_send(tcb.iss, 1, now);
+        let syn = tcb.syn();
+        (tcb, syn)
+    }
+
+    /// 受信ウィンドウ（RCV.WND）
+    pub fn rcv_wnd(&self) -> u32 {
+        RECEIVE_BUFFER_SIZE - self.received.len() as u32
+    }
+
+    /// 再送タイマーか持続タイマーの、早い方の期限
+    pub fn deadline(&self) -> Option<Instant> {
+        match (self.retransmission.deadline(), self.persist_deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// TIME-WAIT の期限が過ぎたかどうか
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.time_wait_until.is_some_and(|until| until <= now)
+    }
+
+    /// 受信してまだ読んでいないデータ
+    pub fn received(&self) -> &[u8] {
+        &self.received
+    }
+
+    /// 受信したデータを取り出す
+    pub fn read(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.received)
+    }
+
+    /// データを送信バッファに追加し、今送れるセグメントを返す
+    pub fn send(&mut self, data: &[u8], now: Instant) -> Result<Vec<TcpSegment>, TcpError> {
+        if !self.state.can_send() {
+            return Err(self.unusable());
+        }
+        self.snd.push(data);
+        Ok(self.transmit(now))
+    }
+
+    /// こちらから閉じる。バッファのデータを送り終えたらFINを送る
+    pub fn close(&mut self, now: Instant) -> Result<Vec<TcpSegment>, TcpError> {
+        let next = match self.state {
+            TcpState::SynSent => {
+                self.state = TcpState::Closed;
+                return Ok(Vec::new());
+            }
+            TcpState::SynReceived | TcpState::Established => TcpState::FinWait1,
+            TcpState::CloseWait => TcpState::LastAck,
+            _ => return Err(TcpError::ConnectionClosing),
+        };
+        self.state = next;
+        self.snd.queue_fin();
+        Ok(self.transmit(now))
+    }
+
+    /// 送信バッファから、MSS と相手の受信ウィンドウに収まるセグメントを切り出す
+    pub fn transmit(&mut self, now: Instant) -> Vec<TcpSegment> {
+        let mut segments = Vec::new();
+        while let Some(outgoing) = self.snd.next_segment() {
+            let len = outgoing.data.len() as u32 + outgoing.fin as u32;
+            self.retransmission.on_send(outgoing.seq, len, now);
+            segments.push(self.data_segment(outgoing));
+        }
+        self.update_persist_timer(now);
+        segments
+    }
+
+    /// 再送タイマーの期限が来ていれば、確認応答されていない先頭のセグメントを再送する
+    ///
+    /// 再送が `max_retries` 回続いたらコネクションを中断し、`state` を `TcpState::Closed` にして
+    /// 同期済みならRSTを返す。持続タイマーの期限が来ていればウィンドウのプローブを送る。
+    pub fn on_timer(&mut self, now: Instant, max_retries: u32) -> Vec<TcpSegment> {
+        if self
+            .persist_deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            return self.probe_window(now);
+        }
+        if !self.retransmission.is_due(now) {
+            return Vec::new();
+        }
+        if self.retransmission.retries() >= max_retries {
+            return self.abort();
+        }
+        if !self.retransmission.on_timeout(now) {
+            return Vec::new();
+        }
+        if !self.snd.syn_acked() {
+            return vec![self.syn()];
+        }
+        self.snd
+            .retransmission()
+            .map(|outgoing| self.data_segment(outgoing))
+            .into_iter()
+            .collect()
+    }
+
+    /// 相手の受信ウィンドウが0で送れないデータが残っていれば、持続タイマーを動かす
+    ///
+    /// ウィンドウを開くACKが失われても止まらないよう、プローブを送り続ける（RFC 9293 3.8.6.1）。
+    fn update_persist_timer(&mut self, now: Instant) {
+        let blocked = self.snd.wnd == 0
+            && self.retransmission.is_empty()
+            && (self.snd.unsent() > 0 || self.snd.in_flight() > 0);
+        if !blocked {
+            self.persist_deadline = None;
+        } else if self.persist_deadline.is_none() {
+            self.persist_timeout = self.retransmission.rtt.rto();
+            self.persist_deadline = Some(now + self.persist_timeout);
+        }
+    }
+
+    /// 1バイトのプローブを送り、持続タイマーの間隔を倍にする。相手が応答する限り諦めない
+    fn probe_window(&mut self, now: Instant) -> Vec<TcpSegment> {
+        self.persist_timeout = (self.persist_timeout * 2).min(MAX_RTO);
+        self.persist_deadline = Some(now + self.persist_timeout);
+        self.snd
+            .window_probe()
+            .map(|outgoing| self.data_segment(outgoing))
+            .into_iter()
+            .collect()
+    }
+
+    /// コネクションを中断する（RFC 9293 3.10.5）。SYN-SENT 以外ではRSTを送る
+    fn abort(&mut self) -> Vec<TcpSegment> {
+        let state = std::mem::replace(&mut self.state, TcpState::Closed);
+        if state == TcpState::SynSent {
+            return Vec::new();
+        }
+        vec![TcpSegment::new(
+            &self.key,
+            self.snd.nxt,
+            0,
+            TCP_RST,
+            0,
+            Vec::new(),
+        )]
+    }
+
+    /// 受信したセグメントを処理し、送り返すセグメントを返す（RFC 9293 3.10.7）
+    ///
+    /// コネクションが終わった場合は `state` が `TcpState::Closed` になる。
+    pub fn on_segment(
+        &mut self,
+        header: &TcpHeader,
+        payload: &[u8],
+        now: Instant,
+    ) -> Vec<TcpSegment> {
+        if self.state == TcpState::SynSent {
+            return self.on_syn_sent(header, now);
+        }
+        let seg_seq = header.sequence_number;
+        let seg_ack = header.acknowledgment_number;
+        let syn = header.flags & TCP_SYN != 0;
+        let fin = header.flags & TCP_FIN != 0;
+
+        // 1. シーケンス番号の検査
+        if !self.is_acceptable(seg_seq, segment_len(header, payload.len())) {
+            if header.flags & TCP_RST != 0 {
+                return Vec::new();
+            }
+            // 再送されたFINなら TIME-WAIT をやり直す
+            if self.state == TcpState::TimeWait && fin {
+                self.time_wait_until = Some(now + TIME_WAIT_DURATION);
+            }
+            return vec![self.ack()];
+        }
+
+        // 2. RST。ウィンドウ内でも RCV.NXT と一致しなければ確認のACKを返す（RFC 5961 3.2）
+        if header.flags & TCP_RST != 0 {
+            if seg_seq != self.rcv_nxt {
+                return vec![self.ack()];
+            }
+            self.state = TcpState::Closed;
+            return Vec::new();
+        }
+
+        // 4. SYN。同期後のSYNには確認のACKを返す（RFC 5961 4.2）
+        if syn {
+            if self.state == TcpState::SynReceived && self.passive {
+                self.state = TcpState::Closed;
+                return Vec::new();
+            }
+            return vec![self.ack()];
+        }
+
+        // 5. ACK
+        if header.flags & TCP_ACK == 0 {
+            return Vec::new();
+        }
+        if self.state == TcpState::SynReceived {
+            if !(seq_lt(self.snd.una, seg_ack) && seq_le(seg_ack, self.snd.nxt)) {
+                return vec![TcpSegment::new(
+                    &self.key,
+                    seg_ack,
+                    0,
+                    TCP_RST,
+                    0,
+                    Vec::new(),
+                )];
+            }
+            self.state = TcpState::Established;
+            self.acknowledge(seg_ack, now);
+            self.update_window(header);
+        }
+        if seq_gt(seg_ack, self.snd.nxt) {
+            // まだ送っていない範囲への確認応答
+            return vec![self.ack()];
+        }
+        if seq_le(self.snd.una, seg_ack) {
+            self.acknowledge(seg_ack, now);
+            if seq_lt(self.snd_wl1, seg_seq)
+                || (self.snd_wl1 == seg_seq && seq_le(self.snd_wl2, seg_ack))
+            {
+                self.update_window(header);
+            }
+        }
+        let fin_acked = self.snd.fin_acked();
+        match self.state {
+            TcpState::FinWait1 if fin_acked => self.state = TcpState::FinWait2,
+            TcpState::Closing if fin_acked => self.enter_time_wait(now),
+            TcpState::LastAck if fin_acked => {
+                self.state = TcpState::Closed;
+                return Vec::new();
+            }
+            _ => {}
+        }
+
+        // 7. データ。順番どおりのものだけを受け取り、先に届いたものは捨てて再送を待つ
+        let mut needs_ack = false;
+        if self.state.can_receive() && !payload.is_empty() {
+            if seq_gt(seg_seq, self.rcv_nxt) {
+                return vec![self.ack()];
+            }
+            let skip = self.rcv_nxt.wrapping_sub(seg_seq) as usize;
+            let data = &payload[skip.min(payload.len())..];
+            let data = &data[..data.len().min(self.rcv_wnd() as usize)];
+            self.received.extend_from_slice(data);
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(data.len() as u32);
+            needs_ack = true;
+        }
+
+        // 8. FIN。データを全て受け取れた場合だけ処理する
+        if fin && seg_seq.wrapping_add(payload.len() as u32) == self.rcv_nxt {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            needs_ack = true;
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 if fin_acked => self.enter_time_wait(now),
+                TcpState::FinWait1 => self.state = TcpState::Closing,
+                TcpState::FinWait2 | TcpState::TimeWait => self.enter_time_wait(now),
+                _ => {}
+            }
+        }
+
+        // ウィンドウが開いていれば続きを送る。送るものがあればそれが確認応答を兼ねる
+        let segments = self.transmit(now);
+        if segments.is_empty() && needs_ack {
+            return vec![self.ack()];
+        }
+        segments
+    }
+
+    /// SYN-SENT でのセグメントの処理
+    fn on_syn_sent(&mut self, header: &TcpHeader, now: Instant) -> Vec<TcpSegment> {
+        let seg_ack = header.acknowledgment_number;
+        let ack = header.flags & TCP_ACK != 0;
+        let rst = header.flags & TCP_RST != 0;
+        if ack && (seq_le(seg_ack, self.iss) || seq_gt(seg_ack, self.snd.nxt)) {
+            if rst {
+                return Vec::new();
+            }
+            return vec![TcpSegment::new(
+                &self.key,
+                seg_ack,
+                0,
+                TCP_RST,
+                0,
+                Vec::new(),
+            )];
+        }
+        if rst {
+            // こちらのSYNへの確認応答を伴うRSTだけが接続の拒否を表す
+            if ack {
+                self.state = TcpState::Closed;
+            }
+            return Vec::new();
+        }
+        if header.flags & TCP_SYN == 0 {
+            return Vec::new();
+        }
+        self.irs = header.sequence_number;
+        self.rcv_nxt = header.sequence_number.wrapping_add(1);
+        self.negotiate_mss(header);
+        self.update_window(header);
+        if ack {
+            self.acknowledge(seg_ack, now);
+            self.state = TcpState::Established;
+            return vec![self.ack()];
+        }
+        // 同時オープン
+        self.state = TcpState::SynReceived;
+        vec![self.syn()]
+    }
+
+    /// SND.UNA を進め、確認応答されたセグメントを再送キューから外す
+    fn acknowledge(&mut self, seg_ack: u32, now: Instant) {
+        let syn_acked = self.snd.syn_acked();
+        if !self.snd.acknowledge(seg_ack) {
+            return;
+        }
+        let retransmitted = self.retransmission.on_ack(seg_ack, now);
+        if !syn_acked && retransmitted {
+            self.retransmission.rtt.reset_after_syn_retransmission();
+        }
+    }
+
+    /// セグメントがウィンドウ内にあるかどうか（RFC 9293 3.10.7.4）
+    fn is_acceptable(&self, seg_seq: u32, seg_len: u32) -> bool {
+        let rcv_wnd = self.rcv_wnd();
+        match (seg_len, rcv_wnd) {
+            (0, 0) => seg_seq == self.rcv_nxt,
+            (0, _) => seq_in_window(seg_seq, self.rcv_nxt, rcv_wnd),
+            (_, 0) => false,
+            _ => {
+                seq_in_window(seg_seq, self.rcv_nxt, rcv_wnd)
+                    || seq_in_window(seg_seq.wrapping_add(seg_len - 1), self.rcv_nxt, rcv_wnd)
+            }
+        }
+    }
+
+    fn update_window(&mut self, header: &TcpHeader) {
+        self.snd.wnd = header.window_size as u32;
+        self.snd_wl1 = header.sequence_number;
+        self.snd_wl2 = header.acknowledgment_number;
+    }
+
+    /// 相手のSYNの MSS オプションと小さい方を使う。なければ既定値を仮定する
+    fn negotiate_mss(&mut self, syn: &TcpHeader) {
+        let default = match self.key.remote_address {
+            IpAddress::V4(_) => DEFAULT_IPV4_MSS,
+            IpAddress::V6(_) => DEFAULT_IPV6_MSS,
+        };
+        let peer_mss = syn.mss().unwrap_or(default);
+        self.snd.mss = self.snd.mss.min(peer_mss);
+    }
+
+    fn enter_time_wait(&mut self, now: Instant) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_until = Some(now + TIME_WAIT_DURATION);
+    }
+
+    /// 送れない理由
+    fn unusable(&self) -> TcpError {
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived => TcpError::NotSynchronized,
+            _ => TcpError::ConnectionClosing,
+        }
+    }
+
+    /// 通知する受信ウィンドウ
+    fn window(&self) -> u16 {
+        self.rcv_wnd().min(u16::MAX as u32) as u16
+    }
+
+    /// `<SEQ=SND.NXT><ACK=RCV.NXT><CTL=ACK>`
+    fn ack(&self) -> TcpSegment {
+        self.segment(self.snd.nxt, TCP_ACK, Vec::new())
+    }
+
+    /// SYN-SENT ならSYN、それ以外はSYN-ACK。こちらの MSS を通知する
+    fn syn(&self) -> TcpSegment {
+        let mut syn = if self.state == TcpState::SynSent {
+            TcpSegment::new(&self.key, self.iss, 0, TCP_SYN, self.window(), Vec::new())
+        } else {
+            self.segment(self.iss, TCP_SYN | TCP_ACK, Vec::new())
+        };
+        syn.header
+            .set_options(vec![TcpOption::MaximumSegmentSize(self.local_mss)])
+            .expect("MSS option fits in the header");
+        syn
+    }
+
+    /// 送信バッファから切り出したデータのセグメント
+    fn data_segment(&self, outgoing: OutgoingData) -> TcpSegment {
+        let mut flags = TCP_ACK;
+        if outgoing.fin {
+            flags |= TCP_FIN;
+        }
+        // バッファの最後まで送るセグメントには PSH を立てる
+        if !outgoing.data.is_empty() && self.snd.unsent() == 0 {
+            flags |= TCP_PSH;
+        }
+        self.segment(outgoing.seq, flags, outgoing.data)
+    }
+
+    /// RCV.NXT を確認応答するセグメント
+    fn segment(&self, seq: u32, flags: u8, payload: Vec<u8>) -> TcpSegment {
+        TcpSegment::new(&self.key, seq, self.rcv_nxt, flags, self.window(), payload)
+    }
+}
@@ -6,3 +6,52 @@ pub const TCP_ACK: u8 = 0b0001_0000;
 pub const TCP_URG: u8 = 0b0010_0000;
 pub const TCP_ECE: u8 = 0b0100_0000;
 pub const TCP_CWR: u8 = 0b1000_0000;
+
+/// フラグとその名前の対応（下位ビットから順）
+pub const TCP_FLAG_NAMES: [(u8, &str); 8] = [
+    (TCP_FIN, "FIN"),
+    (TCP_SYN, "SYN"),
+    (TCP_RST, "RST"),
+    (TCP_PSH, "PSH"),
+    (TCP_ACK, "ACK"),
+    (TCP_URG, "URG"),
+    (TCP_ECE, "ECE"),
+    (TCP_CWR, "CWR"),
+];
+
+/// 立っているフラグの名前を返す（例: `["SYN", "ACK"]`）
+pub fn flag_names(flags: u8) -> Vec<&'static str> {
+    TCP_FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// フラグ名の並びからフラグの値を組み立てる。未知の名前があればその名前を返す
+pub fn flags_from_names<S: AsRef<str>>(names: &[S]) -> Result<u8, String> {
+    names.iter().try_fold(0, |flags, name| {
+        let name = name.as_ref();
+        TCP_FLAG_NAMES
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(flag, _)| flags | flag)
+            .ok_or_else(|| name.to_string())
+    })
+}
+
+/// フラグを名前の配列としてシリアライズする（`#[serde(with = "...")]` 用）
+#[cfg(feature = "serde")]
+pub mod serde_names {
+    use super::{flag_names, flags_from_names};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(flags: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(flag_names(*flags))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        flags_from_names(&names).map_err(|name| D::Error::custom(format!("unknown TCP flag: {}", name)))
+    }
+}
@@ -1,35 +1,87 @@
-use crate::protocols::ip::ipv4_address::IPv4Address;
-use crate::types::bit_stream::{BitStream, Bits, BitsCompatible};
+use crate::protocols::checksum;
+use crate::protocols::ip::ip_address::IpAddress;
+use crate::protocols::tcp::tcp_option::{TcpOption, TCP_MAX_WINDOW_SCALE};
+use crate::types::bit_stream::{BitStream, BitWriter};
 use crate::types::byte_object::ByteObject;
+use crate::types::field_layout::{read_fields, write_fields};
+use crate::types::parse_error::ParseError;
 use std::fmt::{Display, Formatter};
 
+/// TCPヘッダー
+///
+/// Data Offset はオプションから導出するためフィールドとしては持たない。`data_offset()` を参照すること。
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TcpHeader {
     pub source_port: u16,
     pub destination_port: u16,
     pub sequence_number: u32,
     pub acknowledgment_number: u32,
-    pub data_offset: u8,
     pub reserved: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::protocols::tcp::tcp_flags::serde_names"))]
     pub flags: u8,
     pub window_size: u16,
     pub checksum: u16,
     pub urgent_pointer: u16,
+    /// オプション。書き出すときは4バイト境界までEnd（0）で埋める
+    pub options: Vec<TcpOption>,
+}
+
+/// オプションを含めたヘッダー長の上限（バイト）
+pub const TCP_MAX_HEADER_LENGTH: usize = 60;
+
+/// オプションが Data Offset で表せる40バイトに収まらない
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionsTooLong {
+    /// パディングを含めたオプションのバイト長
+    pub length: usize,
+}
+
+impl Display for OptionsTooLong {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "TCP options take {} bytes, exceeding {}",
+            self.length,
+            TCP_MAX_HEADER_LENGTH - 20
+        )
+    }
+}
+
+impl std::error::Error for OptionsTooLong {}
+
+/// ヘッダーの固定部分（オプションより前）のフィールド配置
+pub mod layout {
+    use crate::types::field_layout::FieldSpec;
+
+    pub const SOURCE_PORT: FieldSpec = FieldSpec::new("Source Port", 16);
+    pub const DESTINATION_PORT: FieldSpec = FieldSpec::new("Destination Port", 16);
+    pub const SEQUENCE_NUMBER: FieldSpec = FieldSpec::new("Sequence Number", 32);
+    pub const ACKNOWLEDGMENT_NUMBER: FieldSpec = FieldSpec::new("Acknowledgment Number", 32);
+    pub const DATA_OFFSET: FieldSpec = FieldSpec::new("Header Length", 4);
+    pub const RESERVED: FieldSpec = FieldSpec::new("Reserved", 4);
+    pub const FLAGS: FieldSpec = FieldSpec::new("Flags", 8);
+    pub const WINDOW: FieldSpec = FieldSpec::new("Window", 16);
+    pub const CHECKSUM: FieldSpec = FieldSpec::new("Checksum", 16);
+    pub const URGENT_POINTER: FieldSpec = FieldSpec::new("Urgent Pointer", 16);
+
+    pub const FIXED: [FieldSpec; 10] = [
+        SOURCE_PORT,
+        DESTINATION_PORT,
+        SEQUENCE_NUMBER,
+        ACKNOWLEDGMENT_NUMBER,
+        DATA_OFFSET,
+        RESERVED,
+        FLAGS,
+        WINDOW,
+        CHECKSUM,
+        URGENT_POINTER,
+    ];
 }
 
 impl ByteObject for TcpHeader {
-    fn from_stream(src: &mut BitStream) -> Self {
-        let source_port = src.pop(16).to_u16();
-        let destination_port = src.pop(16).to_u16();
-        let sequence_number = src.pop(32).to_u32();
-        let acknowledgment_number = src.pop(32).to_u32();
-        let data_offset = src.pop(4).to_u8();
-        let reserved = src.pop(4).to_u8();
-        let flags = src.pop(8).to_u8();
-        let window_size = src.pop(16).to_u16();
-        let checksum = src.pop(16).to_u16();
-        let urgent_pointer = src.pop(16).to_u16();
-
-        TcpHeader {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let [
             source_port,
             destination_port,
             sequence_number,
@@ -40,120 +92,174 @@ impl ByteObject for TcpHeader {
             window_size,
             checksum,
             urgent_pointer,
+        ] = read_fields(src, &layout::FIXED)?;
+        let data_offset = data_offset as u8;
+        if data_offset < 5 {
+            return Err(ParseError::BadHeaderLength(data_offset));
         }
+
+        let mut options_stream = src.sub_stream((data_offset as usize - 5) * 32)?;
+        let options = TcpOption::parse_list(&mut options_stream)?;
+
+        Ok(TcpHeader {
+            source_port: source_port as u16,
+            destination_port: destination_port as u16,
+            sequence_number: sequence_number as u32,
+            acknowledgment_number: acknowledgment_number as u32,
+            reserved: reserved as u8,
+            flags: flags as u8,
+            window_size: window_size as u16,
+            checksum: checksum as u16,
+            urgent_pointer: urgent_pointer as u16,
+            options,
+        })
     }
-    fn to_bits(&self) -> Bits {
-        let mut bits = Bits::new();
-        bits.append(&self.source_port.to_bits());
-        bits.append(&self.destination_port.to_bits());
-        bits.append(&self.sequence_number.to_bits());
-        bits.append(&self.acknowledgment_number.to_bits());
-        bits.append(&self.data_offset.to_bits()[4..].to_bits());
-        bits.append(&self.reserved.to_bits()[4..].to_bits());
-        bits.append(&self.flags.to_bits());
-        bits.append(&self.window_size.to_bits());
-        bits.append(&self.checksum.to_bits());
-        bits.append(&self.urgent_pointer.to_bits());
-        bits
+
+    /// `options` を直接書き換えて40バイトを超えた場合はパニックする。`set_options` を使うこと
+    fn write_to(&self, writer: &mut BitWriter) {
+        assert!(
+            self.header_length() <= TCP_MAX_HEADER_LENGTH,
+            "TCP options exceed 40 bytes"
+        );
+        let start = writer.byte_len();
+        write_fields(
+            writer,
+            &layout::FIXED,
+            [
+                self.source_port as u64,
+                self.destination_port as u64,
+                self.sequence_number as u64,
+                self.acknowledgment_number as u64,
+                self.data_offset() as u64,
+                self.reserved as u64,
+                self.flags as u64,
+                self.window_size as u64,
+                self.checksum as u64,
+                self.urgent_pointer as u64,
+            ],
+        );
+        for option in &self.options {
+            option.write_to(writer);
+        }
+        while writer.byte_len() - start < self.header_length() {
+            writer.write_u8(0);
+        }
     }
 }
 
 impl TcpHeader {
+    /// オプションのバイト長（パディングを含まない）
+    pub fn options_length(&self) -> usize {
+        self.options.iter().map(TcpOption::encoded_len).sum()
+    }
+
+    /// ヘッダー長（バイト）。オプションを4バイト境界まで埋めた長さを含む
+    pub fn header_length(&self) -> usize {
+        20 + self.options_length().div_ceil(4) * 4
+    }
+
+    /// Data Offset（32ビットワード単位のヘッダー長）
+    pub fn data_offset(&self) -> u8 {
+        (self.header_length() / 4) as u8
+    }
+
+    /// オプションを置き換える。パディングを含めて40バイトに収まらなければ、元のまま残してエラーにする。
+    /// チェックサムは更新しないため、必要なら `update_checksum` を呼ぶこと
+    pub fn set_options(&mut self, options: Vec<TcpOption>) -> Result<(), OptionsTooLong> {
+        let length = options.iter().map(TcpOption::encoded_len).sum::<usize>().div_ceil(4) * 4;
+        if 20 + length > TCP_MAX_HEADER_LENGTH {
+            return Err(OptionsTooLong { length });
+        }
+        self.options = options;
+        Ok(())
+    }
+
+    /// MSS オプションの値
+    pub fn mss(&self) -> Option<u16> {
+        self.options.iter().find_map(|option| match option {
+            TcpOption::MaximumSegmentSize(mss) => Some(*mss),
+            _ => None,
+        })
+    }
+
+    /// Window Scale オプションのシフト量。14を超える値は14として扱う（RFC 7323 2.3節）
+    pub fn window_scale(&self) -> Option<u8> {
+        self.options.iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some((*shift).min(TCP_MAX_WINDOW_SCALE)),
+            _ => None,
+        })
+    }
+
     /// TCPセグメントのチェックサムを計算する
-    /// RFC 793に従って、IPv4疑似ヘッダー + TCPヘッダー + データの16ビット単位の1の補数の和を計算する
+    /// RFC 793に従って、疑似ヘッダー + TCPヘッダー + データの16ビット単位の1の補数の和を計算する。
+    /// 疑似ヘッダーはアドレスのバージョンに応じてIPv4用（RFC 793）かIPv6用（RFC 8200）を使う
     pub fn calculate_checksum(
         &self,
-        src_ip: &IPv4Address,
-        dst_ip: &IPv4Address,
+        src_ip: impl Into<IpAddress>,
+        dst_ip: impl Into<IpAddress>,
         tcp_data: &[u8],
     ) -> u16 {
-        let mut sum: u32 = 0;
-
-        // IPv4疑似ヘッダーの計算
-        // Source IP Address (32 bits = 2 x 16 bits)
-        let src_bytes = &src_ip.address;
-        sum += src_bytes.to_u16s()[0] as u32;
-        sum += src_bytes.to_u16s()[1] as u32;
-
-        // Destination IP Address (32 bits = 2 x 16 bits)
-        let dst_bytes = &dst_ip.address;
-        sum += dst_bytes.to_u16s()[0] as u32;
-        sum += dst_bytes.to_u16s()[1] as u32;
-
-        // Protocol (TCP = 6)
-        sum += 6u16 as u32;
-
-        // TCP Length (TCP Header + Data)
-        let tcp_length = 20 + tcp_data.len(); // 基本的なTCPヘッダーは20バイト
-        sum += tcp_length as u32;
-
-        // TCPヘッダーの計算
-        sum += self.source_port as u32;
-        sum += self.destination_port as u32;
-        sum += (self.sequence_number >> 16) as u32;
-        sum += (self.sequence_number & 0xFFFF) as u32;
-        sum += (self.acknowledgment_number >> 16) as u32;
-        sum += (self.acknowledgment_number & 0xFFFF) as u32;
-
-        // Data Offset (4 bits) + Reserved (4 bits) + Flags (8 bits)
-        let offset_reserved_flags =
-            ((self.data_offset as u16) << 12) | ((self.reserved as u16) << 8) | (self.flags as u16);
-        sum += offset_reserved_flags as u32;
-
-        sum += self.window_size as u32;
-        // Checksum フィールドは0として計算
-        // sum += 0;
-        sum += self.urgent_pointer as u32;
-
-        // TCPデータの計算
-        for chunk in tcp_data.chunks(2) {
-            if chunk.len() == 2 {
-                let word = ((chunk[0] as u16) << 8) | (chunk[1] as u16);
-                sum += word as u32;
-            } else {
-                // 奇数バイトの場合、最後のバイトの後に0を追加
-                let word = (chunk[0] as u16) << 8;
-                sum += word as u32;
-            }
-        }
+        let mut writer = BitWriter::with_capacity(self.header_length());
+        self.write_to(&mut writer);
+        let mut header = writer.finish();
 
-        // キャリーを加算
-        while (sum >> 16) != 0 {
-            sum = (sum & 0xFFFF) + (sum >> 16);
-        }
+        // Checksum フィールドは0として計算
+        header[16] = 0;
+        header[17] = 0;
 
-        // 1の補数を取る
-        !sum as u16
+        // TCP Length はオプションを含めたヘッダー長 + データ長
+        let tcp_length = header.len() + tcp_data.len();
+        let mut acc = IpAddress::pseudo_header(&src_ip.into(), &dst_ip.into(), 6, tcp_length);
+        acc.add_bytes(&header);
+        acc.add_bytes(tcp_data);
+        acc.finish()
     }
 
     /// チェックサムが正しいかどうかを検証する
     pub fn verify_checksum(
         &self,
-        src_ip: &IPv4Address,
-        dst_ip: &IPv4Address,
+        src_ip: impl Into<IpAddress>,
+        dst_ip: impl Into<IpAddress>,
         tcp_data: &[u8],
     ) -> bool {
         self.calculate_checksum(src_ip, dst_ip, tcp_data) == self.checksum
     }
 
     /// チェックサムを再計算して更新する
-    pub fn update_checksum(&mut self, src_ip: &IPv4Address, dst_ip: &IPv4Address, tcp_data: &[u8]) {
+    pub fn update_checksum(
+        &mut self,
+        src_ip: impl Into<IpAddress>,
+        dst_ip: impl Into<IpAddress>,
+        tcp_data: &[u8],
+    ) {
         self.checksum = self.calculate_checksum(src_ip, dst_ip, tcp_data);
     }
 
-    /// 新しいTCPヘッダーを作成し、チェックサムを自動計算する
+    /// 送信元ポートを書き換え、チェックサムを差分更新する
+    pub fn set_source_port(&mut self, port: u16) {
+        self.checksum = checksum::update(self.checksum, self.source_port, port);
+        self.source_port = port;
+    }
+
+    /// 宛先ポートを書き換え、チェックサムを差分更新する
+    pub fn set_destination_port(&mut self, port: u16) {
+        self.checksum = checksum::update(self.checksum, self.destination_port, port);
+        self.destination_port = port;
+    }
+
+    /// オプションのない新しいTCPヘッダーを作成し、チェックサムを自動計算する
+    #[allow(clippy::too_many_arguments)]
     pub fn new_with_checksum(
         source_port: u16,
         destination_port: u16,
         sequence_number: u32,
         acknowledgment_number: u32,
-        data_offset: u8,
         reserved: u8,
         flags: u8,
         window_size: u16,
         urgent_pointer: u16,
-        src_ip: &IPv4Address,
-        dst_ip: &IPv4Address,
+        src_ip: impl Into<IpAddress>,
+        dst_ip: impl Into<IpAddress>,
         tcp_data: &[u8],
     ) -> Self {
         let mut header = TcpHeader {
@@ -161,12 +267,12 @@ impl TcpHeader {
             destination_port,
             sequence_number,
             acknowledgment_number,
-            data_offset,
             reserved,
             flags,
             window_size,
             checksum: 0, // 一時的に0に設定
             urgent_pointer,
+            options: Vec::new(),
         };
 
         // チェックサムを計算して設定
@@ -180,17 +286,47 @@ impl Display for TcpHeader {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         write!(
             f,
-            "TcpHeader {{ Source Port: {}, Destination Port: {}, Sequence Number: {}, Acknowledgment Number: {}, Data Offset: {}, Reserved: {}, Flags: {:b}, Window Size: {}, Checksum: {}, Urgent Pointer: {} }}",
+            "TcpHeader {{ Source Port: {}, Destination Port: {}, Sequence Number: {}, Acknowledgment Number: {}, Data Offset: {}, Reserved: {}, Flags: {:b}, Window Size: {}, Checksum: {}, Urgent Pointer: {}, Options: {} }}",
             self.source_port,
             self.destination_port,
             self.sequence_number,
             self.acknowledgment_number,
-            self.data_offset,
+            self.data_offset(),
             self.reserved,
             self.flags,
             self.window_size,
             self.checksum,
-            self.urgent_pointer
+            self.urgent_pointer,
+            self.options.len()
         )
     }
 }
+
+/// 各フィールドをビット幅に収まる範囲で生成し、オプションは合計40バイトに収める
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TcpHeader {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut options = Vec::new();
+        let mut options_length = 0;
+        while u.ratio(1, 2)? {
+            let option: TcpOption = u.arbitrary()?;
+            if options_length + option.encoded_len() > TCP_MAX_HEADER_LENGTH - 20 {
+                break;
+            }
+            options_length += option.encoded_len();
+            options.push(option);
+        }
+        Ok(TcpHeader {
+            source_port: u.arbitrary()?,
+            destination_port: u.arbitrary()?,
+            sequence_number: u.arbitrary()?,
+            acknowledgment_number: u.arbitrary()?,
+            reserved: u.int_in_range(0..=0xF)?,
+            flags: u.arbitrary()?,
+            window_size: u.arbitrary()?,
+            checksum: u.arbitrary()?,
+            urgent_pointer: u.arbitrary()?,
+            options,
+        })
+    }
+}
@@ -0,0 +1,196 @@
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::byte_object::ByteObject;
+use crate::types::parse_error::ParseError;
+
+/// オプションの種別（RFC 9293 / RFC 7323 / RFC 2018）
+pub const TCP_OPTION_END: u8 = 0;
+pub const TCP_OPTION_NOP: u8 = 1;
+pub const TCP_OPTION_MAXIMUM_SEGMENT_SIZE: u8 = 2;
+pub const TCP_OPTION_WINDOW_SCALE: u8 = 3;
+pub const TCP_OPTION_SACK_PERMITTED: u8 = 4;
+pub const TCP_OPTION_TIMESTAMPS: u8 = 8;
+
+/// Window Scale のシフト量の上限（RFC 7323 2.3節）
+pub const TCP_MAX_WINDOW_SCALE: u8 = 14;
+
+/// TCPヘッダーのオプション
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TcpOption {
+    /// End of Option List
+    End,
+    /// No Operation
+    Nop,
+    /// Maximum Segment Size。SYNにだけ付ける
+    MaximumSegmentSize(u16),
+    /// Window Scale のシフト数（RFC 7323）
+    WindowScale(u8),
+    /// SACK Permitted（RFC 2018）
+    SackPermitted,
+    /// Timestamps（RFC 7323）
+    Timestamps { value: u32, echo_reply: u32 },
+    /// 解釈しないオプション。種別と長さを除いた中身をそのまま保持する
+    Unknown { kind: u8, data: Vec<u8> },
+    /// End の後ろに続く、0でないバイトを含むパディング。受け取ったまま書き戻す
+    Padding(Vec<u8>),
+}
+
+impl TcpOption {
+    /// 種別の番号
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::End => TCP_OPTION_END,
+            TcpOption::Nop => TCP_OPTION_NOP,
+            TcpOption::MaximumSegmentSize(_) => TCP_OPTION_MAXIMUM_SEGMENT_SIZE,
+            TcpOption::WindowScale(_) => TCP_OPTION_WINDOW_SCALE,
+            TcpOption::SackPermitted => TCP_OPTION_SACK_PERMITTED,
+            TcpOption::Timestamps { .. } => TCP_OPTION_TIMESTAMPS,
+            TcpOption::Unknown { kind, .. } => *kind,
+            // パディングは End の一部として扱う
+            TcpOption::Padding(_) => TCP_OPTION_END,
+        }
+    }
+
+    /// 種別・長さのオクテットを含めたバイト長
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            TcpOption::End | TcpOption::Nop => 1,
+            TcpOption::MaximumSegmentSize(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Timestamps { .. } => 10,
+            TcpOption::Unknown { data, .. } => 2 + data.len(),
+            TcpOption::Padding(bytes) => bytes.len(),
+        }
+    }
+
+    /// オプション部を読み切るまで解析する
+    ///
+    /// End以降はパディングとして読み捨てる。ただし4バイト境界への切り上げだけでは
+    /// 元のヘッダー長にならない場合は、その分のEndを残して Data Offset が変わらないようにする。
+    /// 0でないパディングも受け入れ、チェックサムを検証できるよう `Padding` として残す。
+    pub fn parse_list(src: &mut BitStream) -> Result<Vec<TcpOption>, ParseError> {
+        let area_len = src.remaining / 8;
+        let mut options = Vec::new();
+        let mut len: usize = 0;
+        while src.remaining > 0 {
+            let option = TcpOption::from_stream(src)?;
+            if option == TcpOption::End {
+                let padding = src.pop_remaining_bytes();
+                if padding.iter().any(|&byte| byte != 0) {
+                    options.push(TcpOption::End);
+                    options.push(TcpOption::Padding(padding));
+                } else if len.div_ceil(4) * 4 != area_len {
+                    options.resize(options.len() + area_len - len, TcpOption::End);
+                }
+                break;
+            }
+            len += option.encoded_len();
+            options.push(option);
+        }
+        Ok(options)
+    }
+}
+
+impl ByteObject for TcpOption {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError> {
+        let kind = src.try_pop(8)?.to_u8();
+        match kind {
+            TCP_OPTION_END => return Ok(TcpOption::End),
+            TCP_OPTION_NOP => return Ok(TcpOption::Nop),
+            _ => {}
+        }
+        let length = src.try_pop(8)?.to_u8();
+        if length < 2 {
+            return Err(ParseError::BadOptionLength { kind, length });
+        }
+        let mut body = src.sub_stream((length as usize - 2) * 8)?;
+        let expect = |expected: u8| {
+            if length == expected {
+                Ok(())
+            } else {
+                Err(ParseError::BadOptionLength { kind, length })
+            }
+        };
+
+        let option = match kind {
+            TCP_OPTION_MAXIMUM_SEGMENT_SIZE => {
+                expect(4)?;
+                TcpOption::MaximumSegmentSize(body.try_pop(16)?.to_u16())
+            }
+            TCP_OPTION_WINDOW_SCALE => {
+                expect(3)?;
+                TcpOption::WindowScale(body.try_pop(8)?.to_u8())
+            }
+            TCP_OPTION_SACK_PERMITTED => {
+                expect(2)?;
+                TcpOption::SackPermitted
+            }
+            TCP_OPTION_TIMESTAMPS => {
+                expect(10)?;
+                TcpOption::Timestamps {
+                    value: body.try_pop(32)?.to_u32(),
+                    echo_reply: body.try_pop(32)?.to_u32(),
+                }
+            }
+            _ => TcpOption::Unknown {
+                kind,
+                data: body.read_remaining_bytes(),
+            },
+        };
+        Ok(option)
+    }
+
+    fn write_to(&self, writer: &mut BitWriter) {
+        if let TcpOption::Padding(bytes) = self {
+            writer.write_bytes(bytes);
+            return;
+        }
+        writer.write_u8(self.kind());
+        if matches!(self, TcpOption::End | TcpOption::Nop) {
+            return;
+        }
+        writer.write_u8(self.encoded_len() as u8);
+        match self {
+            TcpOption::MaximumSegmentSize(mss) => writer.write_u16_be(*mss),
+            TcpOption::WindowScale(shift) => writer.write_u8(*shift),
+            TcpOption::SackPermitted => {}
+            TcpOption::Timestamps { value, echo_reply } => {
+                writer.write_u32_be(*value);
+                writer.write_u32_be(*echo_reply);
+            }
+            TcpOption::Unknown { data, .. } => writer.write_bytes(data),
+            TcpOption::End | TcpOption::Nop | TcpOption::Padding(_) => unreachable!(),
+        }
+    }
+}
+
+/// 書き戻したときに同じ値として読めるオプションだけを生成する
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for TcpOption {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=5)? {
+            0 => TcpOption::Nop,
+            1 => TcpOption::MaximumSegmentSize(u.arbitrary()?),
+            2 => TcpOption::WindowScale(u.arbitrary()?),
+            3 => TcpOption::SackPermitted,
+            4 => TcpOption::Timestamps {
+                value: u.arbitrary()?,
+                echo_reply: u.arbitrary()?,
+            },
+            _ => {
+                let mut kind = u.int_in_range(5..=u8::MAX)?;
+                if kind == TCP_OPTION_TIMESTAMPS {
+                    kind += 1;
+                }
+                let len = u.int_in_range(0..=8)?;
+                TcpOption::Unknown {
+                    kind,
+                    data: (0..len)
+                        .map(|_| u.arbitrary())
+                        .collect::<arbitrary::Result<_>>()?,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,75 @@
+//! 期限を一定間隔のスロットに振り分けるタイマーホイール。
+
+use std::time::{Duration, Instant};
+
+/// 期限ごとにキーを登録し、期限が来たものを取り出すタイマーホイール
+///
+/// 期限を `tick` 単位に切り捨て、スロット数で割った余りのスロットに入れる。
+/// 1周より先の期限は同じスロットに残り、期限が来るまで取り出されない。
+/// 登録の取り消しはできないので、取り出した側で期限が今も有効か確かめる。
+#[derive(Clone, Debug)]
+pub struct TimerWheel<K> {
+    tick: Duration,
+    slots: Vec<Vec<(K, Instant)>>,
+    /// スロット番号を数える起点
+    origin: Instant,
+    /// 次に調べる tick の番号
+    current: u64,
+}
+
+impl<K> TimerWheel<K> {
+    /// `slots` 個のスロットを持ち、`tick` ごとに進むホイール
+    pub fn new(tick: Duration, slots: usize, origin: Instant) -> Self {
+        assert!(!tick.is_zero() && slots > 0);
+        TimerWheel {
+            tick,
+            slots: (0..slots).map(|_| Vec::new()).collect(),
+            origin,
+            current: 0,
+        }
+    }
+
+    /// `deadline` に `key` を取り出せるようにする
+    pub fn schedule(&mut self, key: K, deadline: Instant) {
+        // 過ぎた期限は次に調べるスロットに入れる
+        let tick = self.tick_of(deadline).max(self.current);
+        let index = (tick % self.slots.len() as u64) as usize;
+        self.slots[index].push((key, deadline));
+    }
+
+    /// 登録されている数
+    pub fn len(&self) -> usize {
+        self.slots.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Vec::is_empty)
+    }
+
+    /// `now` までに期限の来たキーを取り出す
+    pub fn expire(&mut self, now: Instant) -> Vec<K> {
+        let target = self.tick_of(now);
+        if target < self.current {
+            return Vec::new();
+        }
+        // 1周以上進んだ場合も、全てのスロットを1度ずつ調べれば足りる
+        let count = (target - self.current + 1).min(self.slots.len() as u64);
+        let mut expired = Vec::new();
+        for tick in self.current..self.current + count {
+            let index = (tick % self.slots.len() as u64) as usize;
+            let (due, pending) = std::mem::take(&mut self.slots[index])
+                .into_iter()
+                .partition(|(_, deadline)| *deadline <= now);
+            self.slots[index] = pending;
+            expired.extend(due.into_iter().map(|(key, _): (K, Instant)| key));
+        }
+        // 今の tick のスロットには期限前のものが残りうるので、次も同じスロットから調べる
+        self.current = target;
+        expired
+    }
+
+    fn tick_of(&self, instant: Instant) -> u64 {
+        let elapsed = instant.saturating_duration_since(self.origin);
+        (elapsed.as_nanos() / self.tick.as_nanos()) as u64
+    }
+}
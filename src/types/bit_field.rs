@@ -0,0 +1,53 @@
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::parse_error::ParseError;
+
+/// 任意のビット幅で読み書きできるフィールド
+///
+/// `#[derive(ByteObject)]` で `#[bits(n)]` が付いたフィールドの読み書きに使われる。
+pub trait BitField: Sized {
+    /// 型が持つビット数
+    const WIDTH: usize;
+
+    /// ストリームから `width` ビットを読み取る
+    fn read_bits(src: &mut BitStream, width: usize) -> Result<Self, ParseError>;
+
+    /// 下位 `width` ビットを `writer` に書き込む
+    fn write_bits(&self, writer: &mut BitWriter, width: usize);
+}
+
+macro_rules! impl_bit_field {
+    ($ty:ty, $to:ident) => {
+        impl BitField for $ty {
+            const WIDTH: usize = <$ty>::BITS as usize;
+
+            fn read_bits(src: &mut BitStream, width: usize) -> Result<Self, ParseError> {
+                assert!(width <= Self::WIDTH, "Bit width exceeds the field type");
+                Ok(src.try_pop(width)?.$to())
+            }
+
+            fn write_bits(&self, writer: &mut BitWriter, width: usize) {
+                assert!(width <= Self::WIDTH, "Bit width exceeds the field type");
+                writer.write_bits(*self as u64, width);
+            }
+        }
+    };
+}
+
+impl_bit_field!(u8, to_u8);
+impl_bit_field!(u16, to_u16);
+impl_bit_field!(u32, to_u32);
+impl_bit_field!(u64, to_u64);
+
+impl BitField for bool {
+    const WIDTH: usize = 1;
+
+    fn read_bits(src: &mut BitStream, width: usize) -> Result<Self, ParseError> {
+        assert!(width == 1, "bool fields must be 1 bit wide");
+        Ok(src.try_pop(1)?.get(0))
+    }
+
+    fn write_bits(&self, writer: &mut BitWriter, width: usize) {
+        assert!(width == 1, "bool fields must be 1 bit wide");
+        writer.write_bool(*self);
+    }
+}
@@ -1,245 +1,576 @@
-use std::ops::Index;
+use crate::types::parse_error::ParseError;
+use bytes::{Bytes, BytesMut};
+use std::ops::{Bound, RangeBounds};
 
+/// ビット単位で先頭から読み進めるストリーム
+///
+/// 内部の `Bits` はバイト列を参照カウントで共有しているため、
+/// `pop` / `view` はデータをコピーせずにビット範囲だけを切り出す。
+#[derive(Clone, Debug)]
 pub struct BitStream {
     pub bits: Bits,
     pub pos: usize,
     pub remaining: usize,
+    /// 最上位のストリームの先頭から見た、このストリームの開始位置
+    origin: usize,
+    /// `mark` で記録した位置
+    mark: usize,
 }
 
 impl BitStream {
     pub fn new(data: Bits) -> Self {
+        let remaining = data.len();
         BitStream {
-            bits: data.clone(),
+            bits: data,
             pos: 0,
-            remaining: data.size,
+            remaining,
+            origin: 0,
+            mark: 0,
         }
     }
 
+    /// バイト列からストリームを作成する
+    pub fn from_bytes(data: impl Into<Bytes>) -> Self {
+        BitStream::new(Bits::from_bytes(data))
+    }
+
     pub fn pop(&mut self, len: usize) -> Bits {
-        let res = Bits {
-            size: len,
-            data: self.bits.data[self.pos..(self.pos + len)].to_vec(),
-        };
+        let res = self.view(len);
         self.pos += len;
         self.remaining -= len;
         res
     }
 
     pub fn view(&self, len: usize) -> Bits {
-        Bits {
-            size: len,
-            data: self.bits.data[self.pos..(self.pos + len)].to_vec(),
+        self.bits.slice(self.pos..(self.pos + len))
+    }
+
+    /// `len` ビットを読み進める。残りが足りない場合はエラーを返す
+    pub fn try_pop(&mut self, len: usize) -> Result<Bits, ParseError> {
+        let res = self.try_view(len)?;
+        self.pos += len;
+        self.remaining -= len;
+        Ok(res)
+    }
+
+    /// 位置を進めずに `len` ビットを覗き見る。残りが足りない場合はエラーを返す
+    pub fn try_view(&self, len: usize) -> Result<Bits, ParseError> {
+        if len > self.remaining {
+            return Err(ParseError::Truncated {
+                needed: len,
+                available: self.remaining,
+            });
+        }
+        Ok(self.view(len))
+    }
+
+    /// 現在の読み取り位置（ビット）
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// ストリーム全体のビット数
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// 最上位のストリームの先頭から見た、このストリームの開始位置（ビット）
+    ///
+    /// `sub_stream` で切り出した子ストリームでも、元のフレーム上のオフセットを求めるのに使う。
+    pub fn origin(&self) -> usize {
+        self.origin
+    }
+
+    /// 読み取り位置を `pos` ビット目に移動する
+    pub fn seek(&mut self, pos: usize) -> Result<(), ParseError> {
+        if pos > self.bits.len() {
+            return Err(ParseError::Truncated {
+                needed: pos,
+                available: self.bits.len(),
+            });
         }
+        self.pos = pos;
+        self.remaining = self.bits.len() - pos;
+        Ok(())
+    }
+
+    /// 読み取り位置を先頭に戻す
+    pub fn rewind(&mut self) {
+        self.pos = 0;
+        self.remaining = self.bits.len();
+    }
+
+    /// 現在の読み取り位置を記録する
+    pub fn mark(&mut self) {
+        self.mark = self.pos;
+    }
+
+    /// `mark` で記録した位置に戻る
+    pub fn reset(&mut self) {
+        self.pos = self.mark;
+        self.remaining = self.bits.len() - self.mark;
+    }
+
+    /// 現在位置から `len` ビットだけを読める子ストリームを切り出し、その分だけ読み進める
+    ///
+    /// 子ストリームは親とデータを共有し、`len` ビットより先を読むことはできない。
+    pub fn sub_stream(&mut self, len: usize) -> Result<BitStream, ParseError> {
+        let origin = self.origin + self.pos;
+        let mut child = BitStream::new(self.try_pop(len)?);
+        child.origin = origin;
+        Ok(child)
     }
 
     pub fn append(&mut self, data: Bits) -> usize {
-        self.bits.data.extend_from_slice(&data.data);
-        self.remaining += data.size;
-        self.bits.size - self.pos
+        self.bits.append(&data);
+        self.remaining += data.len();
+        self.bits.len() - self.pos
     }
 
     pub fn read_remaining_bytes(&self) -> Vec<u8> {
         if self.remaining == 0 {
             return Vec::new();
         }
-        
-        let remaining_bits = Bits {
-            size: self.remaining,
-            data: self.bits.data[self.pos..self.pos + self.remaining].to_vec(),
-        };
-        
-        remaining_bits.to_u8s()
+
+        self.view(self.remaining).to_u8s()
+    }
+
+    /// 残りを全てバイト列として読み進める
+    pub fn pop_remaining_bytes(&mut self) -> Vec<u8> {
+        let data = self.read_remaining_bytes();
+        self.pos = self.bits.len();
+        self.remaining = 0;
+        data
     }
 }
 
-#[derive(Clone)]
+/// バイト列上のビット範囲
+///
+/// `data` の先頭から `offset` ビット目を起点とした `size` ビットを表す。
+/// 複製や切り出しはバイト列の参照カウントを増やすだけで、コピーは発生しない。
+#[derive(Clone, Default)]
 pub struct Bits {
-    pub size: usize,
-    pub data: Vec<bool>,
+    data: Bytes,
+    offset: usize,
+    size: usize,
 }
 
-use std::slice::SliceIndex;
+impl Bits {
+    pub fn new() -> Self {
+        Bits::default()
+    }
+
+    /// バイト列全体を指す `Bits` を作成する
+    pub fn from_bytes(data: impl Into<Bytes>) -> Self {
+        let data = data.into();
+        let size = data.len() * 8;
+        Bits {
+            data,
+            offset: 0,
+            size,
+        }
+    }
+
+    pub fn from_vec(data: Vec<bool>) -> Self {
+        let mut bytes = vec![0u8; data.len().div_ceil(8)];
+        for (i, &bit) in data.iter().enumerate() {
+            if bit {
+                bytes[i / 8] |= 1 << (7 - i % 8);
+            }
+        }
+        Bits {
+            data: Bytes::from(bytes),
+            offset: 0,
+            size: data.len(),
+        }
+    }
 
-impl<I> Index<I> for Bits
-where
-    I: SliceIndex<[bool], Output = [bool]>,
-{
-    type Output = [bool];
+    /// ビット数を返す
+    pub fn len(&self) -> usize {
+        self.size
+    }
 
-    fn index(&self, index: I) -> &Self::Output {
-        &self.data[index]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
     }
-}
 
-impl Bits {
-    pub fn new() -> Self {
+    /// `index` ビット目の値を返す
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < self.size, "Bit index out of range");
+        let bit = self.offset + index;
+        self.data[bit / 8] & (1 << (7 - bit % 8)) != 0
+    }
+
+    /// 指定したビット範囲を切り出す（コピーは発生しない）
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Bits {
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e + 1,
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => self.size,
+        };
+        assert!(
+            start <= end && end <= self.size,
+            "Bit range {}..{} out of range for {} bits",
+            start,
+            end,
+            self.size
+        );
         Bits {
-            size: 0,
-            data: Vec::new(),
+            data: self.data.clone(),
+            offset: self.offset + start,
+            size: end - start,
         }
     }
 
     pub fn append(&mut self, bits: &Bits) {
-        self.data.extend_from_slice(&bits.data);
+        if bits.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = bits.clone();
+            return;
+        }
+
+        let mut bytes = self.take_bytes_mut();
+        if self.size.is_multiple_of(8) {
+            bytes.extend_from_slice(&bits.to_u8s());
+        } else {
+            bytes.resize((self.size + bits.size).div_ceil(8), 0);
+            for i in 0..bits.size {
+                if bits.get(i) {
+                    let bit = self.size + i;
+                    bytes[bit / 8] |= 1 << (7 - bit % 8);
+                }
+            }
+        }
         self.size += bits.size;
+        self.offset = 0;
+        self.data = bytes.freeze();
     }
 
-    pub fn from_vec(data: Vec<bool>) -> Self {
-        let size = data.len();
-        Bits { size, data }
+    /// 追記用に、このビット列だけを先頭から含むバッファを取り出す
+    ///
+    /// 先頭から始まり他と共有していないバッファはそのまま使い回すため、追記を繰り返してもコピーは償却O(1)で済む。
+    fn take_bytes_mut(&mut self) -> BytesMut {
+        let data = std::mem::take(&mut self.data);
+        if self.offset == 0 {
+            match data.try_into_mut() {
+                Ok(mut bytes) => {
+                    bytes.truncate(self.size.div_ceil(8));
+                    if !self.size.is_multiple_of(8)
+                        && let Some(last) = bytes.last_mut()
+                    {
+                        *last &= 0xFF << (8 - self.size % 8);
+                    }
+                    return bytes;
+                }
+                Err(data) => self.data = data,
+            }
+        } else {
+            self.data = data;
+        }
+        BytesMut::from(&self.to_u8s()[..])
+    }
+
+    /// バイト境界に揃っている場合、コピーせずにバイト列を返す
+    pub fn as_bytes(&self) -> Option<Bytes> {
+        if !self.offset.is_multiple_of(8) || !self.size.is_multiple_of(8) {
+            return None;
+        }
+        let start = self.offset / 8;
+        Some(self.data.slice(start..start + self.size / 8))
     }
 
     pub fn to_bools(&self) -> Vec<bool> {
-        self.data.clone()
+        (0..self.size).map(|i| self.get(i)).collect()
     }
+
     pub fn to_u8s(&self) -> Vec<u8> {
-        let mut result = Vec::new();
-        for chunk in self.data.chunks(8) {
-            let mut byte = 0u8;
-            for (i, &bit) in chunk.iter().enumerate() {
-                if bit {
-                    byte |= 1 << (7 - i);
-                }
+        if self.offset.is_multiple_of(8) {
+            // バイト境界から始まる場合はそのままコピーし、末尾の余りビットだけ落とす
+            let start = self.offset / 8;
+            let mut result = self.data[start..start + self.size.div_ceil(8)].to_vec();
+            if !self.size.is_multiple_of(8)
+                && let Some(last) = result.last_mut()
+            {
+                *last &= 0xFF << (8 - self.size % 8);
             }
-            result.push(byte);
+            return result;
+        }
+
+        let mut result = Vec::with_capacity(self.size.div_ceil(8));
+        let mut pos = 0;
+        while pos < self.size {
+            let len = (self.size - pos).min(8);
+            result.push((self.read_uint(pos, len) as u8) << (8 - len));
+            pos += len;
         }
         result
     }
+
     pub fn to_u16s(&self) -> Vec<u16> {
-        let mut result = Vec::new();
-        for chunk in self.data.chunks(16) {
-            let mut value = 0u16;
-            for (i, &bit) in chunk.iter().enumerate() {
-                if bit {
-                    value |= 1 << (15 - i);
-                }
-            }
-            result.push(value);
+        let mut result = Vec::with_capacity(self.size.div_ceil(16));
+        let mut pos = 0;
+        while pos < self.size {
+            let len = (self.size - pos).min(16);
+            result.push((self.read_uint(pos, len) as u16) << (16 - len));
+            pos += len;
         }
         result
     }
+
     pub fn to_u8(&self) -> u8 {
         assert!(self.size <= 8, "Cannot convert more than 8 bits to u8");
-        let mut byte = 0u8;
-        for (i, &bit) in self.data.iter().enumerate().take(8) {
-            if bit {
-                byte |= 1 << (self.size - 1 - i);
-            }
-        }
-        byte
+        self.read_uint(0, self.size) as u8
     }
+
     pub fn to_u16(&self) -> u16 {
         assert!(self.size <= 16, "Cannot convert more than 16 bits to u16");
-        let mut value = 0u16;
-        for (i, &bit) in self.data.iter().enumerate().take(16) {
-            if bit {
-                value |= 1 << (self.size - 1 - i);
-            }
-        }
-        value
+        self.read_uint(0, self.size) as u16
     }
+
     pub fn to_u32(&self) -> u32 {
         assert!(self.size <= 32, "Cannot convert more than 32 bits to u32");
-        let mut value = 0u32;
-        for (i, &bit) in self.data.iter().enumerate().take(32) {
-            if bit {
-                value |= 1 << (self.size - 1 - i);
-            }
+        self.read_uint(0, self.size) as u32
+    }
+
+    pub fn to_u64(&self) -> u64 {
+        assert!(self.size <= 64, "Cannot convert more than 64 bits to u64");
+        self.read_uint(0, self.size)
+    }
+
+    /// `start` ビット目から `len` ビットを符号なし整数として読む（ビッグエンディアン）
+    fn read_uint(&self, start: usize, len: usize) -> u64 {
+        let mut value = 0u64;
+        let mut bit = self.offset + start;
+        let end = bit + len;
+        while bit < end {
+            let bit_in_byte = bit % 8;
+            let take = (8 - bit_in_byte).min(end - bit);
+            let mask = ((1u16 << take) - 1) as u8;
+            let chunk = (self.data[bit / 8] >> (8 - bit_in_byte - take)) & mask;
+            value = (value << take) | chunk as u64;
+            bit += take;
         }
         value
     }
 }
 
+/// 任意のビット幅の値を先頭から詰めて書き込むライタ
+///
+/// ヘッダーのシリアライズに使う。書き込んだビット列は `finish` でバイト列として取り出す。
+#[derive(Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    bit_len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter::default()
+    }
+
+    /// 指定したバイト数の領域をあらかじめ確保して作成する
+    pub fn with_capacity(bytes: usize) -> Self {
+        BitWriter {
+            buf: Vec::with_capacity(bytes),
+            bit_len: 0,
+        }
+    }
+
+    /// 書き込み済みのビット数を返す
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+
+    /// 書き込み済みのバイト数を返す（端数のビットは1バイトとして数える）
+    pub fn byte_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        self.bit_len.is_multiple_of(8)
+    }
+
+    /// `value` の下位 `width` ビットをビッグエンディアンで書き込む
+    pub fn write_bits(&mut self, value: u64, width: usize) {
+        assert!(width <= 64, "Cannot write more than 64 bits at once");
+        let mut remaining = width;
+        while remaining > 0 {
+            let bit_in_byte = self.bit_len % 8;
+            if bit_in_byte == 0 {
+                self.buf.push(0);
+            }
+            let take = (8 - bit_in_byte).min(remaining);
+            let chunk = ((value >> (remaining - take)) & ((1u64 << take) - 1)) as u8;
+            if let Some(last) = self.buf.last_mut() {
+                *last |= chunk << (8 - bit_in_byte - take);
+            }
+            self.bit_len += take;
+            remaining -= take;
+        }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u64, 1);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.write_bits(value as u64, 8);
+    }
+
+    pub fn write_u16_be(&mut self, value: u16) {
+        if self.is_byte_aligned() {
+            self.buf.extend_from_slice(&value.to_be_bytes());
+            self.bit_len += 16;
+        } else {
+            self.write_bits(value as u64, 16);
+        }
+    }
+
+    pub fn write_u32_be(&mut self, value: u32) {
+        if self.is_byte_aligned() {
+            self.buf.extend_from_slice(&value.to_be_bytes());
+            self.bit_len += 32;
+        } else {
+            self.write_bits(value as u64, 32);
+        }
+    }
+
+    /// バイト列をそのまま書き込む
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.is_byte_aligned() {
+            self.buf.extend_from_slice(bytes);
+            self.bit_len += bytes.len() * 8;
+        } else {
+            for &byte in bytes {
+                self.write_bits(byte as u64, 8);
+            }
+        }
+    }
+
+    /// `Bits` の内容をそのまま書き込む
+    pub fn append_bits(&mut self, bits: &Bits) {
+        if let Some(bytes) = bits.as_bytes() {
+            self.write_bytes(&bytes);
+            return;
+        }
+        let mut pos = 0;
+        while pos < bits.len() {
+            let len = (bits.len() - pos).min(64);
+            self.write_bits(bits.slice(pos..pos + len).to_u64(), len);
+            pos += len;
+        }
+    }
+
+    /// 次のバイト境界まで0で埋める
+    pub fn align_to_byte(&mut self) {
+        self.bit_len = self.buf.len() * 8;
+    }
+
+    /// 書き込み済みのバイト数が `bytes` の倍数になるまで0で埋める。`bytes` が0ならパニックする
+    pub fn pad_to_multiple_of(&mut self, bytes: usize) {
+        assert!(bytes > 0, "Padding unit must be at least one byte");
+        self.align_to_byte();
+        let padded = self.buf.len().next_multiple_of(bytes);
+        self.buf.resize(padded, 0);
+        self.bit_len = padded * 8;
+    }
+
+    /// バイト境界まで0で埋め、書き込んだバイト列を返す
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// 書き込んだビット列をそのままの長さで `Bits` として返す
+    pub fn into_bits(self) -> Bits {
+        let bit_len = self.bit_len;
+        Bits::from_bytes(self.buf).slice(..bit_len)
+    }
+}
+
+impl PartialEq for Bits {
+    /// 格納位置に関係なく、ビット列の内容が等しいかどうかを比較する
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.to_u8s() == other.to_u8s()
+    }
+}
+
+impl Eq for Bits {}
+
+impl std::fmt::Debug for Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Bits({} bits: ", self.size)?;
+        for byte in self.to_u8s() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
 pub trait BitsCompatible {
     fn to_bits(&self) -> Bits;
 }
 
 impl BitsCompatible for u8 {
     fn to_bits(&self) -> Bits {
-        let bits = (0..8).map(|i| (self & (1 << (7 - i))) != 0).collect();
-        Bits {
-            size: 8,
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(&[*self]))
     }
 }
 
 impl BitsCompatible for u16 {
     fn to_bits(&self) -> Bits {
-        let bits = (0..16).map(|i| (self & (1 << (15 - i))) != 0).collect();
-        Bits {
-            size: 16,
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(&self.to_be_bytes()))
     }
 }
 
 impl BitsCompatible for u32 {
     fn to_bits(&self) -> Bits {
-        let bits = (0..32).map(|i| (self & (1 << (31 - i))) != 0).collect();
-        Bits {
-            size: 32,
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(&self.to_be_bytes()))
     }
 }
 
 impl BitsCompatible for u64 {
     fn to_bits(&self) -> Bits {
-        let bits = (0..64).map(|i| (self & (1 << (63 - i))) != 0).collect();
-        Bits {
-            size: 64,
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(&self.to_be_bytes()))
     }
 }
 
 impl BitsCompatible for usize {
     fn to_bits(&self) -> Bits {
-        let bits = (0..std::mem::size_of::<usize>() * 8)
-            .map(|i| (self & (1 << (std::mem::size_of::<usize>() * 8 - 1 - i))) != 0)
-            .collect();
-        Bits {
-            size: std::mem::size_of::<usize>() * 8,
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(&self.to_be_bytes()))
     }
 }
 
 impl BitsCompatible for Vec<u8> {
     fn to_bits(&self) -> Bits {
-        let bits: Vec<bool> = self.iter().flat_map(|&v| v.to_bits().data).collect();
-        Bits {
-            size: bits.len(),
-            data: bits,
-        }
+        Bits::from_bytes(self.clone())
     }
 }
 
 impl BitsCompatible for Vec<bool> {
     fn to_bits(&self) -> Bits {
-        Bits {
-            size: self.len(),
-            data: self.clone(),
-        }
+        Bits::from_vec(self.clone())
     }
 }
 
 impl BitsCompatible for [u8] {
     fn to_bits(&self) -> Bits {
-        let bits: Vec<bool> = self.iter().flat_map(|&v| v.to_bits().data).collect();
-        Bits {
-            size: bits.len(),
-            data: bits,
-        }
+        Bits::from_bytes(Bytes::copy_from_slice(self))
     }
 }
 
 impl BitsCompatible for [bool] {
     fn to_bits(&self) -> Bits {
-        Bits {
-            size: self.len(),
-            data: self.to_vec(),
-        }
+        Bits::from_vec(self.to_vec())
     }
 }
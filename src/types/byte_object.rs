@@ -1,5 +1,43 @@
-use crate::types::bit_stream::{BitStream, Bits};
-pub trait ByteObject {
-    fn from_stream(src: &mut BitStream) -> Self;
-    fn to_bits(&self) -> Bits;
+use crate::types::bit_stream::{BitStream, BitWriter, Bits};
+use crate::types::parse_error::ParseError;
+/// 構造体のフィールドを宣言順に読み書きする `ByteObject` 実装を生成する
+///
+/// `#[bits(n)]` の幅は型のビット幅以下でなければならない。
+///
+/// ```
+/// use ferrix::types::byte_object::ByteObject;
+///
+/// #[derive(ByteObject)]
+/// struct Header {
+///     #[bits(4)]
+///     version: u8,
+///     #[bits(4)]
+///     length: u8,
+/// }
+/// ```
+///
+/// `u8` に9ビットは収まらないため、コンパイルエラーになる。
+///
+/// ```compile_fail
+/// use ferrix::types::byte_object::ByteObject;
+///
+/// #[derive(ByteObject)]
+/// struct Header {
+///     #[bits(9)]
+///     version: u8,
+/// }
+/// ```
+pub use ferrix_derive::ByteObject;
+
+pub trait ByteObject: Sized {
+    fn from_stream(src: &mut BitStream) -> Result<Self, ParseError>;
+
+    /// 自身をビット列として `writer` に書き込む
+    fn write_to(&self, writer: &mut BitWriter);
+
+    fn to_bits(&self) -> Bits {
+        let mut writer = BitWriter::new();
+        self.write_to(&mut writer);
+        writer.into_bits()
+    }
 }
@@ -0,0 +1,40 @@
+//! ヘッダーの固定部分のフィールド配置。
+//!
+//! 型付きのパーサーとディセクターが同じ配置から読むことで、フィールドの順番や幅が食い違わないようにする。
+
+use crate::types::bit_stream::{BitStream, BitWriter};
+use crate::types::parse_error::ParseError;
+
+/// 固定幅のフィールド
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldSpec {
+    /// ディセクターで表示する名前
+    pub name: &'static str,
+    /// ビット幅（64ビット以下）
+    pub width: usize,
+}
+
+impl FieldSpec {
+    pub const fn new(name: &'static str, width: usize) -> Self {
+        FieldSpec { name, width }
+    }
+}
+
+/// `layout` の順にフィールドを読み、それぞれの値を返す
+pub fn read_fields<const N: usize>(
+    src: &mut BitStream,
+    layout: &[FieldSpec; N],
+) -> Result<[u64; N], ParseError> {
+    let mut values = [0; N];
+    for (value, spec) in values.iter_mut().zip(layout) {
+        *value = src.try_pop(spec.width)?.to_u64();
+    }
+    Ok(values)
+}
+
+/// `layout` の順に `values` を書き出す
+pub fn write_fields<const N: usize>(writer: &mut BitWriter, layout: &[FieldSpec; N], values: [u64; N]) {
+    for (spec, value) in layout.iter().zip(values) {
+        writer.write_bits(value, spec.width);
+    }
+}
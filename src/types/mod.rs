@@ -1,2 +1,5 @@
+pub mod bit_field;
 pub mod bit_stream;
+pub mod field_layout;
 pub mod byte_object;
+pub mod parse_error;
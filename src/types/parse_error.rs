@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+
+/// パケットの解析に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// データが途中で終わっている（単位はビット）
+    Truncated { needed: usize, available: usize },
+    /// バージョンフィールドが想定と異なる
+    InvalidVersion { expected: u8, found: u8 },
+    /// ヘッダー長フィールド（IHL / Data Offset）が最小値を下回っている
+    BadHeaderLength(u8),
+    /// 全長フィールドがヘッダー長より短い
+    BadTotalLength { total_length: u16, header_length: usize },
+    /// オプションの長さフィールドが種別に対して不正
+    BadOptionLength { kind: u8, length: u8 },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Truncated { needed, available } => write!(
+                f,
+                "truncated packet: needed {} bits but only {} available",
+                needed, available
+            ),
+            ParseError::InvalidVersion { expected, found } => {
+                write!(f, "invalid version: expected {}, found {}", expected, found)
+            }
+            ParseError::BadHeaderLength(length) => {
+                write!(f, "bad header length: {} words", length)
+            }
+            ParseError::BadTotalLength {
+                total_length,
+                header_length,
+            } => write!(
+                f,
+                "bad total length: {} bytes is shorter than the {} byte header",
+                total_length, header_length
+            ),
+            ParseError::BadOptionLength { kind, length } => {
+                write!(f, "bad length {} for option kind {}", length, kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
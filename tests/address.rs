@@ -0,0 +1,110 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_cidr::{CidrParseError, Ipv4Cidr};
+use ferrix::protocols::ip::ipv6_address::{IPv6Address, ZonedAddrParseError};
+use ferrix::protocols::ip::ipv6_cidr::Ipv6Cidr;
+
+#[test]
+fn ipv4_address_converts_to_and_from_std() {
+    let address: IPv4Address = "192.168.1.20".parse().unwrap();
+    assert_eq!(address, IPv4Address::new(192, 168, 1, 20));
+    assert_eq!(Ipv4Addr::from(address), Ipv4Addr::new(192, 168, 1, 20));
+    assert_eq!(IPv4Address::from(Ipv4Addr::LOCALHOST), IPv4Address::LOCALHOST);
+    assert!("192.168.1".parse::<IPv4Address>().is_err());
+}
+
+#[test]
+fn ipv4_address_classification() {
+    assert!(IPv4Address::new(127, 0, 0, 1).is_loopback());
+    assert!(IPv4Address::new(224, 0, 0, 251).is_multicast());
+    assert!(IPv4Address::new(172, 31, 255, 1).is_private());
+    assert!(!IPv4Address::new(172, 32, 0, 1).is_private());
+    assert!(IPv4Address::BROADCAST.is_broadcast());
+    assert!(IPv4Address::new(169, 254, 3, 4).is_link_local());
+}
+
+#[test]
+fn ipv4_cidr_boundaries() {
+    let cidr: Ipv4Cidr = "10.0.0.1/24".parse().unwrap();
+    assert_eq!(cidr.network(), IPv4Address::new(10, 0, 0, 0));
+    assert_eq!(cidr.broadcast(), IPv4Address::new(10, 0, 0, 255));
+    assert_eq!(cidr.netmask(), IPv4Address::new(255, 255, 255, 0));
+    assert!(cidr.contains(&IPv4Address::new(10, 0, 0, 200)));
+    assert!(!cidr.contains(&IPv4Address::new(10, 0, 1, 1)));
+    assert_eq!(cidr.to_string(), "10.0.0.1/24");
+
+    let all: Ipv4Cidr = "0.0.0.0/0".parse().unwrap();
+    assert!(all.contains(&IPv4Address::new(8, 8, 8, 8)));
+    assert_eq!(
+        Ipv4Cidr::from_netmask(IPv4Address::LOCALHOST, IPv4Address::new(255, 0, 0, 0)),
+        Ipv4Cidr::new(IPv4Address::LOCALHOST, 8)
+    );
+    assert_eq!(
+        Ipv4Cidr::from_netmask(IPv4Address::LOCALHOST, IPv4Address::new(255, 0, 255, 0)),
+        None
+    );
+    assert_eq!("10.0.0.0/33".parse::<Ipv4Cidr>(), Err(CidrParseError::InvalidPrefix));
+    assert_eq!("10.0.0.0".parse::<Ipv4Cidr>(), Err(CidrParseError::MissingPrefix));
+}
+
+#[test]
+fn ipv6_address_formats_canonically() {
+    let cases = [
+        ("2001:0DB8:0000:0000:0000:0000:0000:0001", "2001:db8::1"),
+        ("2001:db8:0:0:1:0:0:1", "2001:db8::1:0:0:1"),
+        ("2001:db8:0:1:1:1:1:1", "2001:db8:0:1:1:1:1:1"),
+        ("fe80:0000:0000:0000:0000:0000:0000:0001", "fe80::1"),
+        ("0:0:0:0:0:0:0:0", "::"),
+        ("::ffff:c000:0201", "::ffff:192.0.2.1"),
+    ];
+    for (input, expected) in cases {
+        let address: IPv6Address = input.parse().unwrap();
+        assert_eq!(address.to_string(), format!("ipv6({})", expected), "{}", input);
+        assert_eq!(expected.parse::<IPv6Address>().unwrap(), address);
+    }
+
+    let mapped: IPv6Address = "::ffff:1.2.3.4".parse().unwrap();
+    assert_eq!(mapped.to_ipv4_mapped(), Some(IPv4Address::new(1, 2, 3, 4)));
+    assert_eq!(IPv6Address::from(Ipv6Addr::LOCALHOST), IPv6Address::LOCALHOST);
+    assert_eq!(Ipv6Addr::from(mapped), Ipv4Addr::new(1, 2, 3, 4).to_ipv6_mapped());
+    assert!("2001:db8::1::2".parse::<IPv6Address>().is_err());
+}
+
+#[test]
+fn ipv6_address_parses_zone_id() {
+    let (address, zone) = IPv6Address::parse_with_zone("fe80::1%eth0").unwrap();
+    assert!(address.is_link_local());
+    assert_eq!(zone, Some("eth0"));
+    assert_eq!(
+        IPv6Address::parse_with_zone("2001:db8::1").unwrap(),
+        ("2001:db8::1".parse().unwrap(), None)
+    );
+    assert_eq!(
+        IPv6Address::parse_with_zone("fe80::1%"),
+        Err(ZonedAddrParseError::EmptyZone)
+    );
+    assert_eq!(
+        IPv6Address::parse_with_zone("fe80::g%eth0"),
+        Err(ZonedAddrParseError::InvalidAddress)
+    );
+    assert!("fe80::1%eth0".parse::<IPv6Address>().is_err());
+}
+
+#[test]
+fn ipv6_cidr_prefix_matching() {
+    let cidr: Ipv6Cidr = "2001:db8:abcd:12::1/64".parse().unwrap();
+    assert_eq!(cidr.network(), "2001:db8:abcd:12::".parse().unwrap());
+    assert_eq!(cidr.netmask(), "ffff:ffff:ffff:ffff::".parse().unwrap());
+    assert!(cidr.contains(&"2001:db8:abcd:12:ffff::9".parse().unwrap()));
+    assert!(!cidr.contains(&"2001:db8:abcd:13::1".parse().unwrap()));
+    assert_eq!(cidr.to_string(), "2001:db8:abcd:12::1/64");
+
+    let all: Ipv6Cidr = "::/0".parse().unwrap();
+    assert!(all.contains(&IPv6Address::LOCALHOST));
+    let host: Ipv6Cidr = "::1/128".parse().unwrap();
+    assert!(host.contains(&IPv6Address::LOCALHOST));
+    assert!(!host.contains(&IPv6Address::UNSPECIFIED));
+    assert_eq!("::/129".parse::<Ipv6Cidr>(), Err(CidrParseError::InvalidPrefix));
+    assert_eq!("2001:db8::".parse::<Ipv6Cidr>(), Err(CidrParseError::MissingPrefix));
+}
@@ -0,0 +1,137 @@
+use ferrix::types::bit_stream::{BitStream, BitWriter, Bits};
+use ferrix::types::parse_error::ParseError;
+
+#[test]
+fn sub_stream_cannot_read_past_its_length() {
+    let mut stream = BitStream::from_bytes(vec![0xAB, 0xCD, 0xEF]);
+    stream.try_pop(4).unwrap();
+    let mut child = stream.sub_stream(12).unwrap();
+    // 親は切り出した分だけ進む
+    assert_eq!(stream.position(), 16);
+    assert_eq!(child.origin(), 4);
+    assert_eq!(child.len(), 12);
+
+    assert_eq!(child.try_pop(8).unwrap().to_u8(), 0xBC);
+    assert_eq!(
+        child.try_pop(8),
+        Err(ParseError::Truncated {
+            needed: 8,
+            available: 4
+        })
+    );
+    // 失敗しても位置は変わらない
+    assert_eq!(child.position(), 8);
+    assert_eq!(child.try_pop(4).unwrap().to_u8(), 0xD);
+    assert_eq!(stream.try_pop(8).unwrap().to_u8(), 0xEF);
+}
+
+#[test]
+fn sub_stream_longer_than_remaining_is_an_error() {
+    let mut stream = BitStream::from_bytes(vec![0x12, 0x34]);
+    stream.try_pop(8).unwrap();
+    assert_eq!(
+        stream.sub_stream(16).err(),
+        Some(ParseError::Truncated {
+            needed: 16,
+            available: 8
+        })
+    );
+    assert_eq!(stream.position(), 8);
+}
+
+#[test]
+fn nested_sub_stream_origin_is_relative_to_the_top() {
+    let mut stream = BitStream::from_bytes(vec![0; 8]);
+    stream.try_pop(8).unwrap();
+    let mut child = stream.sub_stream(40).unwrap();
+    child.try_pop(16).unwrap();
+    let grandchild = child.sub_stream(8).unwrap();
+    assert_eq!(grandchild.origin(), 24);
+}
+
+#[test]
+fn seek_rejects_positions_past_the_end() {
+    let mut stream = BitStream::from_bytes(vec![0x12, 0x34]);
+    stream.seek(16).unwrap();
+    assert!(stream.try_pop(1).is_err());
+    assert_eq!(
+        stream.seek(17),
+        Err(ParseError::Truncated {
+            needed: 17,
+            available: 16
+        })
+    );
+    assert_eq!(stream.position(), 16);
+
+    stream.seek(4).unwrap();
+    assert_eq!(stream.try_pop(8).unwrap().to_u8(), 0x23);
+}
+
+#[test]
+fn reset_inside_sub_stream_returns_to_its_own_mark() {
+    let mut stream = BitStream::from_bytes(vec![0x11, 0x22, 0x33, 0x44]);
+    stream.try_pop(8).unwrap();
+    let mut child = stream.sub_stream(16).unwrap();
+    child.try_pop(8).unwrap();
+    child.mark();
+    assert_eq!(child.try_pop(8).unwrap().to_u8(), 0x33);
+    child.reset();
+    // 位置は子ストリームの先頭から数える
+    assert_eq!(child.position(), 8);
+    assert_eq!(child.try_pop(8).unwrap().to_u8(), 0x33);
+    assert!(child.try_pop(1).is_err());
+    // 子の mark / reset は親の位置に影響しない
+    assert_eq!(stream.position(), 24);
+}
+
+#[test]
+fn rewind_returns_to_the_start() {
+    let mut stream = BitStream::from_bytes(vec![0xF0, 0x0F]);
+    assert_eq!(stream.position(), 0);
+    stream.try_pop(3).unwrap();
+    assert_eq!(stream.position(), 3);
+    stream.try_view(5).unwrap();
+    assert_eq!(stream.position(), 3);
+
+    stream.rewind();
+    assert_eq!(stream.position(), 0);
+    assert_eq!(stream.pop_remaining_bytes(), [0xF0, 0x0F]);
+    assert_eq!(stream.position(), 16);
+}
+
+#[test]
+fn append_keeps_bits_and_leaves_shared_data_alone() {
+    let mut bits = Bits::from_bytes(vec![0xAB]);
+    let shared = bits.clone();
+    bits.append(&Bits::from_vec(vec![true, false, true]));
+    bits.append(&Bits::from_bytes(vec![0xFF]));
+    assert_eq!(bits.len(), 19);
+    assert_eq!(bits.to_u8s(), [0xAB, 0xBF, 0xE0]);
+    // 元のバイト列を共有している値は変わらない
+    assert_eq!(shared.to_u8s(), [0xAB]);
+
+    // 繰り返し追記しても順に並ぶ
+    let mut stream = BitStream::new(Bits::new());
+    for i in 0..1000u16 {
+        stream.append(Bits::from_bytes(i.to_be_bytes().to_vec()));
+    }
+    for i in 0..1000u16 {
+        assert_eq!(stream.try_pop(16).unwrap().to_u16(), i);
+    }
+}
+
+#[test]
+fn pad_to_multiple_of_fills_with_zero() {
+    let mut writer = BitWriter::new();
+    writer.write_bits(0b101, 3);
+    writer.pad_to_multiple_of(4);
+    assert_eq!(writer.byte_len(), 4);
+    writer.pad_to_multiple_of(4);
+    assert_eq!(writer.finish(), [0xA0, 0, 0, 0]);
+}
+
+#[test]
+#[should_panic(expected = "Padding unit must be at least one byte")]
+fn pad_to_multiple_of_zero_panics() {
+    BitWriter::new().pad_to_multiple_of(0);
+}
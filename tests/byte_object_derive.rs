@@ -0,0 +1,35 @@
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+
+/// 型のビット幅ちょうどまでの `#[bits(n)]` は受け付ける
+#[derive(ByteObject, Debug, PartialEq)]
+struct Packed {
+    #[bits(4)]
+    version: u8,
+    #[bits(1)]
+    flag: bool,
+    #[bits(3)]
+    reserved: u8,
+    #[bits(16)]
+    length: u16,
+    checksum: u16,
+}
+
+#[test]
+fn reads_and_writes_bit_fields() {
+    let bytes = vec![0x4a, 0x00, 0x28, 0xbe, 0xef];
+    let packed = Packed::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    assert_eq!(
+        packed,
+        Packed {
+            version: 4,
+            flag: true,
+            reserved: 2,
+            length: 40,
+            checksum: 0xbeef
+        }
+    );
+    let mut writer = BitWriter::new();
+    packed.write_to(&mut writer);
+    assert_eq!(writer.finish(), bytes);
+}
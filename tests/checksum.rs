@@ -0,0 +1,60 @@
+//! チェックサムの差分更新を、全体を計算し直した結果と比べる
+
+use ferrix::protocols::checksum;
+use proptest::prelude::*;
+
+/// 先頭の語を0以外に固定したヘッダー。全て0のデータだけが 0xFFFF になるので、その場合を避ける
+fn header(rest: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x45, 0x00];
+    data.extend_from_slice(rest);
+    data
+}
+
+#[test]
+fn update_follows_rfc1624_example() {
+    // RFC 1624 4節: HC = 0xDD2F, m = 0x5555, m' = 0x3285 のとき HC' は 0x0000 になる。
+    // 式2 `HC - ~m - m'` では 0xFFFF（-0）になってしまう
+    let old = [0xCD, 0x7A, 0x55, 0x55];
+    let new = [0xCD, 0x7A, 0x32, 0x85];
+    assert_eq!(checksum::checksum(&old), 0xDD2F);
+    assert_eq!(checksum::checksum(&new), 0x0000);
+    assert_eq!(checksum::update(0xDD2F, 0x5555, 0x3285), 0x0000);
+    assert_eq!(checksum::update_bytes(0xDD2F, &old[2..], &new[2..]), 0x0000);
+    assert_eq!(checksum::update_u32(0xDD2F, 0xCD7A5555, 0xCD7A3285), 0x0000);
+}
+
+#[test]
+fn update_bytes_pads_odd_length_with_zero() {
+    let old = header(&[1, 2, 3, 4, 5]);
+    let mut new = old.clone();
+    new[4..].copy_from_slice(&[9, 8, 7]);
+    let updated = checksum::update_bytes(checksum::checksum(&old), &old[4..], &new[4..]);
+    assert_eq!(updated, checksum::checksum(&new));
+}
+
+proptest! {
+    #[test]
+    fn update_u32_matches_recompute(rest in proptest::collection::vec(any::<u8>(), 4..64), offset in 0usize..15, new in any::<u32>()) {
+        let old = header(&rest);
+        // 先頭の語は残し、2バイト目以降の偶数オフセットを書き換える
+        let offset = 2 + 2 * (offset % ((old.len() - 6) / 2 + 1));
+        let value = u32::from_be_bytes(old[offset..offset + 4].try_into().unwrap());
+        let mut updated = old.clone();
+        updated[offset..offset + 4].copy_from_slice(&new.to_be_bytes());
+
+        let checksum = checksum::update_u32(checksum::checksum(&old), value, new);
+        prop_assert_eq!(checksum, checksum::checksum(&updated));
+    }
+
+    #[test]
+    fn update_bytes_matches_recompute(rest in proptest::collection::vec(any::<u8>(), 2..64), replacement in proptest::collection::vec(any::<u8>(), 1..64)) {
+        let old = header(&rest);
+        // 先頭の語は残し、2バイト目以降の偶数オフセットから置き換える
+        let len = replacement.len().min(old.len() - 2);
+        let mut updated = old.clone();
+        updated[2..2 + len].copy_from_slice(&replacement[..len]);
+
+        let checksum = checksum::update_bytes(checksum::checksum(&old), &old[2..2 + len], &updated[2..2 + len]);
+        prop_assert_eq!(checksum, checksum::checksum(&updated));
+    }
+}
@@ -0,0 +1,59 @@
+use ferrix::dissector::dissect;
+use ferrix::types::parse_error::ParseError;
+
+/// 10.1.0.1:49152 から 10.1.0.2:80 への SYN
+const SYN: [u8; 40] = [
+    0x45, 0x00, 0x00, 0x28, 0x00, 0x01, 0x40, 0x00, 0x40, 0x06, 0x26, 0xcb, 0x0a, 0x01, 0x00, 0x01,
+    0x0a, 0x01, 0x00, 0x02, 0xc0, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+    0x50, 0x02, 0xff, 0xff, 0xdb, 0x8c, 0x00, 0x00,
+];
+
+#[test]
+fn dissects_ipv4_tcp_syn() {
+    let dissection = dissect(&SYN);
+    assert!(dissection.error.is_none());
+
+    let tree = dissection.render_tree();
+    let lines: Vec<&str> = tree.lines().collect();
+    assert_eq!(lines[0], "Internet Protocol Version 4, Src: 10.1.0.1, Dst: 10.1.0.2");
+    assert!(lines.contains(&"        .1.. .... = Don't fragment: Set  [bit 49, 1 bits, raw 0x1]"));
+    assert!(lines.contains(&"    Header Checksum: 0x26cb [correct]  [bit 80, 16 bits, raw 0x26cb]"));
+    assert!(lines.contains(
+        &"Transmission Control Protocol, Src Port: 49152, Dst Port: 80, Seq: 1, Ack: 0, Flags: [SYN], Len: 0"
+    ));
+    assert!(lines.contains(&"        .... ..1. = Syn: Set  [bit 270, 1 bits, raw 0x1]"));
+    assert!(lines.contains(&"    Checksum: 0xdb8c [correct]  [bit 288, 16 bits, raw 0xdb8c]"));
+
+    assert_eq!(
+        dissection.render_hex_dump(),
+        "0000  45 00 00 28 00 01 40 00  40 06 26 cb 0a 01 00 01  E..(..@.@.&.....  ; Internet Protocol Version 4\n\
+         0010  0a 01 00 02 c0 00 00 50  00 00 00 01 00 00 00 00  .......P........  ; Internet Protocol Version 4 | Transmission Control Protocol\n\
+         0020  50 02 ff ff db 8c 00 00                           P.......          ; Transmission Control Protocol\n"
+    );
+}
+
+#[test]
+fn keeps_fields_read_before_truncation() {
+    // TCPヘッダーの Acknowledgment Number の途中で切れている
+    let dissection = dissect(&SYN[..30]);
+    assert_eq!(
+        dissection.error,
+        Some(ParseError::Truncated {
+            needed: 32,
+            available: 16
+        })
+    );
+
+    let tcp = &dissection.layers[1];
+    assert_eq!(tcp.name, "Transmission Control Protocol");
+    assert_eq!(tcp.child("Sequence Number").unwrap().value, "1");
+    assert!(tcp.child("Acknowledgment Number").is_none());
+
+    let tree = dissection.render_tree();
+    assert!(tree.ends_with("[Malformed Packet: truncated packet: needed 32 bits but only 16 available]\n"));
+    assert!(
+        dissection
+            .render_hex_dump()
+            .ends_with("0010  0a 01 00 02 c0 00 00 50  00 00 00 01 00 00        .......P......    ; Internet Protocol Version 4 | Transmission Control Protocol\n")
+    );
+}
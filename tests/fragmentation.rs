@@ -0,0 +1,79 @@
+use ferrix::protocols::ip::fragmentation::{FragmentError, IdentificationGenerator, fragment};
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_header::{IPV4_FLAG_DF, IPv4Header};
+use ferrix::protocols::ip::ipv4_option::IPv4Option;
+use ferrix::protocols::ip::reassembly::{Ipv4Reassembler, ReassemblyConfig};
+use ferrix::types::bit_stream::BitStream;
+use ferrix::types::byte_object::ByteObject;
+use std::time::Instant;
+
+fn header(flags: u8) -> IPv4Header {
+    IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        0,
+        42,
+        flags,
+        0,
+        64,
+        6,
+        IPv4Address::new(10, 0, 0, 1),
+        IPv4Address::new(10, 1, 0, 2),
+    )
+}
+
+#[test]
+fn fragments_fit_mtu_and_reassemble() {
+    let mut header = header(0);
+    header.options = vec![IPv4Option::RouterAlert(0), IPv4Option::RecordRoute { pointer: 4, route: vec![] }];
+    let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    let packets = fragment(&header, &payload, 1350).unwrap();
+    assert_eq!(packets.len(), 3);
+
+    let mut reassembler = Ipv4Reassembler::new(ReassemblyConfig::default());
+    let mut result = None;
+    for (i, packet) in packets.iter().enumerate().rev() {
+        assert!(packet.len() <= 1350);
+        let mut stream = BitStream::from_bytes(packet.clone());
+        let fragment = IPv4Header::from_stream(&mut stream).unwrap();
+        assert!(fragment.verify_checksum());
+        assert_eq!(fragment.total_length as usize, packet.len());
+        // Router Alert はコピーされ、Record Route は先頭にのみ載る
+        assert_eq!(fragment.options.len(), if i == 0 { 2 } else { 1 });
+        result = reassembler
+            .insert_ipv4(&fragment, &packet[fragment.header_length()..], Instant::now())
+            .unwrap();
+    }
+    let (reassembled, data) = result.unwrap();
+    assert_eq!(data, payload);
+    assert_eq!(reassembled.identification, 42);
+}
+
+#[test]
+fn small_datagram_is_sent_as_is() {
+    let packets = fragment(&header(IPV4_FLAG_DF), &[0; 100], 1350).unwrap();
+    assert_eq!(packets.len(), 1);
+    assert_eq!(packets[0].len(), 120);
+}
+
+#[test]
+fn dont_fragment_is_respected() {
+    assert_eq!(
+        fragment(&header(IPV4_FLAG_DF), &[0; 2000], 1350),
+        Err(FragmentError::DontFragment { mtu: 1350, length: 2020 })
+    );
+    assert_eq!(fragment(&header(0), &[0; 100], 24), Err(FragmentError::MtuTooSmall { mtu: 24 }));
+}
+
+#[test]
+fn identification_is_sequential_per_destination() {
+    let mut ids = IdentificationGenerator::new();
+    let a = IPv4Address::new(10, 1, 0, 2);
+    let b = IPv4Address::new(10, 1, 0, 3);
+    let first = ids.next(a);
+    assert_eq!(ids.next(a), first.wrapping_add(1));
+    // 別の宛先と同じバケットになった場合は、その分だけ進む
+    ids.next(b);
+    assert!(matches!(ids.next(a).wrapping_sub(first), 2 | 3));
+}
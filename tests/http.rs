@@ -0,0 +1,16 @@
+use ferrix::http::request::HttpRequest;
+
+#[test]
+fn waits_for_headers_and_content_length() {
+    let request = b"POST /form HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+    // 途中で区切られたリクエストはまだ揃っていない
+    for end in [20, 50, request.len() - 1] {
+        assert_eq!(HttpRequest::message_length(&request[..end]), None);
+    }
+    assert_eq!(HttpRequest::message_length(request), Some(request.len()));
+
+    let get = b"GET / HTTP/1.1\r\ncontent-length: 0\r\n\r\n";
+    assert_eq!(HttpRequest::message_length(get), Some(get.len()));
+    assert_eq!(HttpRequest::message_length(b"GET / HTTP/1.1\r\n\r\n"), Some(18));
+    assert_eq!(HttpRequest::message_length(b"GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n"), None);
+}
@@ -0,0 +1,126 @@
+use ferrix::dissector::dissect;
+use ferrix::protocols::checksum;
+use ferrix::protocols::icmp::echo::EchoResponder;
+use ferrix::protocols::icmp::icmp_message::IcmpMessage;
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use ferrix::protocols::ip::ipv4_header::IPv4Header;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+
+/// `ping` が送る Echo Request（ID 0x1234, Seq 1, データ "abcd"）
+const ECHO_REQUEST: [u8; 12] = [
+    0x08, 0x00, 0x21, 0x04, 0x12, 0x34, 0x00, 0x01, b'a', b'b', b'c', b'd',
+];
+
+fn local() -> Ipv4Cidr {
+    "10.1.0.0/24".parse().unwrap()
+}
+
+fn header(destination: IPv4Address, payload_length: usize) -> IPv4Header {
+    IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        (20 + payload_length) as u16,
+        1,
+        0,
+        0,
+        64,
+        1,
+        IPv4Address::new(10, 0, 0, 1),
+        destination,
+    )
+}
+
+fn to_bytes(message: &IcmpMessage) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    message.write_to(&mut writer);
+    writer.finish()
+}
+
+#[test]
+fn parses_echo_request_and_replies() {
+    assert!(checksum::verify(&ECHO_REQUEST));
+    let request = IcmpMessage::from_stream(&mut BitStream::from_bytes(ECHO_REQUEST.to_vec())).unwrap();
+    assert_eq!(
+        request,
+        IcmpMessage::EchoRequest {
+            identifier: 0x1234,
+            sequence: 1,
+            data: b"abcd".to_vec(),
+        }
+    );
+
+    let responder = EchoResponder::new(vec![local()]);
+    let reply = responder
+        .respond(&header(IPv4Address::new(10, 1, 0, 2), 12), &request)
+        .unwrap();
+    let bytes = to_bytes(&reply);
+    // Type だけが変わり、チェックサムは正しく計算し直される
+    assert_eq!(bytes[0], 0);
+    assert_eq!(&bytes[4..], &ECHO_REQUEST[4..]);
+    assert!(checksum::verify(&bytes));
+
+    // 応答に応答しない
+    assert_eq!(responder.respond(&header(IPv4Address::new(10, 1, 0, 2), 12), &reply), None);
+}
+
+#[test]
+fn ignores_broadcast_echo_unless_enabled() {
+    let request = IcmpMessage::EchoRequest {
+        identifier: 1,
+        sequence: 1,
+        data: Vec::new(),
+    };
+    let broadcast = header(IPv4Address::BROADCAST, 8);
+    let multicast = header(IPv4Address::new(224, 0, 0, 1), 8);
+    assert_eq!(EchoResponder::new(vec![local()]).respond(&broadcast, &request), None);
+    assert_eq!(EchoResponder::new(vec![local()]).respond(&multicast, &request), None);
+    // ローカルネットワークのブロードキャストアドレス宛て
+    let directed = header(IPv4Address::new(10, 1, 0, 255), 8);
+    assert_eq!(EchoResponder::new(vec![local()]).respond(&directed, &request), None);
+    assert!(
+        EchoResponder::new(vec![local()])
+            .reply_to_broadcast(true)
+            .respond(&broadcast, &request)
+            .is_some()
+    );
+}
+
+#[test]
+fn error_messages_quote_original_datagram() {
+    let original = header(IPv4Address::new(10, 1, 0, 2), 16);
+    let message = IcmpMessage::TimeExceeded {
+        code: 1,
+        original: IcmpMessage::original_datagram(&original, &[7; 16]),
+    };
+    assert!(message.is_error());
+    let bytes = to_bytes(&message);
+    assert_eq!(bytes.len(), 8 + 20 + 8);
+    assert_eq!(&bytes[..2], &[11, 1]);
+    assert!(checksum::verify(&bytes));
+
+    let parsed = IcmpMessage::from_stream(&mut BitStream::from_bytes(bytes)).unwrap();
+    let IcmpMessage::TimeExceeded { original: quoted, .. } = parsed else {
+        panic!("expected Time Exceeded");
+    };
+    let mut stream = BitStream::from_bytes(quoted);
+    assert_eq!(IPv4Header::from_stream(&mut stream).unwrap(), original);
+    assert_eq!(stream.read_remaining_bytes(), vec![7; 8]);
+
+    assert!(IcmpMessage::from_stream(&mut BitStream::from_bytes(vec![3, 0, 0])).is_err());
+}
+
+#[test]
+fn dissects_echo_request() {
+    let mut writer = BitWriter::new();
+    header(IPv4Address::new(10, 1, 0, 2), ECHO_REQUEST.len()).write_to(&mut writer);
+    writer.write_bytes(&ECHO_REQUEST);
+    let dissection = dissect(&writer.finish());
+    assert!(dissection.error.is_none());
+    let icmp = &dissection.layers[1];
+    assert_eq!(icmp.name, "Internet Control Message Protocol");
+    assert_eq!(icmp.value, "Echo Request, ID: 4660, Seq: 1, Len: 4");
+    assert_eq!(icmp.child("Checksum").unwrap().value, "0x2104 [correct]");
+}
@@ -0,0 +1,102 @@
+use ferrix::protocols::checksum;
+use ferrix::protocols::icmp::error::{
+    ICMP_PORT_UNREACHABLE, ICMP_PROTOCOL_UNREACHABLE, IcmpErrorGenerator,
+};
+use ferrix::protocols::icmp::icmp_message::IcmpMessage;
+use ferrix::protocols::icmp::rate_limit::RateLimiter;
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_cidr::Ipv4Cidr;
+use ferrix::protocols::ip::ipv4_header::IPv4Header;
+use ferrix::types::bit_stream::BitWriter;
+use ferrix::types::byte_object::ByteObject;
+use std::time::{Duration, Instant};
+
+fn header(protocol: u8, source: IPv4Address, destination: IPv4Address) -> IPv4Header {
+    IPv4Header::new_with_checksum(4, 0, 0, 48, 7, 0, 0, 64, protocol, source, destination)
+}
+
+const PEER: IPv4Address = IPv4Address::new(10, 0, 0, 1);
+const LOCAL: IPv4Address = IPv4Address::new(10, 1, 0, 2);
+
+#[test]
+fn port_unreachable_quotes_header_and_eight_bytes() {
+    let mut errors = IcmpErrorGenerator::default();
+    let original = header(17, PEER, LOCAL);
+    let udp = [0x30, 0x39, 0x00, 0x35, 0x00, 0x1C, 0xAB, 0xCD, 1, 2, 3, 4];
+    let message = errors
+        .destination_unreachable(ICMP_PORT_UNREACHABLE, &original, &udp, Instant::now())
+        .unwrap();
+
+    let mut writer = BitWriter::new();
+    message.write_to(&mut writer);
+    let bytes = writer.finish();
+    assert_eq!(&bytes[..2], &[3, ICMP_PORT_UNREACHABLE]);
+    assert!(checksum::verify(&bytes));
+    let mut quoted = BitWriter::new();
+    original.write_to(&mut quoted);
+    quoted.write_bytes(&udp[..8]);
+    assert_eq!(&bytes[8..], quoted.finish().as_slice());
+}
+
+#[test]
+fn never_responds_to_errors_broadcasts_or_fragments() {
+    let now = Instant::now();
+    let local: Ipv4Cidr = "10.1.0.0/24".parse().unwrap();
+    let mut errors = IcmpErrorGenerator::default().local_networks(vec![local]);
+    let mut unreachable = |header: &IPv4Header, payload: &[u8]| {
+        errors.destination_unreachable(ICMP_PROTOCOL_UNREACHABLE, header, payload, now)
+    };
+
+    assert!(unreachable(&header(99, PEER, LOCAL), &[]).is_some());
+    // ICMPエラーにはエラーを返さないが、Echo Request などの問い合わせには返してよい
+    assert!(unreachable(&header(1, PEER, LOCAL), &[3, 3, 0, 0]).is_none());
+    assert!(unreachable(&header(1, PEER, LOCAL), &[8, 0, 0, 0]).is_some());
+    assert!(unreachable(&header(99, PEER, IPv4Address::BROADCAST), &[]).is_none());
+    assert!(unreachable(&header(99, PEER, IPv4Address::new(224, 0, 0, 1)), &[]).is_none());
+    assert!(unreachable(&header(99, IPv4Address::UNSPECIFIED, LOCAL), &[]).is_none());
+    // ローカルネットワークのブロードキャストアドレス宛て
+    let directed = IPv4Address::new(10, 1, 0, 255);
+    assert!(unreachable(&header(17, PEER, directed), &[]).is_none());
+    assert!(unreachable(&header(99, IPv4Address::LOCALHOST, LOCAL), &[]).is_none());
+    assert!(unreachable(&header(99, IPv4Address::new(240, 0, 0, 1), LOCAL), &[]).is_none());
+
+    let mut fragment = header(99, PEER, LOCAL);
+    fragment.fragment_offset = 3;
+    assert!(unreachable(&fragment, &[]).is_none());
+}
+
+#[test]
+fn rate_limiter_refills_over_time() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(10, 2);
+    assert!(limiter.allow(start));
+    assert!(limiter.allow(start));
+    assert!(!limiter.allow(start));
+    assert!(!limiter.allow(start + Duration::from_millis(50)));
+    assert!(limiter.allow(start + Duration::from_millis(100)));
+    assert!(!limiter.allow(start + Duration::from_millis(150)));
+    // 長く空いてもバーストの上限までしか貯まらない
+    let later = start + Duration::from_secs(10);
+    assert!(limiter.allow(later));
+    assert!(limiter.allow(later));
+    assert!(!limiter.allow(later));
+
+    let mut errors = IcmpErrorGenerator::new(RateLimiter::new(1, 1));
+    let original = header(99, PEER, LOCAL);
+    assert!(errors.destination_unreachable(2, &original, &[], start).is_some());
+    assert!(errors.destination_unreachable(2, &original, &[], start).is_none());
+    assert_eq!(errors.suppressed(), 1);
+    assert!(matches!(
+        errors.destination_unreachable(2, &original, &[], start + Duration::from_secs(1)),
+        Some(IcmpMessage::DestinationUnreachable { code: 2, .. })
+    ));
+}
+
+#[test]
+fn rate_limiter_accepts_rates_above_one_per_nanosecond() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(u32::MAX, 1);
+    assert!(limiter.allow(start));
+    assert!(!limiter.allow(start));
+    assert!(limiter.allow(start + Duration::from_nanos(1)));
+}
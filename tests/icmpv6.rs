@@ -0,0 +1,280 @@
+use ferrix::dissector::dissect;
+use ferrix::protocols::icmpv6::icmpv6_message::{self, Icmpv6Message};
+use ferrix::protocols::icmpv6::ndp_option::{NDP_INFINITE_LIFETIME, NdpOption};
+use ferrix::protocols::icmpv6::neighbor_discovery::{self, ALL_NODES, ND_HOP_LIMIT};
+use ferrix::protocols::icmpv6::slaac::Slaac;
+use ferrix::protocols::ip::ipv6_address::IPv6Address;
+use ferrix::protocols::ip::ip_address::IpAddress;
+use ferrix::protocols::ip::ipv6_header::IPv6Header;
+use ferrix::protocols::tcp::tcp_header::TcpHeader;
+use ferrix::protocols::tcp::tcp_option::TcpOption;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+use std::time::{Duration, Instant};
+
+fn address(s: &str) -> IPv6Address {
+    s.parse().unwrap()
+}
+
+fn header(source: &str, destination: &str, hop_limit: u8) -> IPv6Header {
+    IPv6Header::new(0, 58, hop_limit, address(source), address(destination))
+}
+
+fn parse(bytes: &[u8]) -> Icmpv6Message {
+    Icmpv6Message::from_stream(&mut BitStream::from_bytes(bytes.to_vec())).unwrap()
+}
+
+#[test]
+fn echo_checksum_covers_pseudo_header() {
+    let source = address("2001:db8::1");
+    let destination = address("2001:db8::2");
+    let request = Icmpv6Message::EchoRequest {
+        identifier: 7,
+        sequence: 1,
+        data: b"ping".to_vec(),
+    };
+    let bytes = request.encode(&source, &destination);
+    assert_eq!(bytes[0], 128);
+    assert!(icmpv6_message::verify(&bytes, &source, &destination));
+    // アドレスが違えば疑似ヘッダーが変わり、検証に失敗する
+    assert!(!icmpv6_message::verify(&bytes, &source, &address("2001:db8::3")));
+    assert_eq!(parse(&bytes), request);
+}
+
+#[test]
+fn parses_router_advertisement_options() {
+    let advertisement = Icmpv6Message::RouterAdvertisement {
+        hop_limit: 64,
+        flags: 0,
+        router_lifetime: 1800,
+        reachable_time: 0,
+        retrans_timer: 0,
+        options: vec![
+            NdpOption::SourceLinkLayerAddress(vec![0x02, 0, 0, 0, 0, 1]),
+            NdpOption::Mtu(1500),
+            NdpOption::PrefixInformation {
+                prefix_len: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime: 86400,
+                preferred_lifetime: 14400,
+                prefix: address("2001:db8:1::"),
+            },
+        ],
+    };
+    let bytes = advertisement.encode(&address("fe80::1"), &ALL_NODES);
+    assert_eq!(bytes.len(), 16 + 8 + 8 + 32);
+    assert_eq!(parse(&bytes), advertisement);
+
+    // 長さ0のオプションは不正
+    let mut zero_length = bytes.clone();
+    zero_length[17] = 0;
+    assert!(Icmpv6Message::from_stream(&mut BitStream::from_bytes(zero_length)).is_err());
+}
+
+#[test]
+fn answers_neighbor_solicitation_for_local_target() {
+    let local = address("fe80::2");
+    let solicitation = Icmpv6Message::NeighborSolicitation {
+        target: local,
+        options: Vec::new(),
+    };
+    let is_local = |a: &IPv6Address| *a == local;
+
+    let unicast = header("fe80::1", "ff02::1:ff00:2", ND_HOP_LIMIT);
+    assert!(neighbor_discovery::is_valid(&unicast, &solicitation));
+    let (destination, advertisement) =
+        neighbor_discovery::advertise(&unicast, &solicitation, is_local).unwrap();
+    assert_eq!(destination, address("fe80::1"));
+    assert_eq!(
+        advertisement,
+        Icmpv6Message::NeighborAdvertisement {
+            router: false,
+            solicited: true,
+            override_flag: true,
+            target: local,
+            options: Vec::new(),
+        }
+    );
+
+    // 重複アドレス検出には全ノード宛てに Solicited なしで返す
+    let duplicate_detection = header("::", "ff02::1:ff00:2", ND_HOP_LIMIT);
+    assert!(neighbor_discovery::is_valid(&duplicate_detection, &solicitation));
+    let (destination, advertisement) =
+        neighbor_discovery::advertise(&duplicate_detection, &solicitation, is_local).unwrap();
+    assert_eq!(destination, ALL_NODES);
+    assert!(matches!(
+        advertisement,
+        Icmpv6Message::NeighborAdvertisement { solicited: false, .. }
+    ));
+
+    let other = Icmpv6Message::NeighborSolicitation {
+        target: address("fe80::3"),
+        options: Vec::new(),
+    };
+    assert_eq!(neighbor_discovery::advertise(&unicast, &other, is_local), None);
+    // ルーターを越えてきたものは受け付けない
+    assert!(!neighbor_discovery::is_valid(&header("fe80::1", "fe80::2", 64), &solicitation));
+}
+
+#[test]
+fn slaac_configures_and_expires_addresses() {
+    let now = Instant::now();
+    let mut slaac = Slaac::new();
+    assert!(slaac.link_local().is_link_local());
+    assert!(slaac.is_local(&slaac.link_local()));
+
+    let advertise = |valid_lifetime: u32, preferred_lifetime: u32| Icmpv6Message::RouterAdvertisement {
+        hop_limit: 64,
+        flags: 0,
+        router_lifetime: 1800,
+        reachable_time: 0,
+        retrans_timer: 0,
+        options: vec![
+            NdpOption::PrefixInformation {
+                prefix_len: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime,
+                preferred_lifetime,
+                prefix: address("2001:db8:1::"),
+            },
+            // 自動設定を許可しないプレフィックスは使わない
+            NdpOption::PrefixInformation {
+                prefix_len: 64,
+                on_link: true,
+                autonomous: false,
+                valid_lifetime,
+                preferred_lifetime,
+                prefix: address("2001:db8:2::"),
+            },
+        ],
+    };
+
+    let configured = slaac.process_router_advertisement(&advertise(3 * 3600, 3600), now);
+    assert_eq!(configured.len(), 1);
+    let configured = configured[0];
+    assert_eq!(&configured.octets()[..8], &address("2001:db8:1::").octets()[..8]);
+    assert!(slaac.is_local(&configured));
+    // 同じプレフィックスなら同じアドレスのまま寿命だけ更新する
+    assert!(slaac.process_router_advertisement(&advertise(3 * 3600, 3600), now).is_empty());
+    assert_eq!(slaac.addresses().len(), 1);
+    assert!(slaac.addresses()[0].is_deprecated(now + Duration::from_secs(3600)));
+
+    // 2時間を下回る短縮は2時間に丸める（RFC 4862 5.5.3 e）
+    slaac.process_router_advertisement(&advertise(60, 60), now);
+    assert_eq!(slaac.addresses()[0].valid_until, Some(now + Duration::from_secs(2 * 3600)));
+    assert!(slaac.expire(now + Duration::from_secs(3600)).is_empty());
+    assert_eq!(slaac.expire(now + Duration::from_secs(2 * 3600)).len(), 1);
+    assert!(!slaac.is_local(&configured));
+
+    slaac.process_router_advertisement(&advertise(NDP_INFINITE_LIFETIME, NDP_INFINITE_LIFETIME), now);
+    assert_eq!(slaac.addresses()[0].valid_until, None);
+    assert!(slaac.expire(now + Duration::from_secs(365 * 24 * 3600)).is_empty());
+}
+
+/// IPv6ヘッダーに `payload` を続けたパケット
+fn packet(header: &IPv6Header, payload: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.write_bytes(payload);
+    writer.finish()
+}
+
+#[test]
+fn dissects_neighbor_solicitation_after_hop_by_hop() {
+    let source = address("fe80::1");
+    let destination = address("ff02::1:ff00:2");
+    let solicitation = Icmpv6Message::NeighborSolicitation {
+        target: address("fe80::2"),
+        options: vec![NdpOption::SourceLinkLayerAddress(vec![0x02, 0, 0, 0, 0, 1])],
+    };
+    // Hop-by-Hop: Next Header 58、PadN で8バイトに揃える
+    let mut payload = vec![58, 0, 1, 4, 0, 0, 0, 0];
+    payload.extend(solicitation.encode(&source, &destination));
+    let header = IPv6Header::new(payload.len() as u16, 0, ND_HOP_LIMIT, source, destination);
+    let dissection = dissect(&packet(&header, &payload));
+    assert!(dissection.error.is_none());
+
+    let names: Vec<&str> = dissection.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Internet Protocol Version 6",
+            "IPv6 Hop-by-Hop Options",
+            "Internet Control Message Protocol v6"
+        ]
+    );
+    assert_eq!(
+        dissection.layers[0].value,
+        "Src: fe80::1, Dst: ff02::1:ff00:2"
+    );
+    assert_eq!(dissection.layers[1].value, "Next Header: ICMPv6 (58)");
+    let icmpv6 = &dissection.layers[2];
+    assert_eq!(icmpv6.value, "Neighbor Solicitation for fe80::2");
+    assert!(icmpv6.child("Checksum").unwrap().value.ends_with("[correct]"));
+    let options = icmpv6.child("Options").unwrap();
+    assert_eq!(options.children[0].name, "Source Link-Layer Address");
+    assert_eq!(options.children[0].value, "02:00:00:00:00:01");
+}
+
+#[test]
+fn dissects_tcp_over_ipv6() {
+    let source = address("2001:db8::1");
+    let destination = address("2001:db8::2");
+    let mut tcp = TcpHeader::new_with_checksum(
+        50000,
+        80,
+        1,
+        0,
+        0,
+        0x02,
+        65535,
+        0,
+        IpAddress::V6(source),
+        IpAddress::V6(destination),
+        &[],
+    );
+    tcp.set_options(vec![TcpOption::MaximumSegmentSize(1440)]).unwrap();
+    tcp.update_checksum(IpAddress::V6(source), IpAddress::V6(destination), &[]);
+    let mut writer = BitWriter::new();
+    tcp.write_to(&mut writer);
+    let segment = writer.finish();
+    let header = IPv6Header::new(segment.len() as u16, 6, 64, source, destination);
+    let dissection = dissect(&packet(&header, &segment));
+    assert!(dissection.error.is_none());
+    let tcp = &dissection.layers[1];
+    assert_eq!(tcp.name, "Transmission Control Protocol");
+    assert!(tcp.child("Checksum").unwrap().value.ends_with("[correct]"));
+    assert_eq!(tcp.child("Options").unwrap().children[0].value, "1440 bytes");
+}
+
+#[test]
+fn detects_icmpv6_error_behind_extension_headers() {
+    let source = address("2001:db8::1");
+    let destination = address("2001:db8::2");
+    let unreachable = Icmpv6Message::DestinationUnreachable {
+        code: 4,
+        original: vec![0; 8],
+    };
+    // 未知のオプション（上位2ビットが10）を持つ Destination Options の後ろのエラーメッセージ
+    let mut payload = vec![58, 0, 0x80, 4, 0, 0, 0, 0];
+    payload.extend(unreachable.encode(&source, &destination));
+    let header = IPv6Header::new(payload.len() as u16, 60, 64, source, destination);
+    assert!(icmpv6_message::carries_error(&packet(&header, &payload)));
+
+    let request = Icmpv6Message::EchoRequest {
+        identifier: 1,
+        sequence: 1,
+        data: vec![],
+    };
+    let payload = request.encode(&source, &destination);
+    let header = IPv6Header::new(payload.len() as u16, 58, 64, source, destination);
+    assert!(!icmpv6_message::carries_error(&packet(&header, &payload)));
+
+    // 先頭以外のフラグメントには上位層のヘッダーがない
+    let mut payload = vec![58, 0, 0, 8, 0, 0, 0, 1];
+    payload.extend(unreachable.encode(&source, &destination));
+    let header = IPv6Header::new(payload.len() as u16, 44, 64, source, destination);
+    assert!(!icmpv6_message::carries_error(&packet(&header, &payload)));
+}
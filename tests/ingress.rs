@@ -0,0 +1,133 @@
+use ferrix::protocols::ip::ingress::{DropReason, Ipv4Ingress};
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_header::IPv4Header;
+use ferrix::protocols::ip::reassembly::ReassemblyError;
+use ferrix::protocols::ip::stats::IpStats;
+use ferrix::types::bit_stream::BitWriter;
+use ferrix::types::byte_object::ByteObject;
+
+fn ingress() -> Ipv4Ingress {
+    Ipv4Ingress::new(vec!["10.1.0.0/24".parse().unwrap()])
+}
+
+fn datagram(source: IPv4Address, destination: IPv4Address, ttl: u8, payload: &[u8]) -> Vec<u8> {
+    let header = IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        (20 + payload.len()) as u16,
+        1,
+        0,
+        0,
+        ttl,
+        6,
+        source,
+        destination,
+    );
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.write_bytes(payload);
+    writer.finish()
+}
+
+const PEER: IPv4Address = IPv4Address::new(10, 0, 0, 1);
+const LOCAL: IPv4Address = IPv4Address::new(10, 1, 0, 2);
+
+#[test]
+fn accepts_valid_datagram_and_strips_padding() {
+    let mut frame = datagram(PEER, LOCAL, 1, &[1, 2, 3, 4]);
+    frame.extend_from_slice(&[0; 6]);
+    let (header, payload) = ingress().validate(&frame).unwrap();
+    assert_eq!(header.ttl, 1, "TTL 1 must not be dropped (RFC 1122)");
+    assert_eq!(payload.read_remaining_bytes(), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn drops_with_reason() {
+    let ingress = ingress();
+    let valid = datagram(PEER, LOCAL, 64, &[0; 8]);
+
+    assert_eq!(ingress.validate(&valid[..24]).unwrap_err(), DropReason::Truncated);
+
+    let mut corrupted = valid.clone();
+    corrupted[8] ^= 1;
+    assert_eq!(ingress.validate(&corrupted).unwrap_err(), DropReason::BadChecksum);
+
+    let mut bad_version = valid.clone();
+    bad_version[0] = 0x65;
+    assert_eq!(ingress.validate(&bad_version).unwrap_err(), DropReason::BadVersion);
+
+    let ttl_zero = datagram(PEER, LOCAL, 0, &[]);
+    assert_eq!(ingress.validate(&ttl_zero).unwrap_err(), DropReason::TtlExpired);
+
+    let elsewhere = datagram(PEER, IPv4Address::new(192, 0, 2, 1), 64, &[]);
+    assert_eq!(ingress.validate(&elsewhere).unwrap_err(), DropReason::NotForUs);
+
+    let multicast_source = datagram(IPv4Address::new(224, 0, 0, 1), LOCAL, 64, &[]);
+    assert_eq!(
+        ingress.validate(&multicast_source).unwrap_err(),
+        DropReason::BadSourceAddress
+    );
+}
+
+#[test]
+fn drops_martian_addresses() {
+    let ingress = ingress();
+    let sources = [
+        IPv4Address::LOCALHOST,
+        IPv4Address::new(127, 9, 9, 9),
+        IPv4Address::UNSPECIFIED,
+    ];
+    for source in sources {
+        let frame = datagram(source, LOCAL, 64, &[]);
+        assert_eq!(
+            ingress.validate(&frame).unwrap_err(),
+            DropReason::BadSourceAddress,
+            "{}",
+            source
+        );
+    }
+
+    // /24 のネットワークアドレスとブロードキャストアドレスはホスト宛てではない
+    for destination in [IPv4Address::new(10, 1, 0, 0), IPv4Address::new(10, 1, 0, 255)] {
+        assert!(!ingress.is_local(&destination));
+        let frame = datagram(PEER, destination, 64, &[]);
+        assert_eq!(
+            ingress.validate(&frame).unwrap_err(),
+            DropReason::BadDestinationAddress,
+            "{}",
+            destination
+        );
+    }
+    assert!(ingress.is_local(&IPv4Address::new(10, 1, 0, 1)));
+    assert!(ingress.is_local(&IPv4Address::new(10, 1, 0, 254)));
+    assert!(ingress.is_local(&IPv4Address::BROADCAST));
+
+    let mut stats = IpStats::new();
+    stats.record_drop(&DropReason::BadDestinationAddress);
+    assert_eq!(stats.in_addr_errors, 1);
+}
+
+#[test]
+fn counters_follow_snmp_names() {
+    let mut stats = IpStats::new();
+    stats.record_drop(&DropReason::BadChecksum);
+    stats.record_drop(&DropReason::NotForUs);
+    assert_eq!(stats.in_hdr_errors, 1);
+    assert_eq!(stats.in_csum_errors, 1);
+    assert_eq!(stats.in_addr_errors, 1);
+
+    let text = stats.to_string();
+    let mut lines = text.lines();
+    assert!(lines.next().unwrap().starts_with("Ip: InReceives InHdrErrors InAddrErrors"));
+    assert!(lines.next().unwrap().starts_with("Ip: 0 1 1"));
+}
+
+#[test]
+fn counts_reassembly_failures_for_lack_of_memory_as_discards() {
+    let mut stats = IpStats::new();
+    stats.record_reassembly_failure(&ReassemblyError::Overlap);
+    assert_eq!((stats.reasm_fails, stats.in_discards), (1, 0));
+    stats.record_reassembly_failure(&ReassemblyError::OutOfMemory);
+    assert_eq!((stats.reasm_fails, stats.in_discards), (2, 1));
+}
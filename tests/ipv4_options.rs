@@ -0,0 +1,99 @@
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_header::IPv4Header;
+use ferrix::protocols::ip::ipv4_option::IPv4Option;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+use ferrix::types::parse_error::ParseError;
+
+fn header_with_options(options: &[u8]) -> Vec<u8> {
+    let ihl = 5 + options.len() / 4;
+    let mut bytes = vec![
+        0x40 | ihl as u8, 0, 0, (ihl * 4) as u8, 0, 0, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+    ];
+    bytes.extend_from_slice(options);
+    bytes
+}
+
+fn to_bytes(header: &IPv4Header) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.finish()
+}
+
+#[test]
+fn parses_record_route_and_router_alert() {
+    let bytes = header_with_options(&[
+        7, 7, 4, 192, 168, 0, 1, // Record Route
+        148, 4, 0, 0, // Router Alert
+        1, // NOP
+    ]);
+    let header = IPv4Header::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    assert_eq!(
+        header.options,
+        vec![
+            IPv4Option::RecordRoute {
+                pointer: 4,
+                route: vec![IPv4Address::new(192, 168, 0, 1)],
+            },
+            IPv4Option::RouterAlert(0),
+            IPv4Option::Nop,
+        ]
+    );
+    assert_eq!(header.ihl(), 8);
+    assert_eq!(to_bytes(&header), bytes);
+}
+
+#[test]
+fn keeps_header_length_when_end_is_followed_by_padding_words() {
+    let bytes = header_with_options(&[1, 0, 0, 0, 0, 0, 0, 0]);
+    let header = IPv4Header::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    assert_eq!(header.header_length(), 28);
+    assert_eq!(to_bytes(&header), bytes);
+}
+
+#[test]
+fn rejects_bad_option_length() {
+    let bytes = header_with_options(&[148, 3, 0, 0]);
+    assert_eq!(
+        IPv4Header::from_stream(&mut BitStream::from_bytes(bytes)),
+        Err(ParseError::BadOptionLength { kind: 148, length: 3 })
+    );
+}
+
+#[test]
+fn keeps_non_zero_padding_after_end() {
+    let bytes = header_with_options(&[1, 0, 0, 7]);
+    let header = IPv4Header::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    assert_eq!(
+        header.options,
+        [IPv4Option::Nop, IPv4Option::End, IPv4Option::Padding(vec![0, 7])]
+    );
+    assert_eq!(to_bytes(&header), bytes);
+}
+
+#[test]
+fn set_options_updates_lengths_and_checksum() {
+    let mut header = IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        40,
+        1,
+        0,
+        0,
+        64,
+        6,
+        IPv4Address::new(10, 0, 0, 1),
+        IPv4Address::new(10, 0, 0, 2),
+    );
+    header.set_options(vec![IPv4Option::RouterAlert(0), IPv4Option::Nop]);
+    assert_eq!(header.ihl(), 7);
+    assert_eq!(header.total_length, 48);
+    assert_eq!(header.payload_length(), 20);
+    assert!(header.verify_checksum());
+
+    // 全長がヘッダー長より短くても、ペイロードを0として計算する
+    header.total_length = 10;
+    header.set_options(vec![]);
+    assert_eq!(header.total_length, 20);
+}
@@ -0,0 +1,202 @@
+use ferrix::protocols::ip::ipv6_extension::{
+    self, ChainError, ExtensionChain, ExtensionHeaders, IPV6_DESTINATION_OPTIONS, IPV6_FRAGMENT,
+    IPV6_HOP_BY_HOP, Ipv6ExtensionHeader, Ipv6Option,
+};
+use ferrix::protocols::ip::ipv6_header::IPv6Header;
+use ferrix::protocols::ip::reassembly::{Ipv6Reassembler, ReassemblyConfig, ReassemblyError};
+use ferrix::types::bit_stream::BitWriter;
+use ferrix::types::byte_object::ByteObject;
+use std::time::Instant;
+
+fn header(next_header: u8, payload: &[u8]) -> IPv6Header {
+    IPv6Header::new(
+        payload.len() as u16,
+        next_header,
+        64,
+        "2001:db8::1".parse().unwrap(),
+        "2001:db8::2".parse().unwrap(),
+    )
+}
+
+fn packet(header: &IPv6Header, payload: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.write_bytes(payload);
+    writer.finish()
+}
+
+/// 8バイトの Hop-by-Hop / Destination Options ヘッダー。`options` は6バイト
+fn options_header(next_header: u8, options: [u8; 6]) -> Vec<u8> {
+    [[next_header, 0].as_slice(), &options].concat()
+}
+
+fn fragment_header(next_header: u8, offset: u16, more: bool, identification: u32) -> Vec<u8> {
+    let field = (offset << 3) | more as u16;
+    [
+        [next_header, 0].as_slice(),
+        &field.to_be_bytes(),
+        &identification.to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn walks_chain_to_upper_layer() {
+    let payload = [
+        // Hop-by-Hop: Router Alert + PadN
+        options_header(IPV6_DESTINATION_OPTIONS, [5, 2, 0, 0, 1, 0]),
+        // Destination Options: 未知だが読み飛ばしてよい種別 (0b00)
+        options_header(6, [0x1E, 1, 0xAA, 0, 0, 0]),
+        vec![0xDE, 0xAD],
+    ]
+    .concat();
+    let chain = ipv6_extension::parse_chain(&header(IPV6_HOP_BY_HOP, &payload), &payload).unwrap();
+    assert_eq!(
+        chain.headers,
+        vec![
+            Ipv6ExtensionHeader::HopByHop(vec![Ipv6Option::RouterAlert(0), Ipv6Option::PadN(2)]),
+            Ipv6ExtensionHeader::DestinationOptions(vec![
+                Ipv6Option::Unknown {
+                    kind: 0x1E,
+                    data: vec![0xAA]
+                },
+                Ipv6Option::Pad1,
+                Ipv6Option::Pad1,
+                Ipv6Option::Pad1,
+            ]),
+        ]
+    );
+    assert_eq!(chain.upper_layer, 6);
+    assert_eq!(chain.offset, 16);
+    assert_eq!(&payload[chain.offset..], &[0xDE, 0xAD]);
+    // Destination Options の Next Header フィールド
+    assert_eq!(chain.next_header_pointer, 40 + 8);
+    assert!(!chain.is_fragmented());
+}
+
+#[test]
+fn unknown_options_follow_action_bits() {
+    let unicast = header(IPV6_DESTINATION_OPTIONS, &[]);
+    let mut multicast = unicast.clone();
+    multicast.destination_address = "ff02::1".parse().unwrap();
+
+    let with_option = |kind: u8| options_header(6, [1, 0, kind, 1, 0, 0]);
+
+    let skip = ipv6_extension::parse_chain(&unicast, &with_option(0x3F)).unwrap();
+    assert_eq!(skip.upper_layer, 6);
+    assert_eq!(
+        ipv6_extension::parse_chain(&unicast, &with_option(0x7F)),
+        Err(ChainError::Discard)
+    );
+    // Pointer はパケット先頭からオプション種別のバイトまで
+    let problem = ChainError::ParameterProblem {
+        code: 2,
+        pointer: 40 + 4,
+    };
+    assert_eq!(ipv6_extension::parse_chain(&unicast, &with_option(0xBF)), Err(problem.clone()));
+    assert_eq!(ipv6_extension::parse_chain(&multicast, &with_option(0xBF)), Err(problem.clone()));
+    assert_eq!(ipv6_extension::parse_chain(&unicast, &with_option(0xFF)), Err(problem));
+    assert_eq!(
+        ipv6_extension::parse_chain(&multicast, &with_option(0xFF)),
+        Err(ChainError::Discard)
+    );
+}
+
+#[test]
+fn rejects_misplaced_hop_by_hop_and_truncation() {
+    let payload = options_header(IPV6_HOP_BY_HOP, [1, 4, 0, 0, 0, 0]);
+    assert_eq!(
+        ipv6_extension::parse_chain(&header(IPV6_DESTINATION_OPTIONS, &payload), &payload),
+        Err(ChainError::ParameterProblem {
+            code: 1,
+            pointer: 40
+        })
+    );
+
+    let truncated = &payload[..5];
+    assert!(matches!(
+        ipv6_extension::parse_chain(&header(IPV6_DESTINATION_OPTIONS, truncated), truncated),
+        Err(ChainError::Malformed(_))
+    ));
+}
+
+#[test]
+fn reassembles_fragments_and_resumes_chain() {
+    // 断片化可能部分: Destination Options に続く上位層のデータ
+    let fragmentable = [options_header(17, [1, 4, 0, 0, 0, 0]), vec![7; 16]].concat();
+    let first = [
+        fragment_header(IPV6_DESTINATION_OPTIONS, 0, true, 42),
+        fragmentable[..16].to_vec(),
+    ]
+    .concat();
+    let last = [
+        fragment_header(IPV6_DESTINATION_OPTIONS, 2, false, 42),
+        fragmentable[16..].to_vec(),
+    ]
+    .concat();
+
+    let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+    let now = Instant::now();
+    let mut result = None;
+    for payload in [&last, &first] {
+        let header = header(IPV6_FRAGMENT, payload);
+        let chain = ipv6_extension::parse_chain(&header, payload).unwrap();
+        assert!(chain.is_fragmented());
+        assert_eq!(chain.upper_layer, IPV6_DESTINATION_OPTIONS);
+        result = reassembler
+            .insert_ipv6(&header, &chain, payload, &packet(&header, payload), now)
+            .unwrap();
+    }
+    let (head, data) = result.unwrap();
+    assert_eq!(data, fragmentable);
+    assert_eq!(head.fragmentable_offset, 48);
+    assert!(reassembler.is_empty());
+
+    let chain = ExtensionChain::parse(ExtensionHeaders::resume(
+        head.next_header,
+        &data,
+        &head.header.destination_address,
+        head.fragmentable_offset,
+    ))
+    .unwrap();
+    assert_eq!(chain.upper_layer, 17);
+    assert_eq!(&data[chain.offset..], &[7; 16]);
+}
+
+#[test]
+fn overlapping_fragments_drop_datagram() {
+    let mut reassembler = Ipv6Reassembler::new(ReassemblyConfig::default());
+    let now = Instant::now();
+    let first = [fragment_header(6, 0, true, 1), vec![0; 16]].concat();
+    let overlapping = [fragment_header(6, 1, false, 1), vec![0; 16]].concat();
+
+    for (payload, expected) in [(&first, Ok(None)), (&overlapping, Err(ReassemblyError::Overlap))] {
+        let header = header(IPV6_FRAGMENT, payload);
+        let chain = ipv6_extension::parse_chain(&header, payload).unwrap();
+        let result = reassembler
+            .insert_ipv6(&header, &chain, payload, &packet(&header, payload), now)
+            .map(|reassembled| reassembled.map(|(_, data)| data));
+        assert_eq!(result, expected);
+    }
+    assert!(reassembler.is_empty());
+}
+
+#[test]
+fn atomic_fragment_and_bad_fragment_length() {
+    let atomic = [fragment_header(6, 0, false, 9), vec![1, 2, 3]].concat();
+    let chain = ipv6_extension::parse_chain(&header(IPV6_FRAGMENT, &atomic), &atomic).unwrap();
+    assert_eq!(chain.fragment(), Some((0, false, 9)));
+    assert!(!chain.is_fragmented());
+    assert_eq!(chain.upper_layer, 6);
+    assert_eq!(&atomic[chain.offset..], &[1, 2, 3]);
+
+    // 最後以外のフラグメントの長さが8の倍数でなければ Payload Length を指す
+    let odd = [fragment_header(6, 0, true, 9), vec![0; 5]].concat();
+    assert_eq!(
+        ipv6_extension::parse_chain(&header(IPV6_FRAGMENT, &odd), &odd),
+        Err(ChainError::ParameterProblem {
+            code: 0,
+            pointer: 4
+        })
+    );
+}
@@ -0,0 +1,142 @@
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_header::{IPV4_FLAG_MF, IPv4Header};
+use ferrix::protocols::ip::reassembly::{
+    Ipv4Reassembler, OverlapPolicy, ReassemblyConfig, ReassemblyError, Reassembler,
+};
+use std::time::{Duration, Instant};
+
+fn reassembler(policy: OverlapPolicy) -> Reassembler<u32, &'static str> {
+    Reassembler::new(ReassemblyConfig {
+        overlap_policy: policy,
+        ..ReassemblyConfig::default()
+    })
+}
+
+#[test]
+fn reassembles_out_of_order_fragments() {
+    let now = Instant::now();
+    let mut r = reassembler(OverlapPolicy::Drop);
+    assert_eq!(r.insert(1, "tail", 16, false, &[3; 4], now), Ok(None));
+    assert_eq!(r.insert(1, "head", 0, true, &[1; 8], now), Ok(None));
+    let done = r.insert(1, "middle", 8, true, &[2; 8], now).unwrap().unwrap();
+    assert_eq!(done.header, "head");
+    assert_eq!(done.payload, [[1; 8].as_slice(), &[2; 8], &[3; 4]].concat());
+    assert!(r.is_empty());
+    assert_eq!(r.memory_usage(), 0);
+}
+
+#[test]
+fn overlap_policies() {
+    let now = Instant::now();
+
+    let mut r = reassembler(OverlapPolicy::Drop);
+    r.insert(1, "h", 0, true, &[1; 8], now).unwrap();
+    assert_eq!(r.insert(1, "h", 0, true, &[1; 8], now), Ok(None), "exact duplicates are ignored");
+    assert_eq!(r.insert(1, "h", 4, false, &[2; 8], now), Err(ReassemblyError::Overlap));
+    assert!(r.is_empty());
+
+    let mut r = reassembler(OverlapPolicy::FirstWins);
+    r.insert(1, "h", 0, true, &[1; 8], now).unwrap();
+    let done = r.insert(1, "h", 4, false, &[2; 8], now).unwrap().unwrap();
+    assert_eq!(done.payload, [[1; 8].as_slice(), &[2; 4]].concat());
+
+    let mut r = reassembler(OverlapPolicy::LastWins);
+    r.insert(1, "h", 0, true, &[1; 8], now).unwrap();
+    let done = r.insert(1, "h", 4, false, &[2; 8], now).unwrap().unwrap();
+    assert_eq!(done.payload, [[1; 4].as_slice(), &[2; 8]].concat());
+}
+
+#[test]
+fn rejects_data_past_the_last_fragment() {
+    let now = Instant::now();
+    let mut r = reassembler(OverlapPolicy::Drop);
+    r.insert(1, "h", 8, false, &[0; 8], now).unwrap();
+    assert_eq!(r.insert(1, "h", 16, true, &[0; 8], now), Err(ReassemblyError::Inconsistent));
+    assert!(r.is_empty());
+}
+
+#[test]
+fn expires_with_received_prefix() {
+    let now = Instant::now();
+    let mut r = reassembler(OverlapPolicy::Drop);
+    r.insert(1, "h", 0, true, &[1; 8], now).unwrap();
+    r.insert(1, "h", 16, false, &[3; 8], now).unwrap();
+    assert!(r.expire(now + Duration::from_secs(1)).is_empty());
+
+    let expired = r.expire(now + r.config().timeout);
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].header, Some("h"));
+    assert_eq!(expired[0].payload, vec![1; 8]);
+    assert!(r.is_empty());
+}
+
+#[test]
+fn memory_cap_evicts_oldest_datagram() {
+    let now = Instant::now();
+    let mut r: Reassembler<u32, ()> = Reassembler::new(ReassemblyConfig {
+        max_memory: 24,
+        ..ReassemblyConfig::default()
+    });
+    r.insert(1, (), 0, true, &[0; 16], now).unwrap();
+    r.insert(2, (), 0, true, &[0; 16], now + Duration::from_millis(1)).unwrap();
+    assert_eq!(r.len(), 1);
+    assert_eq!(r.memory_usage(), 16);
+    assert_eq!(r.insert(3, (), 0, true, &[0; 32], now), Err(ReassemblyError::OutOfMemory));
+}
+
+#[test]
+fn ipv4_reassembly_clears_fragment_fields() {
+    let now = Instant::now();
+    let mut first = IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        28,
+        7,
+        IPV4_FLAG_MF,
+        0,
+        64,
+        17,
+        IPv4Address::new(10, 1, 0, 2),
+        IPv4Address::new(10, 0, 0, 1),
+    );
+    first.update_checksum();
+    let mut second = first.clone();
+    second.flags = 0;
+    second.fragment_offset = 1;
+
+    let mut r = Ipv4Reassembler::new(ReassemblyConfig::default());
+    assert_eq!(r.insert_ipv4(&second, &[2; 4], now), Ok(None));
+    let (header, payload) = r.insert_ipv4(&first, &[1; 8], now).unwrap().unwrap();
+    assert_eq!(payload, [[1; 8].as_slice(), &[2; 4]].concat());
+    assert!(!header.is_fragment());
+    assert_eq!(header.total_length, 32);
+    assert!(header.verify_checksum());
+}
+
+#[test]
+fn ipv4_rejects_misaligned_non_final_fragment() {
+    let now = Instant::now();
+    let mut header = IPv4Header::new_with_checksum(
+        4,
+        0,
+        0,
+        32,
+        9,
+        IPV4_FLAG_MF,
+        0,
+        64,
+        17,
+        IPv4Address::new(10, 1, 0, 2),
+        IPv4Address::new(10, 0, 0, 1),
+    );
+    header.update_checksum();
+
+    let mut r = Ipv4Reassembler::new(ReassemblyConfig::default());
+    assert_eq!(r.insert_ipv4(&header, &[1; 12], now), Err(ReassemblyError::Misaligned));
+    assert!(r.is_empty());
+    // 最終フラグメントは8の倍数でなくてよい
+    header.flags = 0;
+    header.fragment_offset = 1;
+    assert_eq!(r.insert_ipv4(&header, &[2; 12], now), Ok(None));
+}
@@ -0,0 +1,167 @@
+//! ヘッダーのシリアライズとパースの往復、チェックサム、HTTPパーサーに関するプロパティテスト
+
+use arbitrary::{Arbitrary, Unstructured};
+use ferrix::http::request::HttpRequest;
+use ferrix::protocols::checksum;
+use ferrix::protocols::icmp::icmp_message::IcmpMessage;
+use ferrix::protocols::icmpv6::icmpv6_message::{self, Icmpv6Message};
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv4_header::IPv4Header;
+use ferrix::protocols::ip::ipv6_address::IPv6Address;
+use ferrix::protocols::ip::ipv6_header::IPv6Header;
+use ferrix::protocols::tcp::tcp_header::TcpHeader;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+use proptest::prelude::*;
+use std::fmt::Debug;
+
+/// ランダムなバイト列から `Arbitrary` 実装を使って値を生成する
+fn arb<T>() -> impl Strategy<Value = T>
+where
+    T: for<'a> Arbitrary<'a> + Debug,
+{
+    proptest::collection::vec(any::<u8>(), 64..512)
+        .prop_filter_map("not enough entropy", |bytes| {
+            T::arbitrary(&mut Unstructured::new(&bytes)).ok()
+        })
+}
+
+/// シリアライズした結果をパースし直す
+fn reparse<T: ByteObject>(value: &T) -> T {
+    let mut stream = BitStream::new(value.to_bits());
+    let parsed = T::from_stream(&mut stream).expect("serialized value must parse");
+    assert_eq!(stream.remaining, 0, "parser must consume exactly what was written");
+    parsed
+}
+
+fn to_bytes<T: ByteObject>(value: &T) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    value.write_to(&mut writer);
+    writer.finish()
+}
+
+proptest! {
+    #[test]
+    fn ipv4_address_roundtrip(address in arb::<IPv4Address>()) {
+        prop_assert_eq!(reparse(&address), address);
+    }
+
+    #[test]
+    fn ipv4_header_roundtrip(header in arb::<IPv4Header>()) {
+        prop_assert_eq!(reparse(&header), header);
+    }
+
+    #[test]
+    fn ipv6_header_roundtrip(header in arb::<IPv6Header>()) {
+        prop_assert_eq!(reparse(&header), header);
+    }
+
+    #[test]
+    fn tcp_header_roundtrip(header in arb::<TcpHeader>()) {
+        prop_assert_eq!(reparse(&header), header);
+    }
+
+    #[test]
+    fn icmp_message_roundtrip_with_valid_checksum(message in arb::<IcmpMessage>()) {
+        prop_assert_eq!(reparse(&message), message.clone());
+        prop_assert!(checksum::verify(&to_bytes(&message)));
+    }
+
+    #[test]
+    fn icmpv6_message_roundtrip_with_valid_checksum(
+        message in arb::<Icmpv6Message>(),
+        source in arb::<IPv6Address>(),
+        destination in arb::<IPv6Address>(),
+    ) {
+        prop_assert_eq!(reparse(&message), message.clone());
+        let encoded = message.encode(&source, &destination);
+        prop_assert!(icmpv6_message::verify(&encoded, &source, &destination));
+    }
+
+    #[test]
+    fn ipv4_checksum_verifies(header in arb::<IPv4Header>()) {
+        prop_assert!(header.verify_checksum());
+        prop_assert!(checksum::verify(&to_bytes(&header)));
+    }
+
+    #[test]
+    fn ipv4_incremental_checksum_matches_full(mut header in arb::<IPv4Header>(), ttl in any::<u8>()) {
+        header.set_ttl(ttl);
+        prop_assert_eq!(header.header_checksum, header.calculate_checksum());
+    }
+
+    #[test]
+    fn tcp_checksum_verifies(
+        mut header in arb::<TcpHeader>(),
+        src in arb::<IPv4Address>(),
+        dst in arb::<IPv4Address>(),
+        data in proptest::collection::vec(any::<u8>(), 0..64),
+    ) {
+        header.update_checksum(src, dst, &data);
+        prop_assert!(header.verify_checksum(src, dst, &data));
+
+        let mut segment = to_bytes(&header);
+        segment.extend_from_slice(&data);
+        let mut acc = checksum::ipv4_pseudo_header(src.octets(), dst.octets(), 6, segment.len() as u16);
+        acc.add_bytes(&segment);
+        prop_assert_eq!(acc.finish(), 0);
+    }
+
+    #[test]
+    fn tcp_checksum_verifies_over_ipv6(
+        mut header in arb::<TcpHeader>(),
+        src in arb::<IPv6Address>(),
+        dst in arb::<IPv6Address>(),
+        data in proptest::collection::vec(any::<u8>(), 0..64),
+    ) {
+        header.update_checksum(src, dst, &data);
+
+        let mut segment = to_bytes(&header);
+        segment.extend_from_slice(&data);
+        let mut acc = checksum::ipv6_pseudo_header(src.octets(), dst.octets(), 6, segment.len() as u32);
+        acc.add_bytes(&segment);
+        prop_assert_eq!(acc.finish(), 0);
+    }
+
+    #[test]
+    fn bit_writer_roundtrip(fields in proptest::collection::vec((any::<u64>(), 1usize..=64), 0..32)) {
+        let mut writer = BitWriter::new();
+        for &(value, width) in &fields {
+            writer.write_bits(value, width);
+        }
+        let mut stream = BitStream::new(writer.into_bits());
+        for &(value, width) in &fields {
+            let mask = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+            prop_assert_eq!(stream.try_pop(width).unwrap().to_u64(), value & mask);
+        }
+        prop_assert_eq!(stream.remaining, 0);
+    }
+
+    #[test]
+    fn truncated_headers_return_errors(header in arb::<IPv4Header>(), cut in 0usize..20) {
+        let bytes = to_bytes(&header);
+        let mut stream = BitStream::from_bytes(bytes[..cut].to_vec());
+        prop_assert!(IPv4Header::from_stream(&mut stream).is_err());
+    }
+
+    #[test]
+    fn http_parse_never_panics(raw in any::<String>()) {
+        let _ = HttpRequest::parse(&raw);
+    }
+
+    #[test]
+    fn http_parse_never_panics_on_bytes(raw in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let _ = HttpRequest::parse(&String::from_utf8_lossy(&raw));
+    }
+
+    #[test]
+    fn http_parse_never_panics_on_request_like_input(request in arb::<HttpRequest>()) {
+        let mut raw = format!("{} {} {}\r\n", request.method, request.path, request.version);
+        for (key, value) in &request.headers {
+            raw.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(&request.body);
+        let _ = HttpRequest::parse(&raw);
+    }
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "serde")]
+
+use ferrix::packet_log::PacketLogEntry;
+use ferrix::protocols::icmpv6::icmpv6_message::{IPV6_NEXT_HEADER_ICMPV6, Icmpv6Message};
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::ip::ipv6_address::IPv6Address;
+use ferrix::protocols::ip::ipv6_header::IPv6Header;
+use ferrix::protocols::tcp::tcp_flags::{TCP_ACK, TCP_SYN};
+use ferrix::protocols::tcp::tcp_header::TcpHeader;
+use ferrix::protocols::tcp::tcp_option::TcpOption;
+use ferrix::types::bit_stream::BitWriter;
+use ferrix::types::byte_object::ByteObject;
+use serde_json::json;
+
+/// 10.1.0.1:49152 から 10.1.0.2:80 への SYN
+const SYN: [u8; 40] = [
+    0x45, 0x00, 0x00, 0x28, 0x00, 0x01, 0x40, 0x00, 0x40, 0x06, 0x26, 0xcb, 0x0a, 0x01, 0x00, 0x01,
+    0x0a, 0x01, 0x00, 0x02, 0xc0, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+    0x50, 0x02, 0xff, 0xff, 0xdb, 0x8c, 0x00, 0x00,
+];
+
+fn syn_ack() -> TcpHeader {
+    let mut header = TcpHeader::new_with_checksum(
+        80,
+        49152,
+        1000,
+        2,
+        0,
+        TCP_SYN | TCP_ACK,
+        65535,
+        0,
+        IPv4Address::new(10, 1, 0, 2),
+        IPv4Address::new(10, 1, 0, 1),
+        &[],
+    );
+    header.set_options(vec![TcpOption::MaximumSegmentSize(1460)]).unwrap();
+    header
+}
+
+#[test]
+fn serializes_flags_as_names() {
+    let value = serde_json::to_value(syn_ack()).unwrap();
+    assert_eq!(value["flags"], json!(["SYN", "ACK"]));
+}
+
+#[test]
+fn serializes_ipv4_address_as_dotted_quad() {
+    let address = IPv4Address::new(192, 168, 0, 1);
+    assert_eq!(serde_json::to_string(&address).unwrap(), "\"192.168.0.1\"");
+    assert_eq!(serde_json::from_str::<IPv4Address>("\"192.168.0.1\"").unwrap(), address);
+    assert!(serde_json::from_str::<IPv4Address>("\"192.168.0\"").is_err());
+}
+
+#[test]
+fn round_trips_tcp_header() {
+    let header = syn_ack();
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(serde_json::from_str::<TcpHeader>(&json).unwrap(), header);
+}
+
+#[test]
+fn logs_ipv4_tcp_frame() {
+    let entry = PacketLogEntry::from_frame(&SYN);
+    let mut line = Vec::new();
+    entry.write_line(&mut line).unwrap();
+    assert_eq!(line.last(), Some(&b'\n'));
+
+    let value: serde_json::Value = serde_json::from_slice(&line).unwrap();
+    assert_eq!(value["ipv4"]["source_address"], "10.1.0.1");
+    assert_eq!(value["tcp"]["flags"], json!(["SYN"]));
+    assert_eq!(value["ipv6"], json!(null));
+    assert_eq!(value["error"], json!(null));
+
+    // 書き出した行から同じ内容を読み戻せる
+    let parsed: PacketLogEntry = serde_json::from_slice(&line).unwrap();
+    assert_eq!(parsed.ipv4, entry.ipv4);
+    assert_eq!(parsed.tcp, entry.tcp);
+}
+
+#[test]
+fn logs_ipv6_icmpv6_frame() {
+    let source: IPv6Address = "fd00:1::1".parse().unwrap();
+    let destination: IPv6Address = "fd00:1::2".parse().unwrap();
+    let message = Icmpv6Message::EchoRequest {
+        identifier: 7,
+        sequence: 1,
+        data: b"ping".to_vec(),
+    };
+    let body = message.encode(&source, &destination);
+    let header = IPv6Header::new(body.len() as u16, IPV6_NEXT_HEADER_ICMPV6, 64, source, destination);
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.write_bytes(&body);
+
+    let entry = PacketLogEntry::from_frame(&writer.finish());
+    assert!(entry.error.is_none());
+    assert_eq!(entry.ipv6, Some(header));
+    assert_eq!(entry.icmpv6, Some(message));
+    assert!(entry.ipv4.is_none() && entry.tcp.is_none());
+}
@@ -0,0 +1,436 @@
+use ferrix::protocols::ip::ip_address::IpAddress;
+use ferrix::protocols::tcp::connection::{ConnectionKey, ConnectionTable, TcpError, TcpSegment};
+use ferrix::protocols::tcp::isn::IsnGenerator;
+use ferrix::protocols::tcp::rtt::{MAX_RTO, RttEstimator};
+use ferrix::protocols::tcp::sequence::{seq_ge, seq_gt, seq_in_window, seq_le, seq_lt};
+use ferrix::protocols::tcp::tcb::TIME_WAIT_DURATION;
+use ferrix::protocols::tcp::tcp_flags::{TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN};
+use ferrix::protocols::tcp::tcp_header::TcpHeader;
+use ferrix::protocols::tcp::tcp_option::TcpOption;
+use ferrix::protocols::tcp::tcp_state::TcpState;
+use ferrix::protocols::tcp::timer_wheel::TimerWheel;
+use std::time::{Duration, Instant};
+
+const CLIENT_ISS: u32 = u32::MAX - 2;
+
+fn client() -> IpAddress {
+    IpAddress::V4("10.0.0.1".parse().unwrap())
+}
+
+fn server() -> IpAddress {
+    IpAddress::V4("10.1.0.2".parse().unwrap())
+}
+
+fn key() -> ConnectionKey {
+    ConnectionKey {
+        local_address: server(),
+        local_port: 80,
+        remote_address: client(),
+        remote_port: 50000,
+    }
+}
+
+fn header(seq: u32, ack: u32, flags: u8) -> TcpHeader {
+    TcpHeader {
+        source_port: 50000,
+        destination_port: 80,
+        sequence_number: seq,
+        acknowledgment_number: ack,
+        reserved: 0,
+        flags,
+        window_size: 1000,
+        checksum: 0,
+        urgent_pointer: 0,
+        options: Vec::new(),
+    }
+}
+
+/// クライアントからセグメントを送り、返ってきたセグメントを返す
+fn receive(
+    table: &mut ConnectionTable,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    payload: &[u8],
+) -> Vec<TcpSegment> {
+    table.receive(
+        client(),
+        server(),
+        &header(seq, ack, flags),
+        payload,
+        Instant::now(),
+    )
+}
+
+/// 3ウェイハンドシェイクを済ませ、サーバーの ISS を返す
+fn establish(table: &mut ConnectionTable) -> u32 {
+    table.listen(80);
+    let syn_ack = receive(table, CLIENT_ISS, 0, TCP_SYN, &[]);
+    assert_eq!(syn_ack.len(), 1);
+    let syn_ack = &syn_ack[0].header;
+    assert_eq!(syn_ack.flags, TCP_SYN | TCP_ACK);
+    assert_eq!(syn_ack.acknowledgment_number, CLIENT_ISS.wrapping_add(1));
+    assert_eq!(table.state(&key()), TcpState::SynReceived);
+
+    let iss = syn_ack.sequence_number;
+    assert!(
+        receive(
+            table,
+            CLIENT_ISS.wrapping_add(1),
+            iss.wrapping_add(1),
+            TCP_ACK,
+            &[]
+        )
+        .is_empty()
+    );
+    assert_eq!(table.state(&key()), TcpState::Established);
+    iss
+}
+
+#[test]
+fn sequence_numbers_wrap() {
+    assert!(seq_lt(u32::MAX, 0));
+    assert!(seq_gt(5, u32::MAX - 5));
+    assert!(seq_le(7, 7) && seq_ge(7, 7));
+    assert!(seq_in_window(2, u32::MAX, 4));
+    assert!(!seq_in_window(3, u32::MAX, 4));
+}
+
+#[test]
+fn initial_sequence_numbers_follow_clock_and_differ_per_connection() {
+    let isn = IsnGenerator::new();
+    let now = Instant::now();
+    let other = ConnectionKey {
+        remote_port: 50001,
+        ..key()
+    };
+    assert_eq!(isn.generate(&key(), now), isn.generate(&key(), now));
+    assert_ne!(isn.generate(&key(), now), isn.generate(&other, now));
+    // 4マイクロ秒ごとに1進む
+    let later = isn.generate(&key(), now + Duration::from_millis(4));
+    assert_eq!(later.wrapping_sub(isn.generate(&key(), now)), 1000);
+    // 起動ごとに秘密値が変わる
+    assert_ne!(
+        isn.generate(&key(), now),
+        IsnGenerator::new().generate(&key(), now)
+    );
+}
+
+#[test]
+fn closed_port_and_listen_send_resets() {
+    let mut table = ConnectionTable::new();
+    assert_eq!(table.state(&key()), TcpState::Closed);
+
+    // ACKのないセグメントには <SEQ=0><ACK=SEG.SEQ+SEG.LEN><CTL=RST,ACK>
+    let reset = receive(&mut table, 100, 0, TCP_SYN, &[]);
+    assert_eq!(reset[0].header.flags, TCP_RST | TCP_ACK);
+    assert_eq!(reset[0].header.sequence_number, 0);
+    assert_eq!(reset[0].header.acknowledgment_number, 101);
+    assert!(receive(&mut table, 100, 0, TCP_RST, &[]).is_empty());
+
+    // LISTEN でもACKには <SEQ=SEG.ACK><CTL=RST>
+    table.listen(80);
+    assert_eq!(table.state(&key()), TcpState::Listen);
+    let reset = receive(&mut table, 100, 777, TCP_ACK, &[]);
+    assert_eq!(reset[0].header.flags, TCP_RST);
+    assert_eq!(reset[0].header.sequence_number, 777);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn serves_request_and_closes_actively() {
+    let mut table = ConnectionTable::new();
+    let iss = establish(&mut table);
+    let mut seq = CLIENT_ISS.wrapping_add(1);
+
+    let ack = receive(
+        &mut table,
+        seq,
+        iss.wrapping_add(1),
+        TCP_ACK | TCP_PSH,
+        b"GET /",
+    );
+    seq = seq.wrapping_add(5);
+    assert_eq!(ack[0].header.acknowledgment_number, seq);
+    assert_eq!(table.read(&key()).unwrap(), b"GET /");
+
+    let response = table.send(&key(), b"hello", Instant::now()).unwrap();
+    assert_eq!(response[0].header.sequence_number, iss.wrapping_add(1));
+    assert_eq!(response[0].header.flags, TCP_ACK | TCP_PSH);
+    let fin = table.close(&key(), Instant::now()).unwrap();
+    assert_eq!(fin[0].header.flags, TCP_FIN | TCP_ACK);
+    assert_eq!(fin[0].header.sequence_number, iss.wrapping_add(6));
+    assert_eq!(table.state(&key()), TcpState::FinWait1);
+    assert_eq!(
+        table.send(&key(), b"late", Instant::now()),
+        Err(TcpError::ConnectionClosing)
+    );
+
+    receive(&mut table, seq, iss.wrapping_add(7), TCP_ACK, &[]);
+    assert_eq!(table.state(&key()), TcpState::FinWait2);
+    let ack = receive(&mut table, seq, iss.wrapping_add(7), TCP_FIN | TCP_ACK, &[]);
+    assert_eq!(ack[0].header.acknowledgment_number, seq.wrapping_add(1));
+    assert_eq!(table.state(&key()), TcpState::TimeWait);
+
+    assert!(table.expire(Instant::now()).is_empty());
+    assert_eq!(
+        table.expire(Instant::now() + TIME_WAIT_DURATION),
+        vec![key()]
+    );
+    assert_eq!(table.state(&key()), TcpState::Listen);
+}
+
+#[test]
+fn peer_close_goes_through_close_wait_and_last_ack() {
+    let mut table = ConnectionTable::new();
+    let iss = establish(&mut table);
+    let seq = CLIENT_ISS.wrapping_add(1);
+
+    receive(&mut table, seq, iss.wrapping_add(1), TCP_FIN | TCP_ACK, &[]);
+    assert_eq!(table.state(&key()), TcpState::CloseWait);
+    let fin = table.close(&key(), Instant::now()).unwrap();
+    assert_eq!(fin[0].header.acknowledgment_number, seq.wrapping_add(1));
+    assert_eq!(table.state(&key()), TcpState::LastAck);
+
+    receive(
+        &mut table,
+        seq.wrapping_add(1),
+        iss.wrapping_add(2),
+        TCP_ACK,
+        &[],
+    );
+    assert_eq!(table.state(&key()), TcpState::Listen);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn rejects_out_of_window_and_blind_resets() {
+    let mut table = ConnectionTable::new();
+    let iss = establish(&mut table);
+    let seq = CLIENT_ISS.wrapping_add(1);
+
+    // ウィンドウ外のデータには現在の位置を知らせるACKだけを返す
+    let ack = receive(
+        &mut table,
+        seq.wrapping_add(100_000),
+        iss.wrapping_add(1),
+        TCP_ACK,
+        b"x",
+    );
+    assert_eq!(ack[0].header.acknowledgment_number, seq);
+    assert!(table.read(&key()).unwrap().is_empty());
+
+    // RCV.NXT と一致しないRSTは確認のACKを返すだけ（RFC 5961）
+    let challenge = receive(&mut table, seq.wrapping_add(10), 0, TCP_RST, &[]);
+    assert_eq!(challenge[0].header.flags, TCP_ACK);
+    assert_eq!(table.state(&key()), TcpState::Established);
+
+    assert!(receive(&mut table, seq, 0, TCP_RST, &[]).is_empty());
+    assert_eq!(table.state(&key()), TcpState::Listen);
+}
+
+#[test]
+fn active_open_completes_on_syn_ack() {
+    let mut table = ConnectionTable::new();
+    let syn = table.connect(key(), Instant::now()).unwrap();
+    assert_eq!(syn.header.flags, TCP_SYN);
+    assert_eq!(table.state(&key()), TcpState::SynSent);
+    assert_eq!(
+        table.connect(key(), Instant::now()),
+        Err(TcpError::ConnectionAlreadyExists)
+    );
+    assert_eq!(
+        table.send(&key(), b"early", Instant::now()),
+        Err(TcpError::NotSynchronized)
+    );
+
+    let iss = syn.header.sequence_number;
+    // こちらのSYNを確認応答していないSYN-ACKにはRSTを返す
+    let reset = receive(&mut table, 500, iss, TCP_SYN | TCP_ACK, &[]);
+    assert_eq!(reset[0].header.flags, TCP_RST);
+    assert_eq!(table.state(&key()), TcpState::SynSent);
+
+    let ack = receive(&mut table, 500, iss.wrapping_add(1), TCP_SYN | TCP_ACK, &[]);
+    assert_eq!(ack[0].header.flags, TCP_ACK);
+    assert_eq!(ack[0].header.acknowledgment_number, 501);
+    assert_eq!(table.state(&key()), TcpState::Established);
+    assert_eq!(table.get(&key()).unwrap().snd.wnd, 1000);
+}
+
+#[test]
+fn segments_by_mss_within_peer_window() {
+    let mut table = ConnectionTable::new().mtu(1500);
+    table.listen(80);
+    let mut syn = header(CLIENT_ISS, 0, TCP_SYN);
+    syn.set_options(vec![TcpOption::Nop, TcpOption::MaximumSegmentSize(400)]).unwrap();
+    let syn_ack = table.receive(client(), server(), &syn, &[], Instant::now());
+    // こちらのMSSも知らせる
+    assert_eq!(syn_ack[0].header.mss(), Some(1460));
+    let iss = syn_ack[0].header.sequence_number;
+    let seq = CLIENT_ISS.wrapping_add(1);
+    receive(&mut table, seq, iss.wrapping_add(1), TCP_ACK, &[]);
+    assert_eq!(table.get(&key()).unwrap().snd.mss, 400);
+
+    // 相手のウィンドウは1000バイト
+    let data: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+    let segments = table.send(&key(), &data, Instant::now()).unwrap();
+    let lengths: Vec<usize> = segments.iter().map(|s| s.payload.len()).collect();
+    assert_eq!(lengths, vec![400, 400, 200]);
+    assert_eq!(segments[2].header.sequence_number, iss.wrapping_add(801));
+    assert!(segments.iter().all(|s| s.header.flags & TCP_PSH == 0));
+    assert!(table.close(&key(), Instant::now()).unwrap().is_empty());
+
+    // 確認応答でウィンドウが空けば続きとFINを送る
+    let ack = iss.wrapping_add(1001);
+    let segments = receive(&mut table, seq, ack, TCP_ACK, &[]);
+    let lengths: Vec<usize> = segments.iter().map(|s| s.payload.len()).collect();
+    assert_eq!(lengths, vec![400, 400, 200]);
+    assert_eq!(segments[2].header.flags, TCP_ACK | TCP_PSH | TCP_FIN);
+    let sent: Vec<u8> = segments.iter().flat_map(|s| s.payload.clone()).collect();
+    assert_eq!(sent, &data[1000..]);
+
+    receive(&mut table, seq, iss.wrapping_add(2002), TCP_ACK, &[]);
+    assert_eq!(table.state(&key()), TcpState::FinWait2);
+}
+
+#[test]
+fn local_mss_follows_mtu_and_default_peer_mss() {
+    let table = ConnectionTable::new().mtu(1350);
+    assert_eq!(table.local_mss(&key()), 1310);
+
+    let mut table = ConnectionTable::new().mtu(9000);
+    establish(&mut table);
+    // MSS オプションがなければ IPv4 では536を仮定する
+    assert_eq!(table.get(&key()).unwrap().snd.mss, 536);
+}
+
+#[test]
+fn rtt_estimator_follows_rfc_6298() {
+    let mut rtt = RttEstimator::new();
+    assert_eq!(rtt.rto(), Duration::from_secs(1));
+    rtt.sample(Duration::from_millis(500));
+    assert_eq!(rtt.srtt(), Some(Duration::from_millis(500)));
+    assert_eq!(rtt.rttvar(), Duration::from_millis(250));
+    assert_eq!(rtt.rto(), Duration::from_millis(1500));
+    rtt.sample(Duration::from_millis(100));
+    assert_eq!(rtt.srtt(), Some(Duration::from_millis(450)));
+    assert_eq!(rtt.rttvar(), Duration::from_micros(287_500));
+    assert_eq!(rtt.rto(), Duration::from_millis(1600));
+
+    for _ in 0..10 {
+        rtt.backoff();
+    }
+    assert_eq!(rtt.rto(), MAX_RTO);
+    // 短い往復時間でも1秒を下回らない
+    let mut rtt = RttEstimator::new();
+    rtt.sample(Duration::from_millis(10));
+    assert_eq!(rtt.rto(), Duration::from_secs(1));
+}
+
+#[test]
+fn retransmits_syn_ack_with_backoff_then_aborts() {
+    let mut table = ConnectionTable::new().max_retries(2);
+    table.listen(80);
+    let start = Instant::now();
+    let syn = header(CLIENT_ISS, 0, TCP_SYN);
+    let syn_ack = table.receive(client(), server(), &syn, &[], start);
+    let iss = syn_ack[0].header.sequence_number;
+
+    let at = |millis| start + Duration::from_millis(millis);
+    assert!(table.retransmit(at(900)).is_empty());
+    let resent = table.retransmit(at(1000));
+    assert_eq!(resent, syn_ack);
+    // RTO は倍の2秒になる
+    assert!(table.retransmit(at(2900)).is_empty());
+    assert_eq!(table.retransmit(at(3000)), syn_ack);
+
+    let reset = table.retransmit(at(7000));
+    assert_eq!(reset.len(), 1);
+    assert_eq!(reset[0].header.flags, TCP_RST);
+    assert_eq!(reset[0].header.sequence_number, iss.wrapping_add(1));
+    assert!(table.is_empty());
+    assert_eq!(table.state(&key()), TcpState::Listen);
+}
+
+#[test]
+fn retransmits_data_without_sampling_ambiguous_acks() {
+    let mut table = ConnectionTable::new();
+    let iss = establish(&mut table);
+    let seq = CLIENT_ISS.wrapping_add(1);
+    let rtt = |table: &ConnectionTable| table.get(&key()).unwrap().retransmission.rtt.clone();
+    let srtt = rtt(&table).srtt();
+    assert!(srtt.is_some());
+
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let sent = table.send(&key(), b"hello", start).unwrap();
+    let resent = table.retransmit(at(1000));
+    assert_eq!(resent, sent);
+    assert_eq!(rtt(&table).rto(), Duration::from_secs(2));
+
+    // 再送したデータへの確認応答からは測らず、倍にした RTO を使い続ける
+    let ack = header(seq, iss.wrapping_add(6), TCP_ACK);
+    assert!(
+        table
+            .receive(client(), server(), &ack, &[], at(1500))
+            .is_empty()
+    );
+    assert_eq!(rtt(&table).srtt(), srtt);
+    assert_eq!(rtt(&table).rto(), Duration::from_secs(2));
+    assert_eq!(table.get(&key()).unwrap().retransmission.deadline(), None);
+    assert!(table.retransmit(at(5000)).is_empty());
+
+    table.send(&key(), b"world", at(6000)).unwrap();
+    let ack = header(seq, iss.wrapping_add(11), TCP_ACK);
+    table.receive(client(), server(), &ack, &[], at(6200));
+    assert_ne!(rtt(&table).srtt(), srtt);
+    assert_eq!(rtt(&table).rto(), Duration::from_secs(1));
+}
+
+#[test]
+fn timer_wheel_keeps_deadlines_beyond_one_round() {
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let mut wheel = TimerWheel::new(Duration::from_millis(100), 4, start);
+    wheel.schedule("late", at(1000));
+    wheel.schedule("soon", at(250));
+    wheel.schedule("same tick", at(330));
+    assert_eq!(wheel.expire(at(300)), vec!["soon"]);
+    assert_eq!(wheel.expire(at(350)), vec!["same tick"]);
+    assert!(wheel.expire(at(600)).is_empty());
+    assert_eq!(wheel.expire(at(1000)), vec!["late"]);
+    assert!(wheel.is_empty());
+}
+
+#[test]
+fn probes_zero_window_until_it_opens() {
+    let mut table = ConnectionTable::new().max_retries(1);
+    let iss = establish(&mut table);
+    let seq = CLIENT_ISS.wrapping_add(1);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let mut closed = header(seq, iss.wrapping_add(1), TCP_ACK);
+    closed.window_size = 0;
+    table.receive(client(), server(), &closed, &[], start);
+
+    assert!(table.send(&key(), b"hello", start).unwrap().is_empty());
+    assert_eq!(table.get(&key()).unwrap().deadline(), Some(at(1000)));
+    // 1バイトのプローブを、間隔を倍にしながら送り続ける。再送回数の上限では中断しない
+    for millis in [1000, 3000, 7000] {
+        assert!(table.retransmit(at(millis - 1)).is_empty());
+        let probe = table.retransmit(at(millis));
+        assert_eq!(probe.len(), 1);
+        assert_eq!(probe[0].header.sequence_number, iss.wrapping_add(1));
+        assert_eq!(probe[0].payload, b"h");
+    }
+    assert_eq!(table.state(&key()), TcpState::Established);
+
+    // プローブへの確認応答でウィンドウが開けば残りを送る
+    let opened = header(seq, iss.wrapping_add(2), TCP_ACK);
+    let rest = table.receive(client(), server(), &opened, &[], at(7100));
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].header.sequence_number, iss.wrapping_add(2));
+    assert_eq!(rest[0].payload, b"ello");
+    let tcb = table.get(&key()).unwrap();
+    assert_eq!(tcb.deadline(), tcb.retransmission.deadline());
+}
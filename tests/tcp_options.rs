@@ -0,0 +1,181 @@
+use ferrix::protocols::ip::ip_address::IpAddress;
+use ferrix::protocols::ip::ipv4_address::IPv4Address;
+use ferrix::protocols::tcp::tcp_header::{OptionsTooLong, TcpHeader};
+use ferrix::protocols::tcp::tcp_option::TcpOption;
+use ferrix::types::bit_stream::{BitStream, BitWriter};
+use ferrix::types::byte_object::ByteObject;
+use ferrix::types::parse_error::ParseError;
+
+fn header_with_options(options: &[u8], payload: &[u8]) -> Vec<u8> {
+    let data_offset = 5 + options.len() / 4;
+    let mut bytes = vec![
+        0xC3,
+        0x50,
+        0,
+        80,
+        0,
+        0,
+        0,
+        1,
+        0,
+        0,
+        0,
+        0,
+        (data_offset << 4) as u8,
+        0x02,
+        0xFF,
+        0xFF,
+        0,
+        0,
+        0,
+        0,
+    ];
+    bytes.extend_from_slice(options);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+fn to_bytes(header: &TcpHeader) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    header.write_to(&mut writer);
+    writer.finish()
+}
+
+#[test]
+fn parses_syn_options_and_leaves_payload() {
+    let bytes = header_with_options(
+        &[
+            2, 4, 0x05, 0xB4, // MSS 1460
+            4, 2, // SACK Permitted
+            8, 10, 0, 0, 0, 1, 0, 0, 0, 0, // Timestamps
+            1, // NOP
+            3, 3, 7, // Window Scale
+        ],
+        b"data",
+    );
+    let mut stream = BitStream::from_bytes(bytes.clone());
+    let header = TcpHeader::from_stream(&mut stream).unwrap();
+    assert_eq!(
+        header.options,
+        vec![
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps {
+                value: 1,
+                echo_reply: 0
+            },
+            TcpOption::Nop,
+            TcpOption::WindowScale(7),
+        ]
+    );
+    assert_eq!(header.data_offset(), 10);
+    assert_eq!(header.mss(), Some(1460));
+    assert_eq!(stream.read_remaining_bytes(), b"data");
+    assert_eq!(to_bytes(&header), &bytes[..40]);
+}
+
+#[test]
+fn pads_options_and_derives_data_offset() {
+    let bytes = header_with_options(&[2, 4, 0x02, 0x18, 1, 1, 0, 0, 0, 0, 0, 0], &[]);
+    let header = TcpHeader::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    // End の後ろに続くパディングの語もヘッダー長として保つ
+    assert_eq!(header.header_length(), 32);
+    assert_eq!(to_bytes(&header), bytes);
+
+    let mut header = header;
+    header.set_options(vec![TcpOption::WindowScale(2)]).unwrap();
+    assert_eq!(header.data_offset(), 6);
+    assert_eq!(&to_bytes(&header)[20..], &[3, 3, 2, 0]);
+}
+
+#[test]
+fn keeps_non_zero_padding_after_end() {
+    let bytes = header_with_options(&[2, 4, 0x02, 0x18, 0, 1, 1, 1], &[]);
+    let header = TcpHeader::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    assert_eq!(
+        header.options,
+        [
+            TcpOption::MaximumSegmentSize(536),
+            TcpOption::End,
+            TcpOption::Padding(vec![1, 1, 1])
+        ]
+    );
+    assert_eq!(to_bytes(&header), bytes);
+}
+
+#[test]
+fn set_options_rejects_options_over_40_bytes() {
+    let mut header = TcpHeader::new_with_checksum(
+        50000,
+        80,
+        1,
+        0,
+        0,
+        0x02,
+        65535,
+        0,
+        IPv4Address::new(10, 0, 0, 1),
+        IPv4Address::new(10, 1, 0, 2),
+        &[],
+    );
+    header.set_options(vec![TcpOption::MaximumSegmentSize(1460)]).unwrap();
+
+    // 10バイトの Timestamps を5つで50バイト（パディングを含めて52バイト）
+    let timestamps = TcpOption::Timestamps {
+        value: 1,
+        echo_reply: 0,
+    };
+    assert_eq!(
+        header.set_options(vec![timestamps; 5]),
+        Err(OptionsTooLong { length: 52 })
+    );
+    assert_eq!(header.options, [TcpOption::MaximumSegmentSize(1460)]);
+
+    // ちょうど40バイトは受け入れる
+    header.set_options(vec![TcpOption::Nop; 40]).unwrap();
+    assert_eq!(header.data_offset(), 15);
+}
+
+#[test]
+fn clamps_window_scale_above_14() {
+    let bytes = header_with_options(&[3, 3, 15, 0], &[]);
+    let header = TcpHeader::from_stream(&mut BitStream::from_bytes(bytes.clone())).unwrap();
+    // 受け取った値はそのまま書き戻し、使うときだけ14に丸める
+    assert_eq!(header.options[0], TcpOption::WindowScale(15));
+    assert_eq!(header.window_scale(), Some(14));
+    assert_eq!(to_bytes(&header), bytes);
+}
+
+#[test]
+fn checksum_covers_options() {
+    let source = IPv4Address::new(10, 0, 0, 1);
+    let destination = IPv4Address::new(10, 1, 0, 2);
+    let mut header =
+        TcpHeader::new_with_checksum(50000, 80, 1, 0, 0, 0x02, 65535, 0, source, destination, &[]);
+    let without_options = header.checksum;
+    header.set_options(vec![TcpOption::MaximumSegmentSize(1460)]).unwrap();
+    header.update_checksum(source, destination, &[]);
+    assert_ne!(header.checksum, without_options);
+
+    // 疑似ヘッダーとオプションを含めたセグメント全体の和が0xFFFFになる
+    let segment = to_bytes(&header);
+    let mut acc = IpAddress::pseudo_header(&source.into(), &destination.into(), 6, segment.len());
+    acc.add_bytes(&segment);
+    assert_eq!(acc.finish(), 0);
+}
+
+#[test]
+fn rejects_bad_option_length_and_short_data_offset() {
+    let bytes = header_with_options(&[2, 3, 0, 0], &[]);
+    assert_eq!(
+        TcpHeader::from_stream(&mut BitStream::from_bytes(bytes)),
+        Err(ParseError::BadOptionLength { kind: 2, length: 3 })
+    );
+
+    let mut bytes = header_with_options(&[], &[]);
+    bytes[12] = 4 << 4;
+    assert_eq!(
+        TcpHeader::from_stream(&mut BitStream::from_bytes(bytes)),
+        Err(ParseError::BadHeaderLength(4))
+    );
+}